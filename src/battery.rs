@@ -0,0 +1,46 @@
+use crate::config::BatteryConfig;
+use log::info;
+use std::fs;
+use std::path::Path;
+
+const SYS_CLASS_POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+fn read(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Average charge of the batteries, `None` when on AC or without a battery.
+pub fn discharging_percent() -> Option<u32> {
+    let entries = fs::read_dir(SYS_CLASS_POWER_SUPPLY).ok()?;
+    let mut discharging = false;
+    let mut capacities = Vec::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if read(&dir, "type").as_deref() != Some("Battery") {
+            continue;
+        }
+        if read(&dir, "status").as_deref() == Some("Discharging") {
+            discharging = true;
+        }
+        if let Some(c) = read(&dir, "capacity").and_then(|c| c.parse::<u32>().ok()) {
+            capacities.push(c);
+        }
+    }
+    if !discharging || capacities.is_empty() {
+        return None;
+    }
+    Some(capacities.iter().sum::<u32>() / capacities.len() as u32)
+}
+
+/// Whether the machine runs on a battery below `min_percent`.
+pub fn is_low(config: &BatteryConfig) -> bool {
+    match discharging_percent() {
+        Some(p) if p < config.min_percent => {
+            info!("On battery at {}%", p);
+            true
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,105 @@
+use crate::config::BudgetConfig;
+use crate::history::get_data_filename;
+use crate::netdev;
+use chrono::Local;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const BUDGET_FILE_PATH: &str = "budget.toml";
+const BYTES_PER_MB: u64 = 1_000_000;
+
+/// Data the speed tests used this month.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct State {
+    month: String,
+    bytes: u64,
+    tests: u32,
+}
+
+fn this_month() -> String {
+    Local::now().format("%Y-%m").to_string()
+}
+
+fn load() -> State {
+    let path = match get_data_filename(BUDGET_FILE_PATH) {
+        Ok(p) => p,
+        Err(_) => return State::default(),
+    };
+    let state: State = match fs::read_to_string(&path) {
+        Ok(c) => match toml::from_str(&c) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Failed to parse '{}', starting over: {}", path.display(), e);
+                State::default()
+            }
+        },
+        Err(_) => State::default(),
+    };
+    if state.month == this_month() {
+        state
+    } else {
+        State::default()
+    }
+}
+
+/// Bytes the speed tests used so far this month.
+pub fn used() -> u64 {
+    load().bytes
+}
+
+/// Whether this month's tests used up `monthly_mb`.
+pub fn exceeded(config: &BudgetConfig) -> bool {
+    config.enabled && used() >= config.monthly_mb * BYTES_PER_MB
+}
+
+/// Counters of `interface` before a test, to tell what it used afterwards.
+pub fn start(interface: Option<&str>) -> Option<(String, netdev::Counters)> {
+    let interface = interface?;
+    match netdev::read_counters(interface) {
+        Ok(c) => Some((interface.to_string(), c)),
+        Err(e) => {
+            warn!("Not counting the test's data, {}", e);
+            None
+        }
+    }
+}
+
+/// Adds the traffic on the interface since `start` to this month's total,
+/// returning it. Anything else using the network meanwhile counts too.
+pub fn record(start: (String, netdev::Counters)) -> Result<u64, String> {
+    let (interface, before) = start;
+    add(before, netdev::read_counters(&interface)?)
+}
+
+/// Adds the traffic between two readings of the counters to this month's
+/// total, returning it.
+pub fn add(before: netdev::Counters, after: netdev::Counters) -> Result<u64, String> {
+    let bytes = after.rx_bytes.saturating_sub(before.rx_bytes)
+        + after.tx_bytes.saturating_sub(before.tx_bytes);
+    let mut state = load();
+    state.month = this_month();
+    state.bytes += bytes;
+    state.tests += 1;
+    info!(
+        "The test used {} MB, {} MB over {} tests this month",
+        bytes / BYTES_PER_MB,
+        state.bytes / BYTES_PER_MB,
+        state.tests
+    );
+    let path = get_data_filename(BUDGET_FILE_PATH)?;
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+    }
+    let toml = match toml::to_string(&state) {
+        Ok(t) => t,
+        Err(e) => return Err(format!("Failed to convert to TOML: {}", e)),
+    };
+    match fs::write(&path, toml) {
+        Ok(_) => Ok(bytes),
+        Err(e) => Err(format!("Failed to write '{}': {}", path.display(), e)),
+    }
+}
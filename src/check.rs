@@ -0,0 +1,71 @@
+use crate::config::{CheckConfig, Thresholds};
+use crate::notify::degraded_reasons;
+use crate::Fast;
+
+pub const OK: i32 = 0;
+pub const WARNING: i32 = 1;
+pub const CRITICAL: i32 = 2;
+pub const UNKNOWN: i32 = 3;
+
+fn range(value: Option<u32>, low_is_bad: bool) -> String {
+    match value {
+        // Nagios ranges alert outside of them, "50:" means "below 50"
+        Some(v) if low_is_bad => format!("{}:", v),
+        Some(v) => v.to_string(),
+        None => String::new(),
+    }
+}
+
+fn perfdata(info: &Fast, warning: &Thresholds, critical: &Thresholds) -> String {
+    let mut out = vec![format!(
+        "download={};{};{};0;",
+        info.download_speed,
+        range(warning.min_download, true),
+        range(critical.min_download, true)
+    )];
+    if let Some(up) = info.upload_speed {
+        out.push(format!(
+            "upload={};{};{};0;",
+            up,
+            range(warning.min_upload, true),
+            range(critical.min_upload, true)
+        ));
+    }
+    out.push(format!(
+        "latency={}ms;{};{};0;",
+        info.latency,
+        range(warning.max_latency, false),
+        range(critical.max_latency, false)
+    ));
+    out.join(" ")
+}
+
+/// Returns the plugin output line and its exit code.
+pub fn check(config: &CheckConfig, info: &Fast) -> (String, i32) {
+    let critical = degraded_reasons(&config.critical, info);
+    let warning = degraded_reasons(&config.warning, info);
+    let (status, code, reasons) = if !critical.is_empty() {
+        ("CRITICAL", CRITICAL, critical)
+    } else if !warning.is_empty() {
+        ("WARNING", WARNING, warning)
+    } else {
+        ("OK", OK, Vec::new())
+    };
+    let summary = if reasons.is_empty() {
+        let mut s = format!("{} Mbps down", info.download_speed);
+        if let Some(up) = info.upload_speed {
+            s.push_str(&format!(", {} Mbps up", up));
+        }
+        s.push_str(&format!(", {} ms latency", info.latency));
+        s
+    } else {
+        reasons.join(", ")
+    };
+    let line = format!(
+        "INTERNET {} - {} | {}",
+        status,
+        summary,
+        perfdata(info, &config.warning, &config.critical)
+    );
+    (line, code)
+}
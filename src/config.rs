@@ -0,0 +1,2203 @@
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_FILE_PATH: &str = "polybar-internet-speed/config.toml";
+/// Every setting with its default, commented out
+const TEMPLATE: &str = include_str!("config.toml");
+/// `PBIS_CHECK_WARNING_MIN_DOWNLOAD=40` overrides `check.warning.min_download`
+const ENV_PREFIX: &str = "PBIS_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Fast,
+    Native,
+    /// Ookla's `speedtest` CLI
+    Speedtest,
+    /// Any command configured in the `exec` section
+    Exec,
+    /// `iperf3` against the server in the `iperf3` section
+    Iperf3,
+    /// Made up results from the `mock` section, never touching the network
+    Mock,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Fast => "fast",
+            Backend::Native => "native",
+            Backend::Speedtest => "speedtest",
+            Backend::Exec => "exec",
+            Backend::Iperf3 => "iperf3",
+            Backend::Mock => "mock",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "fast" => Some(Backend::Fast),
+            "native" => Some(Backend::Native),
+            "speedtest" => Some(Backend::Speedtest),
+            "exec" => Some(Backend::Exec),
+            "iperf3" => Some(Backend::Iperf3),
+            "mock" => Some(Backend::Mock),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Polybar,
+    /// The whole measurement as a JSON object
+    Json,
+    /// One compact JSON object per line, for daemon mode
+    Jsonl,
+    /// Write a node_exporter textfile collector file instead of printing
+    PromTextfile,
+    /// The polybar line with lemonbar's `%{F#hex}` and `%{A:cmd:}` markup
+    Lemonbar,
+    /// The polybar line with dzen2's `^fg()` and `^ca()` markup
+    Dzen2,
+    /// The polybar line with xmobar's `<fc=#hex>` and `<action>` markup
+    Xmobar,
+    /// A compact line with tmux `#[fg=#hex]` styles, for `status-right`
+    Tmux,
+    /// The polybar line with Pango `<span>` markup
+    Pango,
+    /// swaybar/i3bar protocol blocks with Pango markup, for daemon mode
+    Swaybar,
+    /// A Waybar custom module's JSON with the detail as its tooltip
+    Waybar,
+    /// An Argos/xbar plugin with the history and actions in its dropdown
+    Argos,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub backend: Backend,
+    /// Output template, e.g. `"{icon} {latency} ms {download} Mbps"`
+    pub format: Option<String>,
+    pub output: OutputFormat,
+    pub prom_textfile: Option<PathBuf>,
+    /// Same as `--ip`
+    pub show_ip: bool,
+    /// Command a left click on the module runs, none without one
+    pub click: Option<String>,
+    pub middle_click: Option<String>,
+    pub right_click: Option<String>,
+    /// Scrolling up
+    pub scroll: Option<String>,
+    /// Abbreviate the units when the line is wider than this
+    pub max_width: Option<usize>,
+    /// How long a measurement is reused before testing again
+    pub cache_ttl_secs: u64,
+    /// Forced refreshes within this long of the last test share its result
+    pub min_test_interval_secs: u64,
+    pub fast: FastConfig,
+    pub native: NativeConfig,
+    pub speedtest: SpeedtestConfig,
+    pub exec: Option<ExecConfig>,
+    pub iperf3: Option<Iperf3Config>,
+    pub mock: MockConfig,
+    pub wifi: WifiConfig,
+    pub netdev: NetdevConfig,
+    pub interfaces: InterfacesConfig,
+    pub snapshot: SnapshotConfig,
+    pub ipinfo: IpInfoConfig,
+    pub vpn: VpnConfig,
+    pub serve: ServeConfig,
+    pub race: Option<RaceConfig>,
+    pub influxdb: Option<InfluxConfig>,
+    pub mqtt: Option<MqttConfig>,
+    pub statsd: Option<StatsdConfig>,
+    pub notify: NotifyConfig,
+    pub webhook: Option<WebhookConfig>,
+    pub check: CheckConfig,
+    pub history: HistoryConfig,
+    pub sinks: SinksConfig,
+    /// Attached to every measurement, with the detected `host` unless set.
+    /// An empty value drops a label
+    pub labels: BTreeMap<String, String>,
+    pub store: StoreConfig,
+    pub graph: GraphConfig,
+    pub plan: PlanConfig,
+    pub colors: ColorsConfig,
+    pub theme: ThemeConfig,
+    pub ping: PingConfig,
+    pub quick: QuickConfig,
+    pub legs: LegsConfig,
+    pub work: WorkConfig,
+    pub dns: DnsConfig,
+    pub health: HealthConfig,
+    pub endpoints: EndpointsConfig,
+    pub route: RouteConfig,
+    pub outage: OutageConfig,
+    pub latency: LatencyConfig,
+    pub dualstack: DualStackConfig,
+    pub icons: IconsConfig,
+    pub hysteresis: HysteresisConfig,
+    pub sampling: SamplingConfig,
+    pub schedule: ScheduleConfig,
+    pub quiet: QuietConfig,
+    pub cycle: CycleConfig,
+    pub progress: ProgressConfig,
+    pub locale: LocaleConfig,
+    pub stale: StaleConfig,
+    pub age: AgeConfig,
+    pub battery: BatteryConfig,
+    pub daemon: DaemonConfig,
+    pub polybar_ipc: PolybarIpcConfig,
+    pub nm: NmConfig,
+    pub modem: ModemConfig,
+    pub usage: UsageConfig,
+    pub budget: BudgetConfig,
+    pub logging: LoggingConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backend: Backend::Fast,
+            format: None,
+            output: OutputFormat::Polybar,
+            prom_textfile: None,
+            show_ip: false,
+            click: None,
+            middle_click: None,
+            right_click: None,
+            scroll: None,
+            max_width: None,
+            cache_ttl_secs: 86400,
+            min_test_interval_secs: 60,
+            fast: FastConfig::default(),
+            native: NativeConfig::default(),
+            speedtest: SpeedtestConfig::default(),
+            exec: None,
+            iperf3: None,
+            mock: MockConfig::default(),
+            wifi: WifiConfig::default(),
+            netdev: NetdevConfig::default(),
+            interfaces: InterfacesConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            ipinfo: IpInfoConfig::default(),
+            vpn: VpnConfig::default(),
+            serve: ServeConfig::default(),
+            race: None,
+            influxdb: None,
+            mqtt: None,
+            statsd: None,
+            notify: NotifyConfig::default(),
+            webhook: None,
+            check: CheckConfig::default(),
+            history: HistoryConfig::default(),
+            sinks: SinksConfig::default(),
+            labels: BTreeMap::new(),
+            store: StoreConfig::default(),
+            graph: GraphConfig::default(),
+            plan: PlanConfig::default(),
+            colors: ColorsConfig::default(),
+            theme: ThemeConfig::default(),
+            ping: PingConfig::default(),
+            quick: QuickConfig::default(),
+            legs: LegsConfig::default(),
+            work: WorkConfig::default(),
+            dns: DnsConfig::default(),
+            health: HealthConfig::default(),
+            endpoints: EndpointsConfig::default(),
+            route: RouteConfig::default(),
+            outage: OutageConfig::default(),
+            latency: LatencyConfig::default(),
+            dualstack: DualStackConfig::default(),
+            icons: IconsConfig::default(),
+            hysteresis: HysteresisConfig::default(),
+            sampling: SamplingConfig::default(),
+            schedule: ScheduleConfig::default(),
+            quiet: QuietConfig::default(),
+            cycle: CycleConfig::default(),
+            progress: ProgressConfig::default(),
+            locale: LocaleConfig::default(),
+            stale: StaleConfig::default(),
+            age: AgeConfig::default(),
+            battery: BatteryConfig::default(),
+            daemon: DaemonConfig::default(),
+            polybar_ipc: PolybarIpcConfig::default(),
+            nm: NmConfig::default(),
+            modem: ModemConfig::default(),
+            usage: UsageConfig::default(),
+            budget: BudgetConfig::default(),
+            logging: LoggingConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RacePick {
+    /// Whichever backend finishes first, for quick bar updates
+    First,
+    /// Wait for every backend and keep the highest download
+    Max,
+}
+
+/// Runs several backends at once instead of `backend`. Every result that
+/// came in is kept under `results` in the history.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RaceConfig {
+    pub backends: Vec<Backend>,
+    #[serde(default = "default_race_pick")]
+    pub pick: RacePick,
+}
+
+fn default_race_pick() -> RacePick {
+    RacePick::Max
+}
+
+/// How the `fast` backend runs fast-cli, which always gets `--json`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FastConfig {
+    pub path: PathBuf,
+    /// Extra arguments, e.g. `["--upload"]`
+    pub args: Vec<String>,
+    /// Extra environment, e.g. `HTTPS_PROXY`
+    pub env: HashMap<String, String>,
+}
+
+impl Default for FastConfig {
+    fn default() -> Self {
+        FastConfig {
+            path: PathBuf::from("fast"),
+            args: Vec::new(),
+            env: HashMap::new(),
+        }
+    }
+}
+
+/// Runs `command` for `backend = "exec"` and reads the results out of its
+/// JSON output with JSON pointers (RFC 6901), e.g. `/download/bandwidth`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecConfig {
+    /// Program and its arguments, no shell is involved
+    pub command: Vec<String>,
+    #[serde(default = "default_exec_timeout_secs")]
+    pub timeout_secs: u64,
+    pub download: String,
+    pub upload: Option<String>,
+    /// Milliseconds
+    pub latency: String,
+    /// Name or id of the server that was tested against
+    pub server: Option<String>,
+    /// Multiplies the speeds into Mbps, e.g. 0.000008 for bytes per second
+    #[serde(default = "default_speed_scale")]
+    pub speed_scale: f64,
+}
+
+fn default_exec_timeout_secs() -> u64 {
+    120
+}
+
+fn default_speed_scale() -> f64 {
+    1.0
+}
+
+/// Measures against an iperf3 server for `backend = "iperf3"`, e.g. a router
+/// or NAS for LAN throughput or an ISP's public iperf host.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Iperf3Config {
+    pub server: String,
+    #[serde(default = "default_iperf3_port")]
+    pub port: u16,
+    /// Length of each direction's test
+    #[serde(default = "default_iperf3_duration_secs")]
+    pub duration_secs: u64,
+    /// Also test upload, doubling the test time
+    #[serde(default = "default_true")]
+    pub upload: bool,
+}
+
+fn default_iperf3_port() -> u16 {
+    5201
+}
+
+fn default_iperf3_duration_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MockPattern {
+    /// The configured values every time
+    Constant,
+    /// Random jitter around the configured values
+    Random,
+    /// A link degrading over `ramp_steps` tests, then starting over
+    Ramp,
+    /// The `fixture` file's lines in order, then starting over
+    Fixture,
+}
+
+/// Results for `backend = "mock"`, to develop and test the output, thresholds
+/// and alerts without the network. Each test advances a step kept in the
+/// cache, so runs are repeatable.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MockConfig {
+    pub pattern: MockPattern,
+    /// Measurements as JSON lines, e.g. a history file. A line with an
+    /// `error` fails that test
+    pub fixture: Option<PathBuf>,
+    pub download: u32,
+    pub upload: Option<u32>,
+    pub latency: u32,
+    pub jitter_percent: f64,
+    pub ramp_steps: u32,
+    /// Every nth test fails, 0 never
+    pub fail_every: u32,
+    /// How long a test takes
+    pub delay_ms: u64,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            pattern: MockPattern::Random,
+            fixture: None,
+            download: 100,
+            upload: Some(20),
+            latency: 20,
+            jitter_percent: 20.0,
+            ramp_steps: 10,
+            fail_every: 0,
+            delay_ms: 0,
+        }
+    }
+}
+
+/// Server choice for Ookla's CLI, whose results vary a lot between servers.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SpeedtestConfig {
+    /// Server ids tried in order, see `speedtest --servers`
+    pub servers: Vec<u64>,
+    /// Fail instead of letting the CLI pick once every server failed
+    pub pin: bool,
+}
+
+/// Settings for the built-in HTTP backend. The defaults point at Cloudflare's
+/// speed test endpoints, but any server accepting a GET for download and a
+/// POST for upload works (e.g. a self-hosted librespeed).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NativeConfig {
+    pub latency_url: String,
+    pub download_url: String,
+    pub upload: bool,
+    pub upload_url: String,
+    pub upload_bytes: usize,
+    pub timeout_secs: u64,
+}
+
+impl Default for NativeConfig {
+    fn default() -> Self {
+        NativeConfig {
+            latency_url: "https://speed.cloudflare.com/__down?bytes=0".to_string(),
+            download_url: "https://speed.cloudflare.com/__down?bytes=25000000".to_string(),
+            upload: true,
+            upload_url: "https://speed.cloudflare.com/__up".to_string(),
+            upload_bytes: 10_000_000,
+            timeout_secs: 60,
+        }
+    }
+}
+
+/// Enables the `{ssid}` and `{signal}` placeholders.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WifiConfig {
+    pub enabled: bool,
+    /// Defaults to the first interface in `/proc/net/wireless`
+    pub interface: Option<String>,
+}
+
+/// Interface sampled for the `{rx}` and `{tx}` placeholders in daemon mode,
+/// and whose link speed `{link}` shows.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NetdevConfig {
+    /// Defaults to the interface holding the default route
+    pub interface: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterfaceShow {
+    /// Test every interface in `names`, summarizing them all
+    All,
+    /// Only the one holding the default route
+    Active,
+}
+
+/// Tests bound to a network interface, and per interface results for a
+/// failover setup as `{interfaces}`. A change of the default route's
+/// interface makes the buffered result out of date.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InterfacesConfig {
+    /// Binds every test to this interface, Linux only
+    pub bind: Option<String>,
+    pub names: Vec<String>,
+    pub show: InterfaceShow,
+    /// Each interface's part of `{interfaces}`, with `{name}`, `{active}`,
+    /// `{download}`, `{upload}` and `{latency}`
+    pub format: String,
+    /// For interfaces that are down or whose test failed
+    pub missing_format: String,
+    pub active_marker: String,
+    pub separator: String,
+}
+
+impl Default for InterfacesConfig {
+    fn default() -> Self {
+        InterfacesConfig {
+            bind: None,
+            names: Vec::new(),
+            show: InterfaceShow::All,
+            format: "{name}{active} {download}M {latency}ms".to_string(),
+            missing_format: "{name}{active} -".to_string(),
+            active_marker: "*".to_string(),
+            separator: " ".to_string(),
+        }
+    }
+}
+
+/// Records load, Wi-Fi signal, background traffic and CPU hogs alongside
+/// each new measurement, sampling them for a second before the test.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+    /// Processes above this CPU usage are listed in the snapshot
+    pub heavy_process_cpu_percent: f32,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            enabled: false,
+            heavy_process_cpu_percent: 25.0,
+        }
+    }
+}
+
+/// Lookup used for `{ip}`, `{isp}` and `{city}` when the backend doesn't
+/// report them. Understands ipinfo.io and ip-api.com style responses.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IpInfoConfig {
+    pub url: String,
+    pub ttl_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for IpInfoConfig {
+    fn default() -> Self {
+        IpInfoConfig {
+            url: "https://ipinfo.io/json".to_string(),
+            ttl_secs: 3600,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// Swaps the icon for the `vpn` one while traffic goes through a VPN.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VpnConfig {
+    pub enabled: bool,
+    /// Interface name prefixes considered VPNs
+    pub interfaces: Vec<String>,
+    /// When set, a public ip owned by a different ISP also counts as VPN
+    pub expected_isp: Option<String>,
+}
+
+impl Default for VpnConfig {
+    fn default() -> Self {
+        VpnConfig {
+            enabled: true,
+            interfaces: ["tun", "tap", "wg", "ppp", "ipsec", "nordlynx"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            expected_isp: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    pub listen: String,
+    /// When set, the API endpoints require `Authorization: Bearer <token>`
+    pub token: Option<String>,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        ServeConfig {
+            listen: "127.0.0.1:9797".to_string(),
+            token: None,
+        }
+    }
+}
+
+/// Each new measurement is pushed through the InfluxDB v2 write API when this
+/// section is present.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InfluxConfig {
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: String,
+    #[serde(default = "default_influx_measurement")]
+    pub measurement: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_influx_measurement() -> String {
+    "internet_speed".to_string()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// Publishes each new measurement to `<topic_prefix>/<metric>` and, with
+/// `discovery`, announces the sensors to Home Assistant.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Takes `labels` as placeholders, e.g. `internet/{host}`
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    #[serde(default = "default_true")]
+    pub retain: bool,
+    #[serde(default = "default_true")]
+    pub discovery: bool,
+    #[serde(default = "default_mqtt_discovery_prefix")]
+    pub discovery_prefix: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "polybar-internet-speed".to_string()
+}
+
+fn default_mqtt_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsdProtocol {
+    /// Gauges in a UDP datagram
+    Statsd,
+    /// Plaintext lines over TCP
+    Graphite,
+}
+
+/// Sends each new measurement as `<prefix>.<metric>` gauges to a StatsD or
+/// Graphite server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatsdConfig {
+    pub host: String,
+    /// 8125 for StatsD, 2003 for Graphite
+    pub port: Option<u16>,
+    /// Takes `labels` as placeholders, e.g. `internet.{host}`
+    #[serde(default = "default_statsd_prefix")]
+    pub prefix: String,
+    #[serde(default = "default_statsd_protocol")]
+    pub protocol: StatsdProtocol,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl StatsdConfig {
+    pub fn port(&self) -> u16 {
+        match (self.port, self.protocol) {
+            (Some(p), _) => p,
+            (None, StatsdProtocol::Statsd) => 8125,
+            (None, StatsdProtocol::Graphite) => 2003,
+        }
+    }
+}
+
+fn default_statsd_prefix() -> String {
+    "internet_speed".to_string()
+}
+
+fn default_statsd_protocol() -> StatsdProtocol {
+    StatsdProtocol::Statsd
+}
+
+/// Floors a new measurement is checked against before alerting.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    pub min_download: Option<u32>,
+    pub min_upload: Option<u32>,
+    pub max_latency: Option<u32>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            min_download: Some(50),
+            min_upload: None,
+            max_latency: Some(150),
+        }
+    }
+}
+
+/// Desktop notification sent when a new measurement crosses any floor.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub thresholds: Thresholds,
+    pub cooldown_secs: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            enabled: false,
+            thresholds: Thresholds::default(),
+            cooldown_secs: 3600,
+        }
+    }
+}
+
+/// Thresholds for the `check` subcommand.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CheckConfig {
+    pub warning: Thresholds,
+    pub critical: Thresholds,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        CheckConfig {
+            warning: Thresholds::default(),
+            critical: Thresholds {
+                min_download: Some(10),
+                min_upload: None,
+                max_latency: Some(500),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookPreset {
+    Generic,
+    Slack,
+    Discord,
+    Telegram,
+}
+
+/// JSON POSTed when a measurement crosses the thresholds or the speed test
+/// keeps failing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "default_webhook_preset")]
+    pub preset: WebhookPreset,
+    /// Overrides the preset body. Supports `{message}`, `{event}`,
+    /// `{download}`, `{upload}`, `{latency}` and `{chat_id}`
+    pub body: Option<String>,
+    /// Required by the telegram preset
+    pub chat_id: Option<String>,
+    #[serde(default, flatten)]
+    pub thresholds: Thresholds,
+    /// Consecutive failed tests before alerting
+    #[serde(default = "default_webhook_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_webhook_cooldown_secs")]
+    pub cooldown_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_webhook_preset() -> WebhookPreset {
+    WebhookPreset::Generic
+}
+
+fn default_webhook_max_failures() -> u32 {
+    3
+}
+
+fn default_webhook_cooldown_secs() -> u64 {
+    3600
+}
+
+/// Every new measurement is appended to the history file, which defaults to
+/// `$XDG_DATA_HOME/polybar-internet-speed/history.jsonl`, or the platform's
+/// data directory without it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    pub path: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+/// The sinks without a section of their own. The others are enabled by
+/// theirs, `history.enabled`, `notify.enabled` or `[mqtt]` being set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SinksConfig {
+    /// Print the module line
+    pub bar: bool,
+    /// Keep the latest measurement for the next runs to show
+    pub buffer: bool,
+}
+
+impl Default for SinksConfig {
+    fn default() -> Self {
+        SinksConfig {
+            bar: true,
+            buffer: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    /// The TOML buffer in the cache directory and the `history` file
+    #[default]
+    File,
+    Sqlite,
+    /// Nothing on disk, measurements last as long as the process
+    #[serde(alias = "none")]
+    Memory,
+}
+
+/// Where the latest measurement and the history are kept.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub backend: StoreBackend,
+    /// The SQLite database, `$XDG_DATA_HOME/polybar-internet-speed/store.sqlite`
+    /// by default
+    pub path: Option<PathBuf>,
+}
+
+/// Chart of the history drawn by the `graph` command, a PNG or SVG by the
+/// extension of `path`, `$XDG_DATA_HOME/polybar-internet-speed/graph.svg`
+/// by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GraphConfig {
+    pub path: Option<PathBuf>,
+    pub days: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            path: None,
+            days: 7,
+            width: 800,
+            height: 500,
+        }
+    }
+}
+
+/// Advertised speeds of the ISP plan, in Mbps, enabling `{down_pct}` and
+/// `{up_pct}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PlanConfig {
+    pub download: Option<u32>,
+    pub upload: Option<u32>,
+    /// Also color the icon by percentage of plan, keeping the worst level
+    pub color: bool,
+    pub good_percent: u32,
+    pub warn_percent: u32,
+}
+
+impl Default for PlanConfig {
+    fn default() -> Self {
+        PlanConfig {
+            download: None,
+            upload: None,
+            color: false,
+            good_percent: 80,
+            warn_percent: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorPolicy {
+    /// The worst level of every metric with thresholds
+    Worst,
+    Latency,
+    Download,
+}
+
+/// Thresholds picking the icon color: past `warn` it turns yellow, past
+/// `bad` red. Speeds in Mbps, latency in ms.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColorsConfig {
+    pub policy: ColorPolicy,
+    pub latency_warn_above: u32,
+    pub latency_bad_above: u32,
+    pub download_warn_below: Option<u32>,
+    pub download_bad_below: Option<u32>,
+    pub upload_warn_below: Option<u32>,
+    pub upload_bad_below: Option<u32>,
+    /// Derive the speed thresholds from this network's history instead
+    pub adaptive: bool,
+    /// Speeds below this percentile of the history warn
+    pub adaptive_warn_percentile: f64,
+    pub adaptive_bad_percentile: f64,
+    /// Measurements on the network needed before adapting
+    pub adaptive_min_samples: usize,
+}
+
+impl Default for ColorsConfig {
+    fn default() -> Self {
+        ColorsConfig {
+            policy: ColorPolicy::Worst,
+            latency_warn_above: 50,
+            latency_bad_above: 150,
+            download_warn_below: None,
+            download_bad_below: None,
+            upload_warn_below: None,
+            upload_bad_below: None,
+            adaptive: false,
+            adaptive_warn_percentile: 25.0,
+            adaptive_bad_percentile: 10.0,
+            adaptive_min_samples: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    Default,
+    /// Blue, yellow and vermillion, told apart with any color vision
+    ColorblindSafe,
+    /// Shades of gray
+    Monochrome,
+    Gruvbox,
+    Nord,
+}
+
+/// Colors of the good, warn and bad levels in every output, a palette with
+/// any of them overridden.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub palette: Palette,
+    pub good: Option<String>,
+    pub warn: Option<String>,
+    pub bad: Option<String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            palette: Palette::Default,
+            good: None,
+            warn: None,
+            bad: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    Worst,
+    Average,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PingTarget {
+    /// Exposed as the `{ping_<name>}` placeholder
+    pub name: String,
+    /// `host[:port]`, port defaults to 443. `gateway` is the default route's
+    /// gateway
+    pub address: String,
+}
+
+/// Probes the targets every run, replacing the backend's `{latency}` with
+/// their worst or average latency.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PingConfig {
+    pub enabled: bool,
+    pub targets: Vec<PingTarget>,
+    pub aggregate: Aggregate,
+    pub count: u32,
+    pub timeout_ms: u64,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        PingConfig {
+            enabled: false,
+            targets: vec![
+                PingTarget {
+                    name: "gateway".to_string(),
+                    address: "gateway:53".to_string(),
+                },
+                PingTarget {
+                    name: "cloudflare".to_string(),
+                    address: "1.1.1.1:443".to_string(),
+                },
+            ],
+            aggregate: Aggregate::Worst,
+            count: 1,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Probes `--quick` runs instead of a speed test, merged with the last full
+/// measurement.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct QuickConfig {
+    /// `host[:port]` as in `[ping]`
+    pub targets: Vec<String>,
+    /// Probes per target, the spread between them gives `{jitter}`
+    pub count: u32,
+    pub timeout_ms: u64,
+    pub aggregate: Aggregate,
+}
+
+impl Default for QuickConfig {
+    fn default() -> Self {
+        QuickConfig {
+            targets: vec!["1.1.1.1:443".to_string(), "8.8.8.8:443".to_string()],
+            count: 3,
+            timeout_ms: 400,
+            aggregate: Aggregate::Average,
+        }
+    }
+}
+
+/// Separate gateway and internet latency, exposed as `{gateway_latency}`,
+/// `{internet_latency}` and `{legs}` (e.g. `2ms/24ms`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LegsConfig {
+    pub enabled: bool,
+    pub gateway: String,
+    pub internet: String,
+    /// Above this the icon takes `gateway_color`
+    pub gateway_max_latency: u32,
+    pub gateway_color: String,
+    pub count: u32,
+    pub timeout_ms: u64,
+}
+
+impl Default for LegsConfig {
+    fn default() -> Self {
+        LegsConfig {
+            enabled: false,
+            gateway: "gateway:53".to_string(),
+            internet: "1.1.1.1:443".to_string(),
+            gateway_max_latency: 20,
+            gateway_color: "#b16286".to_string(),
+            count: 1,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Latency to the one host that matters during the workday, such as a VPN
+/// gateway, as `{work}` with its own icon and thresholds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorkConfig {
+    pub enabled: bool,
+    pub target: String,
+    /// Placeholders `{icon}`, colored by the level, and `{latency}`
+    pub format: String,
+    pub icon: String,
+    pub warn_above: u32,
+    pub bad_above: u32,
+    pub count: u32,
+    pub timeout_ms: u64,
+}
+
+impl Default for WorkConfig {
+    fn default() -> Self {
+        WorkConfig {
+            enabled: false,
+            target: "vpn.example.com:443".to_string(),
+            format: "{icon} {latency} ms".to_string(),
+            icon: "\u{f0b1}".to_string(),
+            warn_above: 80,
+            bad_above: 200,
+            count: 1,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Times the resolution of `name` for the `{dns}` placeholder. Queries the
+/// system resolver and any `servers` besides.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    pub enabled: bool,
+    pub name: String,
+    pub servers: Vec<String>,
+    /// Slower resolution turns the icon yellow, failure turns it red
+    pub max_latency: u32,
+    pub timeout_ms: u64,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        DnsConfig {
+            enabled: false,
+            name: "example.com".to_string(),
+            servers: Vec::new(),
+            max_latency: 100,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeKind {
+    /// TCP handshake with `target`
+    Ping,
+    /// Resolves `name` through the `target` server, the system resolver when
+    /// empty
+    Dns,
+    /// GET of the `target` URL
+    Http,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthProbe {
+    pub kind: ProbeKind,
+    pub target: String,
+    #[serde(default = "default_health_name")]
+    pub name: String,
+    #[serde(default = "default_health_weight")]
+    pub weight: u32,
+    /// Scores 100 up to `good_ms`, falling to 0 at `bad_ms`. The defaults
+    /// depend on the kind
+    pub good_ms: Option<u32>,
+    pub bad_ms: Option<u32>,
+}
+
+fn default_health_name() -> String {
+    "example.com".to_string()
+}
+
+fn default_health_weight() -> u32 {
+    1
+}
+
+/// Quick probes run at once every render, combined into the weighted 0-100
+/// `{health}` score that colors the icon between speed tests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub probes: Vec<HealthProbe>,
+    pub warn_below: u32,
+    pub bad_below: u32,
+    pub timeout_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        let probe = |kind, target: &str, weight| HealthProbe {
+            kind,
+            target: target.to_string(),
+            name: default_health_name(),
+            weight,
+            good_ms: None,
+            bad_ms: None,
+        };
+        HealthConfig {
+            enabled: false,
+            probes: vec![
+                probe(ProbeKind::Ping, "1.1.1.1:443", 2),
+                probe(ProbeKind::Dns, "", 1),
+                probe(ProbeKind::Http, "https://www.gstatic.com/generate_204", 2),
+            ],
+            warn_below: 70,
+            bad_below: 40,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Endpoint {
+    pub name: String,
+    pub url: String,
+    /// Status codes that count as up, any 2xx or 3xx when empty
+    #[serde(default)]
+    pub expect_status: Vec<u16>,
+    /// Accept any certificate, e.g. a router's self-signed one
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+/// Self-hosted services checked every render: `{endpoints}` counts those up,
+/// e.g. `3/3`, and `{endpoints_down}` names the others. Any of them down
+/// turns the icon red.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EndpointsConfig {
+    pub enabled: bool,
+    pub endpoints: Vec<Endpoint>,
+    pub timeout_ms: u64,
+}
+
+impl Default for EndpointsConfig {
+    fn default() -> Self {
+        EndpointsConfig {
+            enabled: false,
+            endpoints: Vec::new(),
+            timeout_ms: 3000,
+        }
+    }
+}
+
+/// When a measurement fails and `target` doesn't answer either, a bounded
+/// traceroute tells whether the route dies on the LAN, at the ISP or beyond.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RouteConfig {
+    pub enabled: bool,
+    pub target: String,
+    /// Port of the reachability check before tracing
+    pub port: u16,
+    pub traceroute: PathBuf,
+    pub max_hops: u32,
+    /// Per hop
+    pub timeout_secs: u64,
+    /// Hops up to here, counting the gateway, belong to the ISP
+    pub isp_hops: usize,
+}
+
+impl Default for RouteConfig {
+    fn default() -> Self {
+        RouteConfig {
+            enabled: false,
+            target: "1.1.1.1".to_string(),
+            port: 443,
+            traceroute: PathBuf::from("traceroute"),
+            max_hops: 8,
+            timeout_secs: 1,
+            isp_hops: 3,
+        }
+    }
+}
+
+/// In daemon mode, probes `target` every `probe_secs` and records each
+/// outage for the `outages` command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutageConfig {
+    pub enabled: bool,
+    pub target: String,
+    pub probe_secs: u64,
+    pub timeout_ms: u64,
+    /// Shorter outages aren't recorded
+    pub min_secs: u64,
+}
+
+impl Default for OutageConfig {
+    fn default() -> Self {
+        OutageConfig {
+            enabled: false,
+            target: "1.1.1.1:443".to_string(),
+            probe_secs: 30,
+            timeout_ms: 2000,
+            min_secs: 0,
+        }
+    }
+}
+
+/// In daemon mode, times a TCP handshake to `target` every `sample_secs`
+/// into a histogram for `{p50}`, `{p95}`, `{p99}` and `latency-stats`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LatencyConfig {
+    pub enabled: bool,
+    pub target: String,
+    pub sample_secs: u64,
+    pub timeout_ms: u64,
+    /// Percentiles cover the last one to two windows
+    pub window_mins: u64,
+    pub save_secs: u64,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            enabled: false,
+            target: "1.1.1.1:443".to_string(),
+            sample_secs: 5,
+            timeout_ms: 1000,
+            window_mins: 60,
+            save_secs: 60,
+        }
+    }
+}
+
+/// Per address family reachability, exposed as `{ipv4}`, `{ipv6}`,
+/// `{families}` (e.g. `v4✔ v6✘`), `{ipv4_latency}` and `{ipv6_latency}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DualStackConfig {
+    pub enabled: bool,
+    pub ipv4: String,
+    pub ipv6: String,
+    pub timeout_ms: u64,
+}
+
+impl Default for DualStackConfig {
+    fn default() -> Self {
+        DualStackConfig {
+            enabled: false,
+            ipv4: "1.1.1.1:443".to_string(),
+            ipv6: "[2606:4700:4700::1111]:443".to_string(),
+            timeout_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconTheme {
+    /// Needs a patched font, as the original output did
+    #[default]
+    Nerdfont,
+    Emoji,
+    Ascii,
+    None,
+}
+
+/// Icon theme, any icon set here overrides the theme's.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IconsConfig {
+    pub theme: IconTheme,
+    pub good: Option<String>,
+    pub warn: Option<String>,
+    pub bad: Option<String>,
+    pub down: Option<String>,
+    pub up: Option<String>,
+    pub vpn: Option<String>,
+    pub missing: Option<String>,
+}
+
+/// Keeps the icon from flapping when latency hovers around a threshold.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HysteresisConfig {
+    pub enabled: bool,
+    /// Milliseconds past a threshold needed to leave the current level
+    pub margin: u32,
+    /// Consecutive samples at a new level before switching to it
+    pub samples: u32,
+}
+
+impl Default for HysteresisConfig {
+    fn default() -> Self {
+        HysteresisConfig {
+            enabled: false,
+            margin: 10,
+            samples: 2,
+        }
+    }
+}
+
+/// Runs the speed test `count` times, dropping outliers and caching the
+/// median. The spread is exposed as `{download_spread}`, `{upload_spread}`
+/// and `{latency_spread}` (e.g. `±12`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SamplingConfig {
+    pub count: u32,
+    /// Samples further than this many median absolute deviations from the
+    /// median are dropped
+    pub outlier_mads: f64,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        SamplingConfig {
+            count: 1,
+            outlier_mads: 3.0,
+        }
+    }
+}
+
+/// Limits when speed tests may run, as local `HH:MM-HH:MM` windows that wrap
+/// past midnight when the end comes first. Outside the windows, or during a
+/// blackout, the buffered measurement is shown whatever its age and
+/// `{stale}` renders `stale_marker`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// Tests only run inside these, empty means any time
+    pub windows: Vec<String>,
+    pub blackouts: Vec<String>,
+    pub stale_marker: String,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            windows: Vec::new(),
+            blackouts: Vec::new(),
+            stale_marker: "*".to_string(),
+        }
+    }
+}
+
+/// Renders `output` instead of the measurement during the local
+/// `HH:MM-HH:MM` `hours`, or while `toggle` turned it on, e.g. when
+/// presenting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct QuietConfig {
+    pub hours: Vec<String>,
+    /// Empty hides the module, or a minimal mark such as `•`
+    pub output: String,
+}
+
+/// What the daemon shows while a test is running instead of the previous
+/// measurement.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProgressConfig {
+    pub enabled: bool,
+    /// Placeholders `{spinner}`, `{testing}` and `{elapsed}` in seconds
+    pub format: String,
+    /// Glyphs shown one after another, a frame every `frame_ms`
+    pub spinner: Vec<String>,
+    pub frame_ms: u64,
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        ProgressConfig {
+            enabled: true,
+            format: "{spinner} {testing}… {elapsed}s".to_string(),
+            spinner: ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]
+                .map(String::from)
+                .to_vec(),
+            frame_ms: 1000,
+        }
+    }
+}
+
+/// How numbers, units and status words are written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LocaleConfig {
+    /// Taken from `$LC_NUMERIC` or `$LANG` when unset
+    pub decimal_separator: Option<String>,
+    /// `{unit}`, e.g. "Mbit/s"
+    pub speed_unit: String,
+    /// Translations of the status words "offline", "metered" and "testing"
+    pub strings: BTreeMap<String, String>,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> Self {
+        LocaleConfig {
+            decimal_separator: None,
+            speed_unit: "Mbps".to_string(),
+            strings: BTreeMap::new(),
+        }
+    }
+}
+
+/// Display modes `rusting cycle` steps through, each a format shown before
+/// going back to `format`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CycleConfig {
+    pub formats: Vec<String>,
+}
+
+impl Default for CycleConfig {
+    fn default() -> Self {
+        CycleConfig {
+            formats: vec![
+                "{icon} {latency} ms{stale}".to_string(),
+                "{icon} {latency} ms {down_icon} {download} {unit}{stale}".to_string(),
+            ],
+        }
+    }
+}
+
+/// When a new measurement fails, the out of date one is shown instead for up
+/// to `max_age_secs`, marked like any stale measurement.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StaleConfig {
+    pub max_age_secs: u64,
+    /// Append the age, e.g. `* (23h)`, to `{stale}`
+    pub show_age: bool,
+    /// Icon color while stale, e.g. a dimmed gray
+    pub color: Option<String>,
+}
+
+impl Default for StaleConfig {
+    fn default() -> Self {
+        StaleConfig {
+            max_age_secs: 7 * 86400,
+            show_age: true,
+            color: None,
+        }
+    }
+}
+
+/// Colors the whole module once the measurement is older than `max_secs`,
+/// telling a fresh result from one taken this morning.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AgeConfig {
+    pub max_secs: Option<u64>,
+    pub color: String,
+}
+
+impl Default for AgeConfig {
+    fn default() -> Self {
+        AgeConfig {
+            max_secs: None,
+            color: "#928374".to_string(),
+        }
+    }
+}
+
+/// On battery below `min_percent`, no new speed test runs and the buffered
+/// measurement is kept for `ttl_secs` instead of a day, then shown as stale.
+/// `--force` measures anyway.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    pub enabled: bool,
+    pub min_percent: u32,
+    pub ttl_secs: u64,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        BatteryConfig {
+            enabled: true,
+            min_percent: 30,
+            ttl_secs: 3 * 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Seconds between lines, `--interval` overrides it
+    pub interval_secs: u64,
+    /// Measure again once the default route changes interface or gateway
+    pub retest_on_network_change: bool,
+    /// How long the new network gets to settle before the test
+    pub network_settle_secs: u64,
+    /// After a resume from suspend, hold tests back until the network
+    /// settled and has a default route, then refresh a stale measurement
+    pub revalidate_on_resume: bool,
+    /// How far the wall clock may jump ahead of the monotonic one between
+    /// lines before it counts as a suspend
+    pub resume_jump_secs: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            interval_secs: 1,
+            retest_on_network_change: true,
+            network_settle_secs: 5,
+            revalidate_on_resume: true,
+            resume_jump_secs: 30,
+        }
+    }
+}
+
+/// Runs `polybar-msg action "#<module>.hook.<hook>"` after each new
+/// measurement in daemon mode, for a bar that doesn't tail its output.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PolybarIpcConfig {
+    pub enabled: bool,
+    pub polybar_msg: PathBuf,
+    /// Name of the polybar module, without the `module/` prefix
+    pub module: String,
+    /// Index of the hook in the module's `hook-N` list
+    pub hook: u32,
+}
+
+impl Default for PolybarIpcConfig {
+    fn default() -> Self {
+        PolybarIpcConfig {
+            enabled: false,
+            polybar_msg: PathBuf::from("polybar-msg"),
+            module: "internet-speed".to_string(),
+            hook: 0,
+        }
+    }
+}
+
+/// Asks NetworkManager about the active connection, for the `{connection}`,
+/// `{connection_type}` and `{metered}` placeholders. The buffered measurement
+/// is only reused on the connection it was taken on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NmConfig {
+    pub enabled: bool,
+    /// Don't run speed tests on metered connections
+    pub skip_metered: bool,
+    pub metered_marker: String,
+}
+
+impl Default for NmConfig {
+    fn default() -> Self {
+        NmConfig {
+            enabled: false,
+            skip_metered: true,
+            metered_marker: "metered".to_string(),
+        }
+    }
+}
+
+/// An LTE or 5G modem through ModemManager, for the `{modem_signal}`,
+/// `{modem_tech}` and `{operator}` placeholders.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModemConfig {
+    pub enabled: bool,
+    /// Count NetworkManager's mobile broadband connections as metered, even
+    /// when it doesn't guess so
+    pub metered: bool,
+}
+
+impl Default for ModemConfig {
+    fn default() -> Self {
+        ModemConfig {
+            enabled: false,
+            metered: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageSource {
+    /// Interface counters sampled on every run, kept in the data directory
+    Native,
+    /// `vnstat --json`, which also provides `{usage_today}`
+    Vnstat,
+}
+
+/// This month's traffic for the `{usage}`, `{usage_rx}`, `{usage_tx}` and
+/// `{usage_pct}` placeholders. Close to `cap_gb` `{usage}` turns yellow,
+/// then red once it's used up.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    pub enabled: bool,
+    pub source: UsageSource,
+    /// Defaults to the interface holding the default route
+    pub interfaces: Vec<String>,
+    pub cap_gb: Option<f64>,
+    pub warn_percent: u32,
+    /// Send a desktop notification when crossing `warn_percent` and the cap
+    pub notify: bool,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            enabled: false,
+            source: UsageSource::Native,
+            interfaces: Vec::new(),
+            cap_gb: None,
+            warn_percent: 80,
+            notify: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetAction {
+    /// Hold full tests back, like on a metered connection
+    Skip,
+    /// Only probe the latency, as `--quick` does
+    Quick,
+}
+
+/// Caps the data speed tests use per month, counted on the test's interface
+/// and shown as `{test_data}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    pub enabled: bool,
+    pub monthly_mb: u64,
+    /// What happens once it's used up, `--force` still tests
+    pub when_exceeded: BudgetAction,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            enabled: false,
+            monthly_mb: 2000,
+            when_exceeded: BudgetAction::Skip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogKind {
+    Stdout,
+    Stderr,
+    /// systemd-journald's native protocol
+    Journald,
+    /// The local syslog daemon through `/dev/log`
+    Syslog,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogTarget {
+    pub kind: LogKind,
+    #[serde(default = "default_log_level")]
+    pub level: LogLevel,
+    /// For `file`, defaults to `$XDG_STATE_HOME/polybar-internet-speed/rusting.log`
+    pub path: Option<PathBuf>,
+    /// For `file`, rotated past this size
+    #[serde(default = "default_log_max_size_kb")]
+    pub max_size_kb: u64,
+    /// For `file`, rotated files kept as `rusting.log.1` and up, 0 deletes them
+    #[serde(default = "default_log_keep")]
+    pub keep: u32,
+}
+
+impl Default for LogTarget {
+    fn default() -> Self {
+        LogTarget {
+            kind: LogKind::File,
+            level: default_log_level(),
+            path: None,
+            max_size_kb: default_log_max_size_kb(),
+            keep: default_log_keep(),
+        }
+    }
+}
+
+fn default_log_level() -> LogLevel {
+    LogLevel::Info
+}
+
+fn default_log_max_size_kb() -> u64 {
+    1024
+}
+
+fn default_log_keep() -> u32 {
+    3
+}
+
+/// Where the log goes, each target with its own level.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub targets: Vec<LogTarget>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            targets: vec![LogTarget::default()],
+        }
+    }
+}
+
+pub fn get_config_filename() -> Result<PathBuf, String> {
+    Ok(paths::config_dir()?.join(CONFIG_FILE_PATH))
+}
+
+/// Environment variable overriding the setting at `path`.
+fn env_name(path: &[String]) -> String {
+    format!("{}{}", ENV_PREFIX, path.join("_").to_uppercase())
+}
+
+/// Collects the path of every setting in `schema` along with its example
+/// value. Arrays of tables and free form tables have no single setting.
+fn settings(
+    schema: &toml::Value,
+    path: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, toml::Value)>,
+) {
+    match schema {
+        toml::Value::Table(t) => {
+            for (key, v) in t {
+                path.push(key.clone());
+                settings(v, path, out);
+                path.pop();
+            }
+        }
+        toml::Value::Array(a) if a.first().is_some_and(|v| v.is_table()) => (),
+        v => out.push((path.clone(), v.clone())),
+    }
+}
+
+/// Reads a variable as the type the setting takes. Anything that isn't a
+/// TOML value, e.g. `native`, is taken as a string.
+fn parse_env_value(raw: &str, example: &toml::Value) -> toml::Value {
+    if example.is_str() {
+        return toml::Value::String(raw.to_string());
+    }
+    match toml::from_str::<toml::Value>(&format!("v = {}", raw)) {
+        Ok(toml::Value::Table(mut t)) => t
+            .remove("v")
+            .unwrap_or_else(|| toml::Value::String(raw.to_string())),
+        _ => toml::Value::String(raw.to_string()),
+    }
+}
+
+/// Every `PBIS_*` variable set for a known setting, as the setting's path
+/// and value.
+fn env_overrides() -> Result<Vec<(Vec<String>, toml::Value)>, String> {
+    let mut all = Vec::new();
+    settings(&schema()?, &mut Vec::new(), &mut all);
+    Ok(all
+        .into_iter()
+        .filter_map(|(path, example)| {
+            let raw = env::var(env_name(&path)).ok()?;
+            Some((path, parse_env_value(&raw, &example)))
+        })
+        .collect())
+}
+
+fn set(value: &mut toml::Value, path: &[String], setting: toml::Value) {
+    let (last, sections) = match path.split_last() {
+        Some(p) => p,
+        None => return,
+    };
+    let mut table = value;
+    for section in sections {
+        if !table.is_table() {
+            *table = toml::Value::Table(toml::map::Map::new());
+        }
+        table = match table {
+            toml::Value::Table(t) => t
+                .entry(section.clone())
+                .or_insert_with(|| toml::Value::Table(toml::map::Map::new())),
+            _ => return,
+        };
+    }
+    if let toml::Value::Table(t) = table {
+        t.insert(last.clone(), setting);
+    }
+}
+
+/// Applies the settings of `over` on top of `base`, section by section.
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(b), toml::Value::Table(o)) => {
+            for (key, v) in o {
+                match b.get_mut(&key) {
+                    Some(bv) if bv.is_table() && v.is_table() => merge(bv, v),
+                    _ => {
+                        b.insert(key, v);
+                    }
+                }
+            }
+        }
+        (b, o) => *b = o,
+    }
+}
+
+/// Names of the `[profile.<name>]` sections.
+fn profile_names(value: &toml::Value) -> Vec<String> {
+    match value.get("profile").and_then(|p| p.as_table()) {
+        Some(t) => t.keys().cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Loads the config file, falling back to defaults when it doesn't exist.
+/// The `[profile.<name>]` section given, then `PBIS_*` environment variables,
+/// override its settings.
+pub fn load(profile: Option<&str>) -> Result<Config, String> {
+    let path = get_config_filename()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(_) if !path.exists() => String::new(),
+        Err(e) => {
+            return Err(format!(
+                "Failed to read config file: '{}'. Error: '{}'",
+                path.display(),
+                e
+            ));
+        }
+    };
+    let overrides = env_overrides()?;
+    if profile.is_none() && overrides.is_empty() {
+        return match toml::from_str(&contents) {
+            Ok(c) => Ok(c),
+            Err(e) => Err(format!(
+                "Failed to parse config file: '{}'. Error: '{}'",
+                path.display(),
+                e
+            )),
+        };
+    }
+    let mut value: toml::Value = match toml::from_str(&contents) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(format!(
+                "Failed to parse config file: '{}'. Error: '{}'",
+                path.display(),
+                e
+            ));
+        }
+    };
+    let mut applied = Vec::new();
+    if let Some(name) = profile {
+        let settings = match value.get("profile").and_then(|p| p.get(name)) {
+            Some(s) => s.clone(),
+            None => {
+                return Err(format!(
+                    "No [profile.{}] in '{}', found: {}",
+                    name,
+                    path.display(),
+                    profile_names(&value).join(", ")
+                ));
+            }
+        };
+        merge(&mut value, settings);
+        applied.push(format!("profile '{}'", name));
+    }
+    for (p, setting) in overrides {
+        applied.push(env_name(&p));
+        set(&mut value, &p, setting);
+    }
+    match value.try_into() {
+        Ok(c) => Ok(c),
+        Err(e) => Err(format!(
+            "Invalid config with {} applied: '{}'",
+            applied.join(", "),
+            e
+        )),
+    }
+}
+
+/// Writes the commented default config, refusing to overwrite an existing
+/// file unless `force` is set.
+pub fn init(force: bool) -> Result<PathBuf, String> {
+    let path = get_config_filename()?;
+    if path.exists() && !force {
+        return Err(format!(
+            "'{}' already exists, pass --force to overwrite it",
+            path.display()
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+    }
+    match fs::write(&path, TEMPLATE) {
+        Ok(_) => Ok(path),
+        Err(e) => Err(format!("Failed to write '{}': {}", path.display(), e)),
+    }
+}
+
+/// Every key the template documents. Its settings are commented out as
+/// `#key = value` and `#[section]`, prose as `# text`.
+fn schema() -> Result<toml::Value, String> {
+    let settings: Vec<&str> = TEMPLATE
+        .lines()
+        .map(|l| match l.strip_prefix('#') {
+            Some(s) if s.starts_with(|c: char| c == '[' || c.is_ascii_lowercase()) => s,
+            _ => l,
+        })
+        .collect();
+    let mut schema: toml::Value = match toml::from_str(&settings.join("\n")) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Invalid config template: {}", e)),
+    };
+    // Profiles take the same settings as the file itself
+    if let Some(t) = schema.as_table_mut() {
+        t.remove("profile");
+    }
+    Ok(schema)
+}
+
+/// Collects the keys of `value` missing from `schema`. An empty table in the
+/// schema, such as `fast.env`, takes any key.
+fn unknown_keys(schema: &toml::Value, value: &toml::Value, path: &str, out: &mut Vec<String>) {
+    match (schema, value) {
+        (toml::Value::Table(known), toml::Value::Table(table)) if !known.is_empty() => {
+            for (key, v) in table {
+                let key_path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                match known.get(key) {
+                    Some(s) => unknown_keys(s, v, &key_path, out),
+                    None => out.push(format!("Unknown key '{}'", key_path)),
+                }
+            }
+        }
+        (toml::Value::Array(known), toml::Value::Array(items)) => {
+            // The template's entries show different keys, any of them goes
+            let mut merged = toml::map::Map::new();
+            for k in known.iter().filter_map(toml::Value::as_table) {
+                merged.extend(k.clone());
+            }
+            let s = match (merged.is_empty(), known.first()) {
+                (false, _) => toml::Value::Table(merged),
+                (true, Some(s)) => s.clone(),
+                (true, None) => return,
+            };
+            for (i, v) in items.iter().enumerate() {
+                unknown_keys(&s, v, &format!("{}[{}]", path, i), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_color(s: &str) -> bool {
+    match s.strip_prefix('#') {
+        Some(hex) => [3, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+/// Critical thresholds have to be at least as bad as the warning ones, and
+/// the plan's warning level below its good one.
+pub fn threshold_problems(config: &Config) -> Vec<String> {
+    let warning = &config.check.warning;
+    let critical = &config.check.critical;
+    let mut problems = Vec::new();
+    if let (Some(w), Some(c)) = (warning.min_download, critical.min_download) {
+        if c > w {
+            problems.push(format!(
+                "check.critical.min_download {} is above the warning {}",
+                c, w
+            ));
+        }
+    }
+    if let (Some(w), Some(c)) = (warning.min_upload, critical.min_upload) {
+        if c > w {
+            problems.push(format!(
+                "check.critical.min_upload {} is above the warning {}",
+                c, w
+            ));
+        }
+    }
+    if let (Some(w), Some(c)) = (warning.max_latency, critical.max_latency) {
+        if c < w {
+            problems.push(format!(
+                "check.critical.max_latency {} is below the warning {}",
+                c, w
+            ));
+        }
+    }
+    let colors = &config.colors;
+    if colors.latency_warn_above > colors.latency_bad_above {
+        problems.push(format!(
+            "colors.latency_warn_above {} is above latency_bad_above {}",
+            colors.latency_warn_above, colors.latency_bad_above
+        ));
+    }
+    let speeds = [
+        (
+            "download",
+            colors.download_warn_below,
+            colors.download_bad_below,
+        ),
+        ("upload", colors.upload_warn_below, colors.upload_bad_below),
+    ];
+    for (name, warn, bad) in speeds {
+        if let (Some(w), Some(b)) = (warn, bad) {
+            if b > w {
+                problems.push(format!(
+                    "colors.{}_bad_below {} is above {}_warn_below {}",
+                    name, b, name, w
+                ));
+            }
+        }
+    }
+    if config.health.bad_below > config.health.warn_below {
+        problems.push(format!(
+            "health.bad_below {} is above warn_below {}",
+            config.health.bad_below, config.health.warn_below
+        ));
+    }
+    for (n, probe) in config.health.probes.iter().enumerate() {
+        if let (Some(g), Some(b)) = (probe.good_ms, probe.bad_ms) {
+            if g >= b {
+                problems.push(format!(
+                    "health.probes[{}].good_ms {} isn't below bad_ms {}",
+                    n, g, b
+                ));
+            }
+        }
+    }
+    if config.plan.warn_percent > config.plan.good_percent {
+        problems.push(format!(
+            "plan.warn_percent {} is above good_percent {}",
+            config.plan.warn_percent, config.plan.good_percent
+        ));
+    }
+    problems
+}
+
+/// Checks the config file for unknown keys, bad colors and overlapping
+/// thresholds, returning every problem found. Errors when it doesn't parse.
+pub fn validate() -> Result<Vec<String>, String> {
+    let path = get_config_filename()?;
+    if !path.exists() {
+        return Err(format!(
+            "'{}' doesn't exist, `config init` writes one",
+            path.display()
+        ));
+    }
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+    let mut value: toml::Value = match toml::from_str(&contents) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Failed to parse '{}': {}", path.display(), e)),
+    };
+    let mut problems = Vec::new();
+    let schema = schema()?;
+    let profiles = match value.as_table_mut().and_then(|t| t.remove("profile")) {
+        Some(toml::Value::Table(t)) => t,
+        Some(_) => {
+            problems.push("'profile' isn't a table of profiles".to_string());
+            toml::map::Map::new()
+        }
+        None => toml::map::Map::new(),
+    };
+    unknown_keys(&schema, &value, "", &mut problems);
+    for (name, settings) in &profiles {
+        unknown_keys(
+            &schema,
+            settings,
+            &format!("profile.{}", name),
+            &mut problems,
+        );
+    }
+    let mut known = Vec::new();
+    settings(&schema, &mut Vec::new(), &mut known);
+    let known: Vec<String> = known.iter().map(|(p, _)| env_name(p)).collect();
+    for (name, _) in env::vars() {
+        if name.starts_with(ENV_PREFIX) && !known.contains(&name) {
+            problems.push(format!("Unknown environment variable '{}'", name));
+        }
+    }
+    let mut configs = vec![(String::new(), load(None)?)];
+    for name in profiles.keys() {
+        configs.push((format!("profile {}: ", name), load(Some(name))?));
+    }
+    // A profile only reports what it adds to the problems of the base config
+    let mut base: Vec<String> = Vec::new();
+    for (prefix, config) in &configs {
+        let mut found = threshold_problems(config);
+        let colors = [
+            ("legs.gateway_color", Some(&config.legs.gateway_color)),
+            ("stale.color", config.stale.color.as_ref()),
+            ("age.color", Some(&config.age.color)),
+            ("theme.good", config.theme.good.as_ref()),
+            ("theme.warn", config.theme.warn.as_ref()),
+            ("theme.bad", config.theme.bad.as_ref()),
+        ];
+        for (key, color) in colors {
+            if let Some(c) = color.filter(|c| !is_color(c)) {
+                found.push(format!("{} '{}' isn't a #rrggbb color", key, c));
+            }
+        }
+        found.retain(|p| !base.contains(p));
+        problems.extend(found.iter().map(|p| format!("{}{}", prefix, p)));
+        if prefix.is_empty() {
+            base = found;
+        }
+    }
+    Ok(problems)
+}
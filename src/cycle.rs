@@ -0,0 +1,40 @@
+use crate::config::CycleConfig;
+use crate::get_cache_filename;
+use log::info;
+use std::fs;
+
+// How many times the mode was advanced, wrapped around the formats on read
+const MODE_FILE_PATH: &str = ".polybar-internet-speed-mode";
+
+fn counter() -> u64 {
+    get_cache_filename(MODE_FILE_PATH)
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|c| c.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// The selected mode, `formats.len()` standing for `format`, which is also
+/// where a fresh cache starts.
+fn mode(config: &CycleConfig) -> usize {
+    let modes = config.formats.len() as u64 + 1;
+    ((counter() % modes + modes - 1) % modes) as usize
+}
+
+/// The format the selected mode renders, none for `format`.
+pub fn format(config: &CycleConfig) -> Option<String> {
+    config.formats.get(mode(config)).cloned()
+}
+
+/// Selects the next mode, which the next render shows.
+pub fn advance() -> Result<(), String> {
+    let path = get_cache_filename(MODE_FILE_PATH)?;
+    let next = counter().wrapping_add(1);
+    match fs::write(&path, next.to_string()) {
+        Ok(_) => {
+            info!("Display mode advanced");
+            Ok(())
+        }
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::DnsConfig;
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{self, UdpSocket};
+use tokio::time;
+
+const DNS_PORT: u16 = 53;
+
+/// Builds a recursive A record query for `name`.
+fn build_query(id: u16, name: &str) -> Vec<u8> {
+    let mut q = Vec::with_capacity(512);
+    q.extend_from_slice(&id.to_be_bytes());
+    // Standard query, recursion desired
+    q.extend_from_slice(&[0x01, 0x00]);
+    // One question, no answer/authority/additional records
+    q.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    // QTYPE A, QCLASS IN
+    q.extend_from_slice(&[0, 1, 0, 1]);
+    q
+}
+
+/// Times a query sent straight to `server`, bypassing the system resolver.
+pub async fn query_server(server: &str, name: &str, timeout: Duration) -> Result<Duration, String> {
+    let addr: SocketAddr = match server.parse::<SocketAddr>() {
+        Ok(a) => a,
+        Err(_) => match net::lookup_host((server, DNS_PORT)).await {
+            Ok(mut a) => match a.next() {
+                Some(a) => a,
+                None => return Err(format!("No address for DNS server '{}'", server)),
+            },
+            Err(e) => return Err(format!("Invalid DNS server '{}': {}", server, e)),
+        },
+    };
+    let bind = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = match UdpSocket::bind(bind).await {
+        Ok(s) => s,
+        Err(e) => return Err(format!("Failed to bind UDP socket: {}", e)),
+    };
+    let id = (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos()
+        & 0xffff) as u16;
+    let query = build_query(id, name);
+
+    let start = Instant::now();
+    if let Err(e) = socket.send_to(&query, addr).await {
+        return Err(format!("Failed to send DNS query to '{}': {}", addr, e));
+    }
+    let mut buf = [0u8; 512];
+    loop {
+        let left = timeout.saturating_sub(start.elapsed());
+        let (len, from) = match time::timeout(left, socket.recv_from(&mut buf)).await {
+            Ok(Ok(r)) => r,
+            Ok(Err(e)) => return Err(format!("No DNS answer from '{}': {}", addr, e)),
+            Err(_) => return Err(format!("No DNS answer from '{}' in {:?}", addr, timeout)),
+        };
+        // Ignore stray packets, the answer must echo our id with QR set
+        if from == addr && len >= 12 && buf[0..2] == id.to_be_bytes() && buf[2] & 0x80 != 0 {
+            let rcode = buf[3] & 0x0f;
+            if rcode != 0 {
+                return Err(format!("DNS server '{}' answered rcode {}", addr, rcode));
+            }
+            return Ok(start.elapsed());
+        }
+    }
+}
+
+/// Times a lookup through the system resolver, giving up after `timeout`.
+pub async fn query_system(name: &str, timeout: Duration) -> Result<Duration, String> {
+    let start = Instant::now();
+    match time::timeout(timeout, net::lookup_host((name, 0))).await {
+        Ok(Ok(_)) => Ok(start.elapsed()),
+        Ok(Err(e)) => Err(format!("Failed to resolve '{}': {}", name, e)),
+        Err(_) => Err(format!("Timed out resolving '{}'", name)),
+    }
+}
+
+/// Worst resolution time in milliseconds over the system resolver and the
+/// configured servers.
+pub async fn probe(config: &DnsConfig) -> Result<u32, String> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let mut worst = None;
+    match query_system(&config.name, timeout).await {
+        Ok(d) => worst = Some(d),
+        Err(e) => warn!("{}", e),
+    }
+    for server in &config.servers {
+        match query_server(server, &config.name, timeout).await {
+            Ok(d) => worst = Some(worst.map_or(d, |w: Duration| w.max(d))),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    let worst = match worst {
+        Some(w) => w,
+        None => return Err(format!("Nothing resolved '{}'", config.name)),
+    };
+    let ms = (worst.as_secs_f64() * 1000.0).round() as u32;
+    info!("Resolved '{}' in {} ms", config.name, ms);
+    Ok(ms)
+}
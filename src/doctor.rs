@@ -0,0 +1,129 @@
+use crate::config::{self, Config, StoreBackend};
+use crate::{backend_program, is_installed, paths, ping};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time;
+
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+const NETWORK_TARGET: &str = "fast.com:443";
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, result: Result<String, String>) {
+        match result {
+            Ok(detail) => println!("[ OK ] {}: {}", name, detail),
+            Err(e) => {
+                self.failed += 1;
+                println!("[FAIL] {}: {}", name, e);
+            }
+        }
+    }
+}
+
+/// Runs `program --version`, which every supported backend CLI answers.
+async fn check_runnable(program: &Path, hint: &str) -> Result<String, String> {
+    if !is_installed(program) {
+        return Err(format!("'{}' not found, {}", program.display(), hint));
+    }
+    let run = Command::new(program)
+        .arg("--version")
+        .kill_on_drop(true)
+        .output();
+    match time::timeout(VERSION_TIMEOUT, run).await {
+        Ok(Ok(o)) if o.status.success() => {
+            let version = String::from_utf8_lossy(&o.stdout);
+            Ok(version
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string())
+        }
+        Ok(Ok(o)) => Err(format!("'{}' exited with {}", program.display(), o.status)),
+        Ok(Err(e)) => Err(format!("Failed to execute '{}': {}", program.display(), e)),
+        Err(_) => Err(format!("'{}' didn't answer --version", program.display())),
+    }
+}
+
+fn check_writable(dir: &Path) -> Result<String, String> {
+    if let Err(e) = fs::create_dir_all(dir) {
+        return Err(format!("Failed to create '{}': {}", dir.display(), e));
+    }
+    let probe = dir.join(format!(".rusting-doctor.{}", std::process::id()));
+    match fs::write(&probe, "") {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(dir.display().to_string())
+        }
+        Err(e) => Err(format!("'{}' isn't writable: {}", dir.display(), e)),
+    }
+}
+
+/// Checks the config, backend, directories and network, printing a line per
+/// check. Returns the exit code.
+pub async fn run_doctor(loaded: &Result<Config, String>) -> i32 {
+    let mut report = Report { failed: 0 };
+    let path = config::get_config_filename().map(|p| p.display().to_string());
+    report.check(
+        "Config",
+        match loaded {
+            Ok(_) => path.map(|p| format!("'{}' parses", p)),
+            Err(e) => Err(e.clone()),
+        },
+    );
+    let default_config;
+    let config = match loaded {
+        Ok(c) => c,
+        Err(_) => {
+            default_config = Config::default();
+            &default_config
+        }
+    };
+
+    let backend = format!("Backend {}", config.backend.name());
+    match backend_program(config) {
+        Some((program, hint)) => report.check(&backend, check_runnable(&program, hint).await),
+        None => report.check(&backend, Ok("built in".to_string())),
+    }
+    report.check(
+        "Cache directory",
+        paths::cache_dir().and_then(|d| check_writable(&d)),
+    );
+    if config.history.enabled && config.store.backend == StoreBackend::File {
+        let dir = crate::history::get_history_filename(&config.history).and_then(|p| {
+            p.parent()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| "History path has no directory".to_string())
+        });
+        report.check("History directory", dir.and_then(|d| check_writable(&d)));
+    }
+    let problems = config::threshold_problems(config);
+    report.check(
+        "Thresholds",
+        if problems.is_empty() {
+            Ok("consistent".to_string())
+        } else {
+            Err(problems.join(", "))
+        },
+    );
+    report.check(
+        "Network",
+        ping::probe(NETWORK_TARGET, 1, NETWORK_TIMEOUT)
+            .await
+            .map(|ms| format!("reached {} in {} ms", NETWORK_TARGET, ms)),
+    );
+
+    if report.failed == 0 {
+        println!("All checks passed");
+        0
+    } else {
+        println!("{} check(s) failed", report.failed);
+        1
+    }
+}
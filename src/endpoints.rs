@@ -0,0 +1,70 @@
+use crate::config::{Endpoint, EndpointsConfig};
+use log::{error, info, warn};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub name: String,
+    pub up: bool,
+}
+
+async fn check(endpoint: &Endpoint, timeout: Duration) -> Result<(), String> {
+    let client = match reqwest::Client::builder()
+        .timeout(timeout)
+        .danger_accept_invalid_certs(endpoint.insecure)
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Failed to build HTTP client: {}", e)),
+    };
+    let status = match client.get(&endpoint.url).send().await {
+        Ok(r) => r.status(),
+        Err(e) => return Err(format!("Failed to reach '{}': {}", endpoint.url, e)),
+    };
+    let expected = if endpoint.expect_status.is_empty() {
+        status.is_success() || status.is_redirection()
+    } else {
+        endpoint.expect_status.contains(&status.as_u16())
+    };
+    if expected {
+        Ok(())
+    } else {
+        Err(format!("'{}' answered {}", endpoint.url, status))
+    }
+}
+
+/// Checks every endpoint at once, in the configured order.
+pub async fn check_all(config: &EndpointsConfig) -> Vec<Status> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let mut tasks = JoinSet::new();
+    for (n, endpoint) in config.endpoints.iter().cloned().enumerate() {
+        tasks.spawn(async move {
+            let up = match check(&endpoint, timeout).await {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("Endpoint '{}' is down: {}", endpoint.name, e);
+                    false
+                }
+            };
+            (
+                n,
+                Status {
+                    name: endpoint.name,
+                    up,
+                },
+            )
+        });
+    }
+    let mut statuses = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(s) => statuses.push(s),
+            Err(e) => error!("Endpoint check task failed: {}", e),
+        }
+    }
+    statuses.sort_by_key(|(n, _)| *n);
+    let statuses: Vec<Status> = statuses.into_iter().map(|(_, s)| s).collect();
+    info!("Endpoints: {:?}", statuses);
+    statuses
+}
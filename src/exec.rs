@@ -0,0 +1,106 @@
+use crate::config::ExecConfig;
+use crate::system::CommandRunner;
+use crate::Fast;
+use log::{info, warn};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time;
+
+fn number(json: &Value, pointer: &str) -> Result<f64, String> {
+    match json.pointer(pointer) {
+        Some(Value::Number(n)) => n
+            .as_f64()
+            .ok_or_else(|| format!("'{}' isn't a number", pointer)),
+        // Some tools print their numbers as strings
+        Some(Value::String(s)) => match s.trim().parse() {
+            Ok(n) => Ok(n),
+            Err(e) => Err(format!("'{}' isn't a number: {}", pointer, e)),
+        },
+        Some(v) => Err(format!("'{}' isn't a number: {}", pointer, v)),
+        None => Err(format!("'{}' not found in the output", pointer)),
+    }
+}
+
+/// Maps a command's JSON output to a measurement through the configured
+/// JSON pointers, scaling the speeds to Mbps.
+fn parse_output(config: &ExecConfig, output: &str) -> Result<Fast, String> {
+    let json: Value = match serde_json::from_str(output) {
+        Ok(j) => j,
+        Err(e) => {
+            return Err(format!("Failed to parse JSON: {}", e));
+        }
+    };
+    let mbps = |v: f64| (v * config.speed_scale).round() as u32;
+    let upload = match &config.upload {
+        Some(p) => Some(mbps(number(&json, p)?)),
+        None => None,
+    };
+    Ok(Fast {
+        download_speed: mbps(number(&json, &config.download)?),
+        upload_speed: upload,
+        latency: number(&json, &config.latency)?.round() as u32,
+        server: config
+            .server
+            .as_ref()
+            .and_then(|p| json.pointer(p))
+            .map(|v| match v {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            }),
+        ..Default::default()
+    })
+}
+
+/// Runs the configured command, killing it once `timeout_secs` pass or the
+/// measurement is cancelled. `{interface}` in its arguments becomes the
+/// interface to bind to.
+pub async fn get_exec_info(
+    runner: &dyn CommandRunner,
+    config: &ExecConfig,
+    interface: Option<&str>,
+) -> Result<Fast, String> {
+    let (program, args) = match config.command.split_first() {
+        Some(c) => c,
+        None => {
+            return Err("The exec backend needs a command".to_string());
+        }
+    };
+    let mut command = Command::new(program);
+    match interface {
+        Some(i) if args.iter().any(|a| a.contains("{interface}")) => {
+            command.args(args.iter().map(|a| a.replace("{interface}", i)));
+        }
+        Some(i) => {
+            warn!(
+                "The exec command has no {{interface}} to bind it to '{}'",
+                i
+            );
+            command.args(args);
+        }
+        None => {
+            command.args(args);
+        }
+    }
+    let run = runner.output(command);
+    let output = match time::timeout(Duration::from_secs(config.timeout_secs), run).await {
+        Ok(Ok(o)) => o,
+        Ok(Err(e)) => {
+            return Err(format!("Failed to execute '{}': {}", program, e));
+        }
+        Err(_) => {
+            return Err(format!(
+                "'{}' timed out after {}s",
+                program, config.timeout_secs
+            ));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("\tCommand failed:\n{}", &stderr));
+    }
+
+    let o = String::from_utf8_lossy(&output.stdout);
+    info!("Command output: {}", o);
+    parse_output(config, &o)
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+pub const DEFAULT_FORMAT: &str = "{icon} {latency} ms {down_icon} {download} {unit}{stale}";
+pub const DEFAULT_FORMAT_UPLOAD: &str =
+    "{icon} {latency} ms {down_icon} {download} {unit} {up_icon} {upload} {unit}{stale}";
+/// Defaults for status lines with little room, such as tmux's
+pub const COMPACT_FORMAT: &str = "{icon}{latency}ms {down_icon}{download}M{stale}";
+pub const COMPACT_FORMAT_UPLOAD: &str =
+    "{icon}{latency}ms {down_icon}{download}M {up_icon}{upload}M{stale}";
+
+/// Markup dialect of the bar the output goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    Polybar,
+    Lemonbar,
+    Dzen2,
+    Xmobar,
+    Tmux,
+    /// Pango's `<span>`, for swaybar and nwg-panel
+    Pango,
+    /// Argos and xbar lines only take a color for the whole line
+    Argos,
+}
+
+impl Markup {
+    pub fn colored(&self, color: &str, text: &str) -> String {
+        match self {
+            Markup::Polybar | Markup::Lemonbar => format!("%{{F{}}}{}%{{F-}}", color, text),
+            Markup::Dzen2 => format!("^fg({}){}^fg()", color, text),
+            Markup::Xmobar => format!("<fc={}>{}</fc>", color, text),
+            Markup::Tmux => format!("#[fg={}]{}#[fg=default]", color, text),
+            Markup::Pango => format!("<span foreground=\"{}\">{}</span>", color, text),
+            Markup::Argos => text.to_string(),
+        }
+    }
+
+    /// Colors all of `line`, including the parts after another colored span
+    /// ends, as the bars without nesting reset to their default color there.
+    pub fn colored_all(&self, color: &str, line: &str) -> String {
+        let reset = match self {
+            Markup::Polybar | Markup::Lemonbar => "%{F-}",
+            Markup::Dzen2 => "^fg()",
+            Markup::Tmux => "#[fg=default]",
+            Markup::Xmobar | Markup::Pango | Markup::Argos => return self.colored(color, line),
+        };
+        let reopen = self.colored(color, "");
+        let reopen = &reopen[..reopen.len() - reset.len()];
+        self.colored(color, &line.replace(reset, reopen))
+    }
+
+    /// Runs `command` when mouse `button` (1 left, 2 middle, 3 right) clicks
+    /// `text`. Actions nest, so wrapping the result again adds a button.
+    /// Lemonbar prints the command instead, so its output has to be piped
+    /// into `sh`.
+    pub fn clickable(&self, button: u8, command: &str, text: &str) -> String {
+        match self {
+            // Colons end the command unless escaped
+            Markup::Polybar | Markup::Lemonbar => format!(
+                "%{{A{}:{}:}}{}%{{A}}",
+                button,
+                command.replace(':', "\\:"),
+                text
+            ),
+            // dzen2 can't escape a ')' in the command
+            Markup::Dzen2 => format!("^ca({}, {}){}^ca()", button, command, text),
+            Markup::Xmobar => format!("<action=`{}` button={}>{}</action>", command, button, text),
+            // Mouse bindings belong in the bar's config, Argos has its dropdown
+            Markup::Tmux | Markup::Pango | Markup::Argos => text.to_string(),
+        }
+    }
+
+    /// Counts the characters of `line` the bar shows, skipping markup tags.
+    pub fn visible_width(&self, line: &str) -> usize {
+        let (open, close) = match self {
+            Markup::Polybar | Markup::Lemonbar => ("%{", '}'),
+            Markup::Dzen2 => ("^", ')'),
+            Markup::Xmobar | Markup::Pango => ("<", '>'),
+            Markup::Tmux => ("#[", ']'),
+            Markup::Argos => return line.chars().count(),
+        };
+        let mut width = 0;
+        let mut rest = line;
+        while let Some(start) = rest.find(open) {
+            width += rest[..start].chars().count();
+            rest = match rest[start..].find(close) {
+                Some(end) => &rest[start + end + 1..],
+                None => "",
+            };
+        }
+        width + rest.chars().count()
+    }
+}
+
+/// Shortens the units of a rendered line, "15 ms 93 Mbps" to "15ms 93M",
+/// with `speed_unit` being what `{unit}` rendered as. Only a unit right after
+/// a number is replaced, text such as `{ssid}` is left alone.
+pub fn abbreviate_units(line: &str, speed_unit: &str) -> String {
+    let line = line.replace(" ms", "ms");
+    // An empty unit would match between every character
+    if speed_unit.is_empty() {
+        return line;
+    }
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line.as_str();
+    while let Some(i) = rest.find(speed_unit) {
+        let before = &rest[..i];
+        let number = before.strip_suffix(' ').unwrap_or(before);
+        if number.ends_with(|c: char| c.is_ascii_digit()) {
+            out.push_str(number);
+            out.push('M');
+        } else {
+            out.push_str(&rest[..i + speed_unit.len()]);
+        }
+        rest = &rest[i + speed_unit.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_placeholder(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Replaces every `{name}` in `template` with its value. Unknown placeholders
+/// render as an empty string so a disabled module simply disappears. Braces
+/// that don't enclose a name, such as JSON or polybar's `%{F#fff}` tags, are
+/// kept as they are.
+pub fn render(template: &str, values: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = match after.find('}') {
+            Some(e) if !out.ends_with('%') && is_placeholder(&after[..e]) => e,
+            _ => {
+                out.push('{');
+                rest = after;
+                continue;
+            }
+        };
+        if let Some(v) = values.get(&after[..end]) {
+            out.push_str(v);
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
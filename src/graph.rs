@@ -0,0 +1,151 @@
+use crate::Fast;
+use chrono::{Local, TimeZone};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+fn draw_error(e: impl Display) -> String {
+    format!("Failed to draw the graph: {}", e)
+}
+
+/// Local time of a unix timestamp, the date only for graphs over days.
+fn time_label(t: i64, days: u32) -> String {
+    let format = if days > 1 { "%m-%d" } else { "%H:%M" };
+    match Local.timestamp_opt(t, 0).single() {
+        Some(d) => d.format(format).to_string(),
+        None => t.to_string(),
+    }
+}
+
+/// Download and upload on top, latency below, from `start` until now.
+fn draw<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    entries: &[Fast],
+    start: i64,
+    days: u32,
+) -> Result<(), String>
+where
+    DB::ErrorType: 'static,
+{
+    let end = Local::now().timestamp();
+    let mut points: Vec<(i64, &Fast)> = entries
+        .iter()
+        .filter_map(|e| e.timestamp.map(|t| (t, e)))
+        .filter(|(t, _)| *t >= start)
+        .collect();
+    points.sort_by_key(|(t, _)| *t);
+    if points.is_empty() {
+        return Err(format!("No measurements in the last {} day(s)", days));
+    }
+    root.fill(&WHITE).map_err(draw_error)?;
+    let (speeds, latencies) = root.split_vertically(root.dim_in_pixel().1 * 3 / 5);
+    let label = |t: &i64| time_label(*t, days);
+
+    let top = points
+        .iter()
+        .map(|(_, e)| e.download_speed.max(e.upload_speed.unwrap_or(0)))
+        .max()
+        .unwrap_or(0);
+    let mut chart = ChartBuilder::on(&speeds)
+        .caption("Speed", ("sans-serif", 18))
+        .margin(10)
+        .x_label_area_size(25)
+        .y_label_area_size(45)
+        .build_cartesian_2d(start..end, 0..top + top / 10 + 1)
+        .map_err(draw_error)?;
+    chart
+        .configure_mesh()
+        .x_labels(6)
+        .x_label_formatter(&label)
+        .y_desc("Mbps")
+        .draw()
+        .map_err(draw_error)?;
+    chart
+        .draw_series(LineSeries::new(
+            points.iter().map(|(t, e)| (*t, e.download_speed)),
+            &BLUE,
+        ))
+        .map_err(draw_error)?
+        .label("download")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    let uploads: Vec<(i64, u32)> = points
+        .iter()
+        .filter_map(|(t, e)| e.upload_speed.map(|u| (*t, u)))
+        .collect();
+    if !uploads.is_empty() {
+        chart
+            .draw_series(LineSeries::new(uploads, &GREEN))
+            .map_err(draw_error)?
+            .label("upload")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], GREEN));
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(draw_error)?;
+
+    let top = points.iter().map(|(_, e)| e.latency).max().unwrap_or(0);
+    let mut chart = ChartBuilder::on(&latencies)
+        .caption("Latency", ("sans-serif", 18))
+        .margin(10)
+        .x_label_area_size(25)
+        .y_label_area_size(45)
+        .build_cartesian_2d(start..end, 0..top + top / 10 + 1)
+        .map_err(draw_error)?;
+    chart
+        .configure_mesh()
+        .x_labels(6)
+        .x_label_formatter(&label)
+        .y_desc("ms")
+        .draw()
+        .map_err(draw_error)?;
+    chart
+        .draw_series(LineSeries::new(
+            points.iter().map(|(t, e)| (*t, e.latency)),
+            &RED,
+        ))
+        .map_err(draw_error)?;
+    root.present().map_err(draw_error)
+}
+
+fn start_of(days: u32) -> i64 {
+    Local::now().timestamp() - days as i64 * 86400
+}
+
+/// The last `days` of history as an SVG document.
+pub fn render_svg(entries: &[Fast], days: u32, size: (u32, u32)) -> Result<String, String> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        draw(&root, entries, start_of(days), days)?;
+    }
+    Ok(svg)
+}
+
+/// Writes the last `days` of history to `path`, a PNG or SVG by its
+/// extension.
+pub fn write(entries: &[Fast], days: u32, path: &Path, size: (u32, u32)) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+    }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => {
+            let root = BitMapBackend::new(path, size).into_drawing_area();
+            draw(&root, entries, start_of(days), days)
+        }
+        Some("svg") => match fs::write(path, render_svg(entries, days, size)?) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to write '{}': {}", path.display(), e)),
+        },
+        _ => Err(format!(
+            "Unsupported graph '{}', use a .png or .svg file",
+            path.display()
+        )),
+    }
+}
@@ -0,0 +1,96 @@
+use crate::config::{HealthConfig, HealthProbe, ProbeKind};
+use crate::threshold::Level;
+use crate::{dns, http_client, ping};
+use log::{error, info, warn};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Runs one probe, returning how long it took.
+async fn run(probe: &HealthProbe, timeout: Duration) -> Result<Duration, String> {
+    match probe.kind {
+        ProbeKind::Ping => {
+            let addr = match ping::resolve(&probe.target).await?.into_iter().next() {
+                Some(a) => a,
+                None => return Err(format!("No address found for '{}'", probe.target)),
+            };
+            ping::probe_addr(&addr, timeout).await
+        }
+        ProbeKind::Dns if probe.target.is_empty() => dns::query_system(&probe.name, timeout).await,
+        ProbeKind::Dns => dns::query_server(&probe.target, &probe.name, timeout).await,
+        ProbeKind::Http => {
+            let client = http_client(timeout.as_secs().max(1))?;
+            let start = Instant::now();
+            match client.get(&probe.target).send().await {
+                Ok(_) => Ok(start.elapsed()),
+                Err(e) => Err(format!("Failed to reach '{}': {}", probe.target, e)),
+            }
+        }
+    }
+}
+
+fn default_limits(kind: ProbeKind) -> (u32, u32) {
+    match kind {
+        ProbeKind::Ping => (30, 300),
+        ProbeKind::Dns => (50, 500),
+        ProbeKind::Http => (300, 2000),
+    }
+}
+
+/// 100 up to `good_ms`, falling linearly to 0 at `bad_ms`.
+fn probe_score(probe: &HealthProbe, ms: u32) -> f64 {
+    let (good, bad) = default_limits(probe.kind);
+    let good = probe.good_ms.unwrap_or(good);
+    let bad = probe.bad_ms.unwrap_or(bad).max(good + 1);
+    let ms = ms.clamp(good, bad);
+    100.0 * (bad - ms) as f64 / (bad - good) as f64
+}
+
+/// Runs every probe at once and combines them into a 0-100 score, a failed
+/// probe scoring 0. None without probes to weigh.
+pub async fn score(config: &HealthConfig) -> Option<u32> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let mut tasks = JoinSet::new();
+    for probe in config.probes.iter().cloned() {
+        tasks.spawn(async move {
+            let result = run(&probe, timeout).await;
+            (probe, result)
+        });
+    }
+    let mut total = 0.0;
+    let mut weights = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let (probe, result) = match joined {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Health probe task failed: {}", e);
+                continue;
+            }
+        };
+        let score = match result {
+            Ok(d) => probe_score(&probe, d.as_millis() as u32),
+            Err(e) => {
+                warn!("Health probe failed: {}", e);
+                0.0
+            }
+        };
+        total += score * probe.weight as f64;
+        weights += probe.weight;
+    }
+    if weights == 0 {
+        return None;
+    }
+    let score = (total / weights as f64).round() as u32;
+    info!("Health score: {}", score);
+    Some(score)
+}
+
+/// The icon level the score stands for.
+pub fn level(config: &HealthConfig, score: u32) -> Level {
+    if score < config.bad_below {
+        Level::Bad
+    } else if score < config.warn_below {
+        Level::Warn
+    } else {
+        Level::Good
+    }
+}
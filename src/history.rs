@@ -0,0 +1,142 @@
+use crate::config::HistoryConfig;
+use crate::paths;
+use crate::Fast;
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use log::warn;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+const DATA_DIR: &str = "polybar-internet-speed";
+const HISTORY_FILE_PATH: &str = "history.jsonl";
+
+pub fn get_history_filename(config: &HistoryConfig) -> Result<PathBuf, String> {
+    if let Some(p) = &config.path {
+        return Ok(p.clone());
+    }
+    get_data_filename(HISTORY_FILE_PATH)
+}
+
+/// Path of `name` in the data directory, for state that should survive
+/// reboots.
+pub fn get_data_filename(name: &str) -> Result<PathBuf, String> {
+    Ok(paths::data_dir()?.join(DATA_DIR).join(name))
+}
+
+/// Appends a measurement to the history, one JSON object per line.
+pub fn append(config: &HistoryConfig, info: &Fast) -> Result<(), String> {
+    let path = get_history_filename(config)?;
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+    }
+    let line = match serde_json::to_string(info) {
+        Ok(l) => l,
+        Err(e) => {
+            return Err(format!("Failed to convert to JSON: {}", e));
+        }
+    };
+    let mut file = match fs::OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(format!("Failed to open '{}': {}", path.display(), e));
+        }
+    };
+    match file.write_all(format!("{}\n", line).as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path.display(), e)),
+    }
+}
+
+/// Loads every measurement taken at or after `since` (unix time).
+pub fn load(config: &HistoryConfig, since: Option<i64>) -> Result<Vec<Fast>, String> {
+    let path = get_history_filename(config)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", path.display(), e));
+        }
+    };
+    let mut entries = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let info: Fast = match serde_json::from_str(line) {
+            Ok(i) => i,
+            Err(e) => {
+                warn!("Skipping history line {}: {}", n + 1, e);
+                continue;
+            }
+        };
+        match (since, info.timestamp) {
+            (Some(s), Some(t)) if t < s => continue,
+            _ => entries.push(info),
+        }
+    }
+    Ok(entries)
+}
+
+/// Parses `YYYY-MM-DD` (local midnight) or an RFC 3339 timestamp.
+pub fn parse_since(s: &str) -> Result<i64, String> {
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let midnight = d.and_hms_opt(0, 0, 0).unwrap_or_default();
+        return match Local.from_local_datetime(&midnight).earliest() {
+            Some(t) => Ok(t.timestamp()),
+            None => Err(format!("Invalid local date: '{}'", s)),
+        };
+    }
+    match DateTime::parse_from_rfc3339(s) {
+        Ok(t) => Ok(t.timestamp()),
+        Err(e) => Err(format!("Invalid date '{}': {}", s, e)),
+    }
+}
+
+pub fn format_timestamp(timestamp: Option<i64>) -> String {
+    match timestamp.and_then(|t| Local.timestamp_opt(t, 0).single()) {
+        Some(t) => t.to_rfc3339(),
+        None => String::new(),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn export_csv(entries: &[Fast]) -> String {
+    let mut out =
+        String::from("timestamp,backend,download_mbps,upload_mbps,latency_ms,ip,location,labels\n");
+    for e in entries {
+        let fields = [
+            format_timestamp(e.timestamp),
+            e.backend.map(|b| b.name()).unwrap_or_default().to_string(),
+            e.download_speed.to_string(),
+            e.upload_speed.map(|u| u.to_string()).unwrap_or_default(),
+            e.latency.to_string(),
+            e.user_ip.clone().unwrap_or_default(),
+            e.user_location.clone().unwrap_or_default(),
+            e.labels
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(";"),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn export_json(entries: &[Fast]) -> Result<String, String> {
+    match serde_json::to_string_pretty(entries) {
+        Ok(j) => Ok(j),
+        Err(e) => Err(format!("Failed to convert to JSON: {}", e)),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::config::{IconTheme, IconsConfig};
+use crate::threshold::Level;
+
+#[derive(Debug, Clone)]
+pub struct IconSet {
+    pub good: String,
+    pub warn: String,
+    pub bad: String,
+    pub down: String,
+    pub up: String,
+    pub vpn: String,
+    /// Shown when the backend's program isn't installed
+    pub missing: String,
+}
+
+impl IconSet {
+    pub fn for_level(&self, level: Level) -> &str {
+        match level {
+            Level::Good => &self.good,
+            Level::Warn => &self.warn,
+            Level::Bad => &self.bad,
+        }
+    }
+}
+
+fn theme(theme: IconTheme) -> [&'static str; 7] {
+    // good, warn, bad, down, up, vpn, missing
+    match theme {
+        IconTheme::Nerdfont => [
+            "\u{f0ac}", "\u{f0ac}", "\u{f0ac}", "\u{f0ab}", "\u{f0aa}", "\u{f023}", "\u{f0ad}",
+        ],
+        IconTheme::Emoji => [
+            "\u{1f7e2}",
+            "\u{1f7e1}",
+            "\u{1f534}",
+            "\u{2b07}",
+            "\u{2b06}",
+            "\u{1f512}",
+            "\u{1f527}",
+        ],
+        IconTheme::Ascii => ["[+]", "[~]", "[!]", "v", "^", "[vpn]", "[?]"],
+        IconTheme::None => [""; 7],
+    }
+}
+
+/// The icons of the configured theme, with any per-icon overrides applied.
+pub fn icon_set(config: &IconsConfig) -> IconSet {
+    let [good, warn, bad, down, up, vpn, missing] = theme(config.theme);
+    let pick = |o: &Option<String>, d: &str| o.clone().unwrap_or_else(|| d.to_string());
+    IconSet {
+        good: pick(&config.good, good),
+        warn: pick(&config.warn, warn),
+        bad: pick(&config.bad, bad),
+        down: pick(&config.down, down),
+        up: pick(&config.up, up),
+        vpn: pick(&config.vpn, vpn),
+        missing: pick(&config.missing, missing),
+    }
+}
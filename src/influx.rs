@@ -0,0 +1,61 @@
+use crate::config::InfluxConfig;
+use crate::{http_client, Fast};
+use chrono::Local;
+use log::info;
+
+fn escape_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Formats a measurement as an InfluxDB line protocol point, in seconds.
+pub fn line_protocol(measurement: &str, info: &Fast, timestamp: i64) -> String {
+    let mut fields = vec![
+        format!("download={}i", info.download_speed),
+        format!("latency={}i", info.latency),
+    ];
+    if let Some(up) = info.upload_speed {
+        fields.push(format!("upload={}i", up));
+    }
+    let tags: String = info
+        .labels
+        .iter()
+        .map(|(k, v)| format!(",{}={}", escape_tag(k), escape_tag(v)))
+        .collect();
+    format!(
+        "{}{} {} {}",
+        escape_tag(measurement),
+        tags,
+        fields.join(","),
+        timestamp
+    )
+}
+
+/// Pushes a point through the InfluxDB v2 write API.
+pub async fn push(config: &InfluxConfig, info: &Fast) -> Result<(), String> {
+    let line = line_protocol(&config.measurement, info, Local::now().timestamp());
+    let url = format!("{}/api/v2/write", config.url.trim_end_matches('/'));
+    let client = http_client(config.timeout_secs)?;
+    match client
+        .post(&url)
+        .query(&[
+            ("org", config.org.as_str()),
+            ("bucket", config.bucket.as_str()),
+            ("precision", "s"),
+        ])
+        .header("Authorization", format!("Token {}", config.token))
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(line.clone())
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+    {
+        Ok(_) => {
+            info!("Pushed to InfluxDB: {}", line);
+            Ok(())
+        }
+        Err(e) => Err(format!("Failed to push to InfluxDB '{}': {}", url, e)),
+    }
+}
@@ -0,0 +1,107 @@
+use crate::config::{Config, InterfaceShow, InterfacesConfig};
+use crate::system::CommandRunner;
+use crate::{format, get_cache_filename, get_internet_info, netdev, Fast};
+use chrono::Local;
+use log::{error, info};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+
+/// Latest result of each interface, removed when its test fails
+fn cache_name(interface: &str) -> String {
+    format!(".polybar-internet-speed-iface-{}", interface)
+}
+
+fn save(interface: &str, info: Option<&Fast>) -> Result<(), String> {
+    let path = get_cache_filename(&cache_name(interface))?;
+    let result = match info {
+        Some(i) => match serde_json::to_string(i) {
+            Ok(json) => fs::write(&path, json),
+            Err(e) => return Err(format!("Failed to convert to JSON: {}", e)),
+        },
+        None => match fs::remove_file(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        },
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+pub fn load(interface: &str) -> Option<Fast> {
+    let path = get_cache_filename(&cache_name(interface)).ok()?;
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// After a measurement on `active`, keeps it as that interface's result and
+/// with `show = "all"` tests every other configured interface bound to it.
+pub async fn measure_others(runner: &Arc<dyn CommandRunner>, config: &Config, active: &Fast) {
+    let active_name = active.interface.as_deref();
+    if let Some(name) = active_name.filter(|n| config.interfaces.names.iter().any(|i| i == n)) {
+        if let Err(e) = save(name, Some(active)) {
+            error!("{}", e);
+        }
+    }
+    if config.interfaces.show != InterfaceShow::All {
+        return;
+    }
+    for name in &config.interfaces.names {
+        if Some(name.as_str()) == active_name {
+            continue;
+        }
+        let result = if netdev::is_up(name) {
+            let mut bound = config.clone();
+            bound.interfaces.bind = Some(name.clone());
+            info!("Testing '{}'", name);
+            match get_internet_info(runner, &bound).await {
+                Ok(mut i) => {
+                    i.timestamp = Some(Local::now().timestamp());
+                    i.interface = Some(name.clone());
+                    Some(i)
+                }
+                Err(e) => {
+                    error!("Test on '{}' failed: {}", name, e);
+                    None
+                }
+            }
+        } else {
+            info!("'{}' is down, not testing it", name);
+            None
+        };
+        if let Err(e) = save(name, result.as_ref()) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// `{interfaces}`: each configured interface's latest result, or only the
+/// active one's with `show = "active"`.
+pub fn render_summary(config: &InterfacesConfig, active: Option<&str>) -> String {
+    let names = config
+        .names
+        .iter()
+        .filter(|n| config.show == InterfaceShow::All || Some(n.as_str()) == active);
+    let parts: Vec<String> = names
+        .map(|name| {
+            let mut values = HashMap::new();
+            values.insert("name".to_string(), name.clone());
+            if Some(name.as_str()) == active {
+                values.insert("active".to_string(), config.active_marker.clone());
+            }
+            match load(name) {
+                Some(i) => {
+                    values.insert("download".to_string(), i.download_speed.to_string());
+                    values.insert("latency".to_string(), i.latency.to_string());
+                    if let Some(up) = i.upload_speed {
+                        values.insert("upload".to_string(), up.to_string());
+                    }
+                    format::render(&config.format, &values)
+                }
+                None => format::render(&config.missing_format, &values),
+            }
+        })
+        .collect();
+    parts.join(&config.separator)
+}
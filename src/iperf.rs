@@ -0,0 +1,92 @@
+use crate::config::Iperf3Config;
+use crate::ping;
+use crate::system::CommandRunner;
+use crate::Fast;
+use log::info;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::process::Command;
+
+const LATENCY_PROBES: u32 = 3;
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize)]
+struct Sum {
+    bits_per_second: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct End {
+    sum_received: Sum,
+}
+
+#[derive(Debug, Deserialize)]
+struct Report {
+    end: End,
+}
+
+/// Runs one iperf3 test, returning the received rate in Mbps. `reverse` makes
+/// the server send, which measures download.
+async fn run(
+    runner: &dyn CommandRunner,
+    config: &Iperf3Config,
+    reverse: bool,
+    interface: Option<&str>,
+) -> Result<u32, String> {
+    let mut command = Command::new("iperf3");
+    command
+        .args(["-c", &config.server, "--json"])
+        .args(["-p", &config.port.to_string()])
+        .args(["-t", &config.duration_secs.to_string()]);
+    if reverse {
+        command.arg("-R");
+    }
+    if let Some(i) = interface {
+        command.args(["--bind-dev", i]);
+    }
+    let output = match runner.output(command).await {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(format!("Failed to execute iperf3: {}", e));
+        }
+    };
+    // Failures are reported in the JSON's "error" too, stdout has the detail
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Err(format!("\tCommand failed:\n{}", &stdout));
+    }
+
+    let o = String::from_utf8_lossy(&output.stdout);
+    info!("Command output: {}", o);
+    let r: Report = match serde_json::from_str(&o) {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(format!("Failed to parse JSON: {}", e));
+        }
+    };
+    Ok((r.end.sum_received.bits_per_second / 1_000_000.0).round() as u32)
+}
+
+/// Measures download and optionally upload against the iperf3 server. The
+/// latency is a TCP handshake to the server's port.
+pub async fn get_iperf3_info(
+    runner: &dyn CommandRunner,
+    config: &Iperf3Config,
+    interface: Option<&str>,
+) -> Result<Fast, String> {
+    let download = run(runner, config, true, interface).await?;
+    let upload = if config.upload {
+        Some(run(runner, config, false, interface).await?)
+    } else {
+        None
+    };
+    let address = format!("{}:{}", config.server, config.port);
+    let latency = ping::probe_via(&address, LATENCY_PROBES, LATENCY_TIMEOUT, interface).await?;
+    Ok(Fast {
+        download_speed: download,
+        upload_speed: upload,
+        latency,
+        server: Some(config.server.clone()),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,131 @@
+use crate::config::IpInfoConfig;
+use crate::system::SystemClock;
+use crate::{get_cache_filename, get_seconds_since_file_modified, http_client};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+const IP_FILE_PATH: &str = ".polybar-internet-speed-ip.toml";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct IpInfo {
+    pub ip: String,
+    pub city: Option<String>,
+    pub isp: Option<String>,
+}
+
+/// Picks the first of `keys` present in `json`, since every ip-info service
+/// names its fields a little differently.
+fn first_str(json: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .find_map(|k| json.get(*k).and_then(|v| v.as_str()))
+        .map(|s| s.to_string())
+}
+
+async fn lookup(config: &IpInfoConfig) -> Result<IpInfo, String> {
+    let client = http_client(config.timeout_secs)?;
+    let resp = match client
+        .get(&config.url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(format!("Ip info request to '{}' failed: {}", config.url, e));
+        }
+    };
+    let body = match resp.text().await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(format!("Failed to read ip info response: {}", e));
+        }
+    };
+    let json: serde_json::Value = match serde_json::from_str(&body) {
+        Ok(j) => j,
+        Err(e) => {
+            return Err(format!("Failed to parse ip info JSON: {}", e));
+        }
+    };
+    info!("Ip info response: {}", json);
+    let ip = match first_str(&json, &["ip", "query"]) {
+        Some(i) => i,
+        None => {
+            return Err(format!("No ip in response from '{}'", config.url));
+        }
+    };
+    Ok(IpInfo {
+        ip,
+        city: first_str(&json, &["city"]),
+        isp: first_str(&json, &["isp", "org"]),
+    })
+}
+
+/// Caches ip info reported by a backend, saving a separate lookup. Backends
+/// don't report the ISP, so without one it's only kept when the cache has
+/// the ISP of the same ip, which `{isp}` and `vpn.expected_isp` need.
+pub async fn save_reported(reported: &IpInfo) -> Result<(), String> {
+    let mut info = reported.clone();
+    if info.isp.is_none() {
+        let path = get_cache_filename(IP_FILE_PATH)?;
+        match load(&path).await {
+            Ok(cached) if cached.ip == info.ip && cached.isp.is_some() => {
+                info.isp = cached.isp;
+                info.city = info.city.or(cached.city);
+            }
+            _ => return Ok(()),
+        }
+    }
+    save(&info).await
+}
+
+async fn save(info: &IpInfo) -> Result<(), String> {
+    let path = get_cache_filename(IP_FILE_PATH)?;
+    let toml = match toml::to_string(info) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(format!("Failed to convert to TOML: {}", e));
+        }
+    };
+    match fs::write(&path, toml).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write ip file: {}", e)),
+    }
+}
+
+async fn load(path: &str) -> Result<IpInfo, String> {
+    let contents = match fs::read_to_string(path).await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read ip file: {}", e));
+        }
+    };
+    match toml::from_str(&contents) {
+        Ok(i) => Ok(i),
+        Err(e) => Err(format!("Failed to parse ip file: {}", e)),
+    }
+}
+
+/// The cached ip info however old, without looking it up.
+pub async fn get_cached_ip_info() -> Result<IpInfo, String> {
+    load(&get_cache_filename(IP_FILE_PATH)?).await
+}
+
+/// Returns the cached ip info, looking it up again once older than the
+/// configured TTL.
+pub async fn get_ip_info(config: &IpInfoConfig) -> Result<IpInfo, String> {
+    let path = get_cache_filename(IP_FILE_PATH)?;
+    if let Ok(elapsed) = get_seconds_since_file_modified(&path, &SystemClock) {
+        if elapsed <= config.ttl_secs {
+            match load(&path).await {
+                Ok(i) => return Ok(i),
+                Err(e) => warn!("{}", e),
+            }
+        }
+    }
+    let info = lookup(config).await?;
+    if let Err(e) = save(&info).await {
+        warn!("{}", e);
+    }
+    Ok(info)
+}
@@ -0,0 +1,251 @@
+use crate::config::LatencyConfig;
+use crate::{get_cache_filename, ping};
+use chrono::Local;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+// The daemon's latency samples, read by every other invocation
+const LATENCY_FILE_PATH: &str = ".polybar-internet-speed-latency";
+/// Each power of two splits into 2^SUB_BITS buckets, within ~3% of the value
+const SUB_BITS: u32 = 5;
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+
+/// The bucket of a value in microseconds. Values below `SUB_BUCKETS` are
+/// exact, above them each bucket is 1/32 of its power of two wide.
+fn bucket(us: u64) -> u32 {
+    if us < SUB_BUCKETS {
+        return us as u32;
+    }
+    let exponent = 63 - us.leading_zeros();
+    let sub = (us >> (exponent - SUB_BITS)) & (SUB_BUCKETS - 1);
+    ((exponent - SUB_BITS + 1) as u64 * SUB_BUCKETS + sub) as u32
+}
+
+/// The lowest value and width of a bucket.
+fn bucket_range(index: u32) -> (u64, u64) {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return (index, 1);
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let sub = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub) << shift, 1 << shift)
+}
+
+/// Log-linear counts of round trips, HDR histogram style: constant relative
+/// precision from microseconds to seconds in a few hundred buckets at most.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Histogram {
+    /// Unix time of the first sample
+    pub since: i64,
+    pub counts: BTreeMap<u32, u64>,
+    /// Probes that got no answer
+    pub lost: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        *self
+            .counts
+            .entry(bucket(latency.as_micros() as u64))
+            .or_default() += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (b, c) in &other.counts {
+            *self.counts.entry(*b).or_default() += c;
+        }
+        self.lost += other.lost;
+        self.since = match (self.since, other.since) {
+            (0, s) | (s, 0) => s,
+            (a, b) => a.min(b),
+        };
+    }
+
+    /// The middle of the bucket holding the `p`th percentile, in milliseconds.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((p / 100.0 * total as f64).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+        for (b, c) in &self.counts {
+            seen += c;
+            if seen >= rank {
+                let (low, width) = bucket_range(*b);
+                return Some((low as f64 + width as f64 / 2.0) / 1000.0);
+            }
+        }
+        None
+    }
+}
+
+/// The current window and the one before it, so percentiles always cover
+/// between one and two windows.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Windows {
+    pub current: Histogram,
+    pub previous: Histogram,
+}
+
+impl Windows {
+    pub fn combined(&self) -> Histogram {
+        let mut h = self.current.clone();
+        h.merge(&self.previous);
+        h
+    }
+}
+
+pub fn load() -> Option<Windows> {
+    let path = get_cache_filename(LATENCY_FILE_PATH).ok()?;
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn save(windows: &Windows) -> Result<(), String> {
+    let path = get_cache_filename(LATENCY_FILE_PATH)?;
+    let json = match serde_json::to_string(windows) {
+        Ok(j) => j,
+        Err(e) => return Err(format!("Failed to convert to JSON: {}", e)),
+    };
+    match fs::write(&path, json) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// Samples the round trip to the target across daemon ticks, saving the
+/// histograms every `save_secs`.
+pub struct Recorder {
+    windows: Windows,
+    addr: Option<SocketAddr>,
+    sampled_at: Option<Instant>,
+    saved_at: Instant,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            windows: load().unwrap_or_default(),
+            addr: None,
+            sampled_at: None,
+            saved_at: Instant::now(),
+        }
+    }
+
+    /// Probes the target once `sample_secs` passed since the last sample.
+    pub async fn check(&mut self, config: &LatencyConfig) {
+        let interval = Duration::from_secs(config.sample_secs);
+        if self.sampled_at.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.sampled_at = Some(Instant::now());
+        let now = Local::now().timestamp();
+        if self.windows.current.since == 0 {
+            self.windows.current.since = now;
+        } else if now - self.windows.current.since >= config.window_mins as i64 * 60 {
+            self.windows.previous = std::mem::take(&mut self.windows.current);
+            self.windows.current.since = now;
+        }
+        // Resolving every few seconds would time DNS too
+        let addr = match self.addr {
+            Some(a) => a,
+            None => match ping::resolve(&config.target)
+                .await
+                .map(|a| a.into_iter().next())
+            {
+                Ok(Some(a)) => *self.addr.insert(a),
+                Ok(None) => {
+                    warn!("No address found for '{}'", config.target);
+                    return;
+                }
+                Err(e) => {
+                    warn!("{}", e);
+                    return;
+                }
+            },
+        };
+        match ping::probe_addr(&addr, Duration::from_millis(config.timeout_ms)).await {
+            Ok(d) => self.windows.current.record(d),
+            Err(e) => {
+                warn!("{}", e);
+                self.windows.current.lost += 1;
+                self.addr = None;
+            }
+        }
+        if self.saved_at.elapsed() >= Duration::from_secs(config.save_secs) {
+            self.saved_at = Instant::now();
+            if let Err(e) = save(&self.windows) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+/// `{p50}`, `{p95}` and `{p99}` in whole milliseconds.
+pub fn placeholders(histogram: &Histogram) -> Vec<(String, String)> {
+    [("p50", 50.0), ("p95", 95.0), ("p99", 99.0)]
+        .iter()
+        .filter_map(|(name, p)| {
+            let ms = histogram.percentile(*p)?;
+            Some((name.to_string(), format!("{:.0}", ms)))
+        })
+        .collect()
+}
+
+/// The percentiles, losses and a bar per power of two milliseconds.
+pub fn render_stats(histogram: &Histogram) -> String {
+    let total = histogram.total();
+    if total == 0 && histogram.lost == 0 {
+        return "No latency samples, is the daemon running with [latency] enabled?\n".to_string();
+    }
+    let mut out = String::new();
+    let sent = total + histogram.lost;
+    let _ = writeln!(
+        out,
+        "{} samples over the last {}, {} lost ({:.2}%)",
+        sent,
+        crate::format_age((Local::now().timestamp() - histogram.since).max(0) as u64),
+        histogram.lost,
+        100.0 * histogram.lost as f64 / sent as f64
+    );
+    for (name, p) in [
+        ("p50", 50.0),
+        ("p90", 90.0),
+        ("p95", 95.0),
+        ("p99", 99.0),
+        ("p99.9", 99.9),
+        ("max", 100.0),
+    ] {
+        if let Some(ms) = histogram.percentile(p) {
+            let _ = writeln!(out, "{:>6} {:>9.1} ms", name, ms);
+        }
+    }
+    // Buckets grouped per power of two milliseconds
+    let mut groups: BTreeMap<u32, u64> = BTreeMap::new();
+    for (b, c) in &histogram.counts {
+        let ms = bucket_range(*b).0 / 1000;
+        let group = if ms == 0 { 0 } else { 64 - ms.leading_zeros() };
+        *groups.entry(group).or_default() += c;
+    }
+    let most = groups.values().copied().max().unwrap_or(1).max(1);
+    let _ = writeln!(out);
+    for (group, count) in groups {
+        let (low, high) = match group {
+            0 => (0, 1),
+            g => (1u64 << (g - 1), 1u64 << g),
+        };
+        let bar = "#".repeat((count * 40).div_ceil(most) as usize);
+        let _ = writeln!(out, "{:>5}-{:<5} ms {:>7}  {}", low, high, count, bar);
+    }
+    out
+}
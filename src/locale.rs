@@ -0,0 +1,46 @@
+use crate::config::LocaleConfig;
+use std::env;
+
+/// Languages writing a decimal comma, by their ISO 639-1 code
+const DECIMAL_COMMA: &[&str] = &[
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu", "id", "is",
+    "it", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr",
+    "uk", "vi",
+];
+
+/// The locale numbers are formatted for, the first of `$LC_ALL`,
+/// `$LC_NUMERIC` and `$LANG` that is set, like the C library picks it.
+fn numeric_locale() -> Option<String> {
+    ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|l| !l.is_empty())
+}
+
+/// The configured decimal separator, otherwise the locale's, `"de_DE.UTF-8"`
+/// giving a comma.
+pub fn decimal_separator(config: &LocaleConfig) -> String {
+    if let Some(s) = &config.decimal_separator {
+        return s.clone();
+    }
+    let locale = numeric_locale().unwrap_or_default();
+    let language = locale.split(['_', '.', '@']).next().unwrap_or("");
+    if DECIMAL_COMMA.contains(&language) {
+        ",".to_string()
+    } else {
+        ".".to_string()
+    }
+}
+
+/// Swaps the decimal point of an already formatted number, `"12.5"`.
+pub fn number(config: &LocaleConfig, formatted: &str) -> String {
+    formatted.replace('.', &decimal_separator(config))
+}
+
+/// The translation of a status word, the word itself without one.
+pub fn word(config: &LocaleConfig, word: &str) -> String {
+    match config.strings.get(word) {
+        Some(w) => w.clone(),
+        None => word.to_string(),
+    }
+}
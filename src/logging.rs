@@ -0,0 +1,208 @@
+use crate::config::{LogKind, LogLevel, LogTarget, LoggingConfig};
+use crate::paths;
+use log::LevelFilter;
+#[cfg(unix)]
+use log::{Level, Record};
+use log4rs::{
+    append::{
+        console::{ConsoleAppender, Target},
+        rolling_file::{
+            policy::compound::{
+                roll::{delete::DeleteRoller, fixed_window::FixedWindowRoller, Roll},
+                trigger::size::SizeTrigger,
+                CompoundPolicy,
+            },
+            RollingFileAppender,
+        },
+        Append,
+    },
+    config::{Appender, Config as LogConfig, Root},
+    encode::pattern::PatternEncoder,
+    filter::threshold::ThresholdFilter,
+};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+const IDENTIFIER: &str = "polybar-internet-speed";
+const LOG_FILE_PATH: &str = "polybar-internet-speed/rusting.log";
+const FILE_PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S)} [{t} {l} {M}:{L}] - {m}{n}";
+const CONSOLE_PATTERN: &str = "{l} {M}: {m}{n}";
+#[cfg(unix)]
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+#[cfg(target_os = "macos")]
+const SYSLOG_SOCKET: &str = "/var/run/syslog";
+#[cfg(all(unix, not(target_os = "macos")))]
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::Off,
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+        LogLevel::Trace => LevelFilter::Trace,
+    }
+}
+
+/// Syslog severity, which journald's PRIORITY shares.
+#[cfg(unix)]
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+pub fn get_log_filename() -> Result<PathBuf, String> {
+    Ok(paths::state_dir()?.join(LOG_FILE_PATH))
+}
+
+/// Sends each record as a datagram to a local socket, as syslog and journald
+/// both take them.
+#[cfg(unix)]
+#[derive(Debug)]
+struct DatagramAppender {
+    socket: PathBuf,
+    journald: bool,
+}
+
+#[cfg(unix)]
+impl DatagramAppender {
+    fn encode(&self, record: &Record) -> Vec<u8> {
+        let message = format!("{}: {}", record.target(), record.args());
+        if !self.journald {
+            // RFC 3164 with the "user" facility
+            let priority = 8 + severity(record.level());
+            let line = format!(
+                "<{}>{}[{}]: {}",
+                priority,
+                IDENTIFIER,
+                std::process::id(),
+                message
+            );
+            return line.into_bytes();
+        }
+        let mut out = format!(
+            "PRIORITY={}\nSYSLOG_IDENTIFIER={}\n",
+            severity(record.level()),
+            IDENTIFIER
+        )
+        .into_bytes();
+        // Values with newlines need the length prefixed binary form
+        out.extend_from_slice(b"MESSAGE\n");
+        out.extend_from_slice(&(message.len() as u64).to_le_bytes());
+        out.extend_from_slice(message.as_bytes());
+        out.push(b'\n');
+        out
+    }
+}
+
+#[cfg(unix)]
+impl Append for DatagramAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.send_to(&self.encode(record), &self.socket)?;
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+fn build_appender(target: &LogTarget) -> Result<Box<dyn Append>, String> {
+    match target.kind {
+        LogKind::Stdout => Ok(Box::new(
+            ConsoleAppender::builder()
+                .target(Target::Stdout)
+                .encoder(Box::new(PatternEncoder::new(CONSOLE_PATTERN)))
+                .build(),
+        )),
+        LogKind::Stderr => Ok(Box::new(
+            ConsoleAppender::builder()
+                .target(Target::Stderr)
+                .encoder(Box::new(PatternEncoder::new(CONSOLE_PATTERN)))
+                .build(),
+        )),
+        LogKind::File => {
+            let path = match &target.path {
+                Some(p) => p.clone(),
+                None => get_log_filename()?,
+            };
+            let trigger = SizeTrigger::new(target.max_size_kb * 1024);
+            let roller: Box<dyn Roll> = if target.keep == 0 {
+                Box::new(DeleteRoller::new())
+            } else {
+                let pattern = format!("{}.{{}}", path.display());
+                match FixedWindowRoller::builder()
+                    .base(1)
+                    .build(&pattern, target.keep)
+                {
+                    Ok(r) => Box::new(r),
+                    Err(e) => {
+                        return Err(format!("Invalid log rotation for '{}': {}", pattern, e));
+                    }
+                }
+            };
+            let policy = CompoundPolicy::new(Box::new(trigger), roller);
+            match RollingFileAppender::builder()
+                .encoder(Box::new(PatternEncoder::new(FILE_PATTERN)))
+                .build(&path, Box::new(policy))
+            {
+                Ok(a) => Ok(Box::new(a)),
+                Err(e) => Err(format!("Failed to open '{}': {}", path.display(), e)),
+            }
+        }
+        #[cfg(unix)]
+        LogKind::Journald => Ok(Box::new(DatagramAppender {
+            socket: PathBuf::from(JOURNALD_SOCKET),
+            journald: true,
+        })),
+        #[cfg(unix)]
+        LogKind::Syslog => Ok(Box::new(DatagramAppender {
+            socket: PathBuf::from(SYSLOG_SOCKET),
+            journald: false,
+        })),
+        #[cfg(not(unix))]
+        LogKind::Journald | LogKind::Syslog => {
+            Err("syslog and journald are only available on Unix".to_string())
+        }
+    }
+}
+
+/// Sets up every configured target, each filtered at its own level. Targets
+/// that can't be set up are reported on stderr and skipped.
+pub fn init(config: &LoggingConfig) -> Result<(), String> {
+    let mut builder = LogConfig::builder();
+    let mut root = Root::builder();
+    let mut max_level = LevelFilter::Off;
+    for (i, target) in config.targets.iter().enumerate() {
+        let appender = match build_appender(target) {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        let name = format!("{:?}{}", target.kind, i);
+        let level = level_filter(target.level);
+        max_level = max_level.max(level);
+        builder = builder.appender(
+            Appender::builder()
+                .filter(Box::new(ThresholdFilter::new(level)))
+                .build(&name, appender),
+        );
+        root = root.appender(name);
+    }
+    let log_config = match builder.build(root.build(max_level)) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Invalid logging config: {}", e));
+        }
+    };
+    match log4rs::init_config(log_config) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to set up logging: {}", e)),
+    }
+}
@@ -1,20 +1,95 @@
-use chrono::{DateTime, Local};
-use log::{error, info};
-use log4rs::{
-    append::file::FileAppender,
-    config::{Appender, Config, Root},
-    encode::pattern::PatternEncoder,
-};
+use chrono::{DateTime, Local, TimeZone};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use serde_json;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::task::JoinSet;
+use tokio::{signal, time};
+
+mod battery;
+mod budget;
+mod check;
+mod config;
+mod cycle;
+mod dns;
+mod doctor;
+mod endpoints;
+mod exec;
+mod format;
+mod graph;
+mod health;
+mod history;
+mod icons;
+mod influx;
+mod interfaces;
+mod iperf;
+mod ipinfo;
+mod latency;
+mod locale;
+mod logging;
+mod mock;
+mod modem;
+mod mqtt;
+mod native;
+mod netdev;
+mod nm;
+mod notify;
+mod outage;
+mod output;
+mod paths;
+mod ping;
+mod polybar;
+mod quiet;
+mod reload;
+mod report;
+mod route;
+mod sampling;
+mod schedule;
+mod serve;
+mod sink;
+mod snapshot;
+mod speedtest;
+mod stats;
+mod statsd;
+mod store;
+mod system;
+mod systemd;
+mod testlock;
+#[cfg(all(test, unix))]
+mod tests;
+mod threshold;
+mod tui;
+mod usage;
+mod vpn;
+mod webhook;
+mod wifi;
+
+use config::{
+    Backend, BudgetAction, Config, FastConfig, LogKind, LogLevel, LogTarget, LoggingConfig,
+    OutputFormat, RaceConfig, RacePick,
+};
+use format::Markup;
+use system::{Clock, CommandRunner, SystemClock, SystemRunner};
 
 const BUFFER_FILE_PATH: &str = ".polybar-internet-speed.toml";
+/// Layout of the buffered file, bumped when old files need migrating
+const BUFFER_VERSION: u32 = 1;
+/// First line of the buffered file, followed by the checksum of the rest
+const CHECKSUM_PREFIX: &str = "# checksum: ";
+/// Most recent measurements listed in the Argos dropdown
+const ARGOS_HISTORY: usize = 10;
 
-fn get_seconds_since_file_modified(file: &str) -> Result<u64, String> {
+fn get_seconds_since_file_modified(file: &str, clock: &dyn Clock) -> Result<u64, String> {
     let fmeta = match fs::metadata(file) {
         Ok(meta) => meta,
         Err(e) => {
@@ -38,7 +113,7 @@ fn get_seconds_since_file_modified(file: &str) -> Result<u64, String> {
         }
     };
 
-    let ltime = Local::now();
+    let ltime = clock.now();
     let ftime: DateTime<Local> = DateTime::from(file_age);
     let elapsed = match ltime.signed_duration_since(ftime).to_std() {
         Ok(e) => e.as_secs(),
@@ -49,15 +124,56 @@ fn get_seconds_since_file_modified(file: &str) -> Result<u64, String> {
     Ok(elapsed)
 }
 
-fn get_internet_info() -> Result<Fast, String> {
+/// Shared builder for the HTTP clients, which time a whole request out after
+/// `timeout_secs`.
+fn http_client(timeout_secs: u64) -> Result<reqwest::Client, String> {
+    bound_http_client(timeout_secs, None)
+}
+
+/// Like `http_client`, connecting through `interface` when given.
+fn bound_http_client(
+    timeout_secs: u64,
+    interface: Option<&str>,
+) -> Result<reqwest::Client, String> {
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(timeout_secs));
+    #[cfg(target_os = "linux")]
+    let builder = match interface {
+        Some(i) => builder.interface(i),
+        None => builder,
+    };
+    #[cfg(not(target_os = "linux"))]
+    if let Some(i) = interface {
+        return Err(format!("Binding to '{}' is only supported on Linux", i));
+    }
+    match builder.build() {
+        Ok(c) => Ok(c),
+        Err(e) => Err(format!("Failed to build HTTP client: {}", e)),
+    }
+}
+
+async fn get_fast_info(runner: &dyn CommandRunner, config: &FastConfig) -> Result<Fast, String> {
     // println!("Checking internet speed. Please wait...");
-    let output = Command::new("fast")
-        .arg("--json")
-        .output()
-        .expect("Failed to execute command");
+    let mut command = Command::new(&config.path);
+    command.arg("--json").args(&config.args).envs(&config.env);
+    let output = match runner.output(command).await {
+        Ok(o) => o,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "'{}' not found, install fast-cli or set fast.path",
+                config.path.display()
+            ));
+        }
+        Err(e) => {
+            return Err(format!(
+                "Failed to execute '{}': {}",
+                config.path.display(),
+                e
+            ));
+        }
+    };
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("\tCommand failed:\n{}", &stderr));
+        return Err(format!("\tfast test failed:\n{}", &stderr));
     }
 
     let o = String::from_utf8_lossy(&output.stdout);
@@ -72,160 +188,2174 @@ fn get_internet_info() -> Result<Fast, String> {
     Ok(f)
 }
 
+async fn get_backend_info(
+    runner: &dyn CommandRunner,
+    config: &Config,
+    backend: Backend,
+) -> Result<Fast, String> {
+    debug!("Running the {} backend", backend.name());
+    let bind = config.interfaces.bind.as_deref();
+    let mut info = match backend {
+        Backend::Fast if bind.is_some() => Err(
+            "fast-cli can't be bound to an interface, use the native or speedtest backend"
+                .to_string(),
+        ),
+        Backend::Fast => get_fast_info(runner, &config.fast).await,
+        Backend::Native => native::get_native_info(&config.native, bind).await,
+        Backend::Speedtest => speedtest::get_speedtest_info(runner, &config.speedtest, bind).await,
+        Backend::Exec => match &config.exec {
+            Some(e) => exec::get_exec_info(runner, e, bind).await,
+            None => Err("The exec backend needs an [exec] section".to_string()),
+        },
+        Backend::Iperf3 => match &config.iperf3 {
+            Some(i) => iperf::get_iperf3_info(runner, i, bind).await,
+            None => Err("The iperf3 backend needs an [iperf3] section".to_string()),
+        },
+        Backend::Mock => mock::get_mock_info(&config.mock).await,
+    }?;
+    debug!("Parsed {} result: {:?}", backend.name(), info);
+    info.backend = Some(backend);
+    Ok(info)
+}
+
+/// Runs every backend in `race` as its own task.
+async fn race_backends(
+    runner: &Arc<dyn CommandRunner>,
+    config: &Config,
+    race: &RaceConfig,
+) -> Result<Fast, String> {
+    let mut tasks = JoinSet::new();
+    for backend in &race.backends {
+        let runner = runner.clone();
+        let config = config.clone();
+        let backend = *backend;
+        tasks.spawn(async move {
+            (
+                backend,
+                get_backend_info(runner.as_ref(), &config, backend).await,
+            )
+        });
+    }
+
+    let mut results: Vec<Fast> = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (backend, result) = match joined {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Backend task failed: {}", e);
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+        match result {
+            Ok(f) => {
+                info!(
+                    "Backend {} finished: {} Mbps",
+                    backend.name(),
+                    f.download_speed
+                );
+                results.push(f);
+                if race.pick == RacePick::First {
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("Backend {} failed: {}", backend.name(), e);
+                errors.push(format!("{}: {}", backend.name(), e));
+            }
+        }
+    }
+    // Dropping the set cancels the backends still running, killing their
+    // processes
+    drop(tasks);
+    let best = match race.pick {
+        RacePick::First => results.first(),
+        RacePick::Max => results.iter().max_by_key(|f| f.download_speed),
+    };
+    let mut info = match best {
+        Some(f) => f.clone(),
+        None => {
+            return Err(format!("Every backend failed: {}", errors.join("; ")));
+        }
+    };
+    info.results = results;
+    Ok(info)
+}
+
+async fn measure(runner: &Arc<dyn CommandRunner>, config: &Config) -> Result<Fast, String> {
+    match &config.race {
+        Some(race) => race_backends(runner, config, race).await,
+        None => get_backend_info(runner.as_ref(), config, config.backend).await,
+    }
+}
+
+/// Runs the backend `sampling.count` times and keeps the median.
+async fn get_internet_info(
+    runner: &Arc<dyn CommandRunner>,
+    config: &Config,
+) -> Result<Fast, String> {
+    let count = config.sampling.count.max(1);
+    let mut samples = Vec::new();
+    let mut last_error = None;
+    for n in 0..count {
+        match measure(runner, config).await {
+            Ok(f) => samples.push(f),
+            Err(e) => {
+                error!("Sample {} of {} failed: {}", n + 1, count, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match sampling::combine(samples, config.sampling.outlier_mads) {
+        Some(f) => Ok(f),
+        None => Err(last_error.unwrap_or_default()),
+    }
+}
+
 fn write_buffered_file(file: &str, info: &Fast) -> Result<(), String> {
-    let toml = match toml::to_string(&info) {
+    let mut value = match toml::Value::try_from(info) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(format!("Failed to convert to TOML: {}", e));
+        }
+    };
+    if let Some(table) = value.as_table_mut() {
+        table.insert(
+            "version".to_string(),
+            toml::Value::Integer(BUFFER_VERSION.into()),
+        );
+    }
+    let toml = match toml::to_string(&value) {
         Ok(t) => t,
         Err(e) => {
             return Err(format!("Failed to convert to TOML: {}", e));
         }
     };
-    match fs::write(file, toml) {
-        Ok(_) => (),
+    let toml = format!("{}{:016x}\n{}", CHECKSUM_PREFIX, checksum(&toml), toml);
+    // Renaming over the buffer leaves it whole if writing is cut short
+    let temp = format!("{}.{}.tmp", file, process::id());
+    match fs::write(&temp, toml).and_then(|_| fs::rename(&temp, file)) {
+        Ok(_) => Ok(()),
         Err(e) => {
-            return Err(format!("Failed to write buffered file: {}", e));
+            let _ = fs::remove_file(&temp);
+            Err(format!("Failed to write buffered file: {}", e))
         }
     }
-    Ok(())
 }
 
 fn get_buffered_filename() -> Result<String, String> {
-    let xdg = match env::var("XDG_CACHE_HOME") {
-        Ok(x) => x,
-        Err(e) => {
-            return Err(format!("Failed to get XDG_CACHE_HOME: {}", e));
-        }
-    };
-    let path = PathBuf::from(xdg).join(BUFFER_FILE_PATH);
+    get_cache_filename(BUFFER_FILE_PATH)
+}
+
+fn get_cache_filename(name: &str) -> Result<String, String> {
+    let path = paths::cache_dir()?.join(name);
     let file = match path.to_str() {
         Some(f) => f,
         None => {
-            return Err(format!("Failed to convert path to string"));
+            return Err("Failed to convert path to string".to_string());
         }
     };
     Ok(file.to_string())
 }
 
-fn get_new_internet_info() -> Result<Fast, String> {
-    let info = match get_internet_info() {
+/// The configured labels with the detected `host`, without empty ones.
+fn labels(config: &Config) -> BTreeMap<String, String> {
+    let mut labels = config.labels.clone();
+    labels
+        .entry("host".to_string())
+        .or_insert_with(system::hostname);
+    labels.retain(|_, v| !v.is_empty());
+    labels
+}
+
+async fn get_new_internet_info(config: &Config) -> Result<Fast, String> {
+    let requested = Local::now().timestamp();
+    // Concurrent requests wait for the test running and share its result
+    let mut lock = testlock::lock().await?;
+    if let Ok(mut info) = get_buffered_internet_info(config) {
+        if info.timestamp.is_some_and(|t| t >= requested) {
+            info!("Sharing the result of the test that just finished");
+            info.cached = false;
+            return Ok(info);
+        }
+    }
+    if let Some(last) = lock.last_run() {
+        let since = Local::now().timestamp() - last;
+        if (0..config.min_test_interval_secs as i64).contains(&since) {
+            info!("Last test started {}s ago, reusing its result", since);
+            return match get_buffered_internet_info(config) {
+                // Older than that test, which failed
+                Ok(mut i) => {
+                    if i.timestamp.is_none_or(|t| t < last) {
+                        i.stale = true;
+                    }
+                    Ok(i)
+                }
+                Err(e) => Err(format!(
+                    "Last test started {}s ago, under min_test_interval_secs, and left no result: {}",
+                    since, e
+                )),
+            };
+        }
+    }
+    if let Err(e) = lock.record(Local::now().timestamp()) {
+        error!("{}", e);
+    }
+    let environment = if config.snapshot.enabled {
+        Some(snapshot::take_snapshot(&config.snapshot).await)
+    } else {
+        None
+    };
+    let runner: Arc<dyn CommandRunner> = Arc::new(SystemRunner);
+    let counted = if config.budget.enabled {
+        budget::start(test_interface(config).as_deref())
+    } else {
+        None
+    };
+    let result = get_internet_info(&runner, config).await;
+    // Failed tests use data too
+    let data_bytes = counted.and_then(|c| match budget::record(c) {
+        Ok(b) => Some(b),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    });
+    let mut info = match result {
         Ok(f) => f,
+        Err(mut e) => {
+            if config.route.enabled {
+                if let Some(cause) = route::diagnose(runner.as_ref(), &config.route).await {
+                    e = format!("{}, offline: {}", e, cause.label());
+                }
+            }
+            if let Some(webhook_config) = &config.webhook {
+                if let Err(e2) = webhook::record_failure(webhook_config, &e).await {
+                    error!("{}", e2);
+                }
+            }
+            return Err(e);
+        }
+    };
+    route::clear();
+    if config.webhook.is_some() {
+        if let Err(e) = webhook::record_success().await {
+            error!("{}", e);
+        }
+    }
+    info.environment = environment;
+    info.timestamp = Some(Local::now().timestamp());
+    info.backend.get_or_insert(config.backend);
+    info.network = get_connection(config).await.map(|c| c.name);
+    info.interface = test_interface(config);
+    info.labels = labels(config);
+    info.data_bytes = data_bytes;
+    if let Some(ip) = &info.user_ip {
+        let ip_info = ipinfo::IpInfo {
+            ip: ip.clone(),
+            city: info
+                .user_location
+                .as_ref()
+                .map(|l| l.split(',').next().unwrap_or(l).trim().to_string()),
+            isp: None,
+        };
+        if let Err(e) = ipinfo::save_reported(&ip_info).await {
+            error!("{}", e);
+        }
+    }
+    // A sink failing doesn't cost the bar the new measurement
+    sink::send_all(&sink::pipeline(config), &info).await;
+    if !config.interfaces.names.is_empty() {
+        interfaces::measure_others(&runner, config, &info).await;
+    }
+    Ok(info)
+}
+
+/// FNV-1a, stable across builds unlike the std hasher.
+fn checksum(data: &str) -> u64 {
+    data.bytes().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The buffer's TOML, failing only when the file is corrupt.
+fn check_buffer(contents: &str) -> Result<toml::Value, String> {
+    // Buffers written before the checksum start straight with the TOML
+    let contents = match contents.strip_prefix(CHECKSUM_PREFIX) {
+        Some(rest) => {
+            let (sum, body) = rest.split_once('\n').unwrap_or((rest, ""));
+            if u64::from_str_radix(sum.trim(), 16) != Ok(checksum(body)) {
+                return Err("Buffered file checksum mismatch".to_string());
+            }
+            body
+        }
+        None => contents,
+    };
+    match toml::from_str(contents) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!("Failed to parse TOML: {}", e)),
+    }
+}
+
+/// Reads a buffered measurement of any version up to `BUFFER_VERSION`.
+/// Unknown fields are ignored and missing optional ones take their default.
+fn parse_buffer(value: toml::Value) -> Result<Fast, String> {
+    // Buffers from before versioning have no version, but the same layout
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_integer())
+        .unwrap_or(0);
+    if version > BUFFER_VERSION.into() {
+        return Err(format!(
+            "Buffered file version {} is newer than {}",
+            version, BUFFER_VERSION
+        ));
+    }
+    match value.try_into() {
+        Ok(f) => Ok(f),
+        Err(e) => Err(format!("Incompatible buffered file: {}", e)),
+    }
+}
+
+/// The latest measurement in the configured store.
+fn get_buffered_internet_info(config: &Config) -> Result<Fast, String> {
+    store::open(config)?.latest()
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Fast {
+    #[serde(rename = "downloadSpeed")]
+    pub download_speed: u32,
+    #[serde(
+        rename = "uploadSpeed",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub upload_speed: Option<u32>,
+    pub latency: u32,
+    #[serde(rename = "userIp", default, skip_serializing_if = "Option::is_none")]
+    pub user_ip: Option<String>,
+    #[serde(
+        rename = "userLocation",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub user_location: Option<String>,
+    /// Unix time the measurement was taken at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+    /// The server the backend tested against, as it names it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(rename = "serverId", default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// NetworkManager connection the measurement was taken on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// Network interface the test went through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// `labels` of the machine that took it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Traffic on the interface while testing, with `[budget]`
+    #[serde(rename = "dataBytes", default, skip_serializing_if = "Option::is_none")]
+    pub data_bytes: Option<u64>,
+    /// Set when the measurement came from the buffered file
+    #[serde(skip)]
+    pub cached: bool,
+    /// Set when the buffer is out of date but the schedule holds tests back,
+    /// or the last test failed
+    #[serde(skip)]
+    pub stale: bool,
+    /// Set when several samples were combined
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<sampling::Spread>,
+    /// Every backend's result when racing them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<Fast>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<snapshot::Snapshot>,
+}
+
+/// The interface tests go through, the bound one or the default route's.
+fn test_interface(config: &Config) -> Option<String> {
+    match &config.interfaces.bind {
+        Some(i) => Some(i.clone()),
+        None => netdev::default_interface().ok(),
+    }
+}
+
+/// Why a new speed test shouldn't run now, if anything holds it back.
+fn hold_back_reason(
+    config: &Config,
+    battery_low: bool,
+    connection: Option<&nm::Connection>,
+) -> Option<&'static str> {
+    if !schedule::tests_allowed(&config.schedule, Local::now().time()) {
+        Some("tests aren't scheduled now")
+    } else if battery_low {
+        Some("the battery is low")
+    } else if config.nm.skip_metered && connection.is_some_and(|c| c.metered) {
+        Some("the connection is metered")
+    } else if budget::exceeded(&config.budget) {
+        Some("the test data budget is used up")
+    } else {
+        None
+    }
+}
+
+async fn get_connection(config: &Config) -> Option<nm::Connection> {
+    if !config.nm.enabled {
+        return None;
+    }
+    match nm::get_connection().await {
+        Ok(Some(mut c)) => {
+            if config.modem.enabled && config.modem.metered && c.kind == "wwan" {
+                c.metered = true;
+            }
+            Some(c)
+        }
+        Ok(None) => None,
         Err(e) => {
-            return Err(format!("{}", e));
+            error!("{}", e);
+            None
         }
+    }
+}
+
+fn buffer_ttl(config: &Config, battery_low: bool) -> u64 {
+    if battery_low {
+        config.battery.ttl_secs
+    } else {
+        config.cache_ttl_secs
+    }
+}
+
+/// Whether the stored measurement is older than the ttl that applies.
+fn is_stale(
+    config: &Config,
+    store: &dyn store::Store,
+    battery_low: bool,
+    clock: &dyn Clock,
+) -> Result<bool, String> {
+    Ok(store.age(clock)? > buffer_ttl(config, battery_low))
+}
+
+/// Returns the buffered measurement without ever testing, marked stale once
+/// out of date.
+async fn get_cached_info(config: &Config) -> Result<Fast, String> {
+    let store = store::open(config)?;
+    let battery_low = config.battery.enabled && battery::is_low(&config.battery);
+    let stale = is_stale(config, store.as_ref(), battery_low, &SystemClock)?;
+    let mut info = store.latest()?;
+    info.stale = stale;
+    Ok(info)
+}
+
+/// Whether to only probe the latency, for `--quick` or a used up `[budget]`.
+fn quick_only(config: &Config, quick: bool, force: bool) -> bool {
+    let budget = &config.budget;
+    quick || (!force && budget.when_exceeded == BudgetAction::Quick && budget::exceeded(budget))
+}
+
+/// The last full measurement with the latency `--quick` just probed, and
+/// its `{jitter}` and `{loss}`.
+async fn get_quick_info(config: &Config) -> Result<(Fast, HashMap<String, String>), String> {
+    let interface = config.interfaces.bind.as_deref();
+    let (info, quick) = tokio::join!(
+        get_cached_info(config),
+        ping::probe_quick(&config.quick, interface)
+    );
+    let mut info = info?;
+    info.latency = match quick.latency {
+        Some(l) => l,
+        None => return Err("None of the quick targets answered".to_string()),
     };
-    let path = match get_buffered_filename() {
-        Ok(p) => p,
+    let mut values = HashMap::new();
+    if let Some(j) = quick.jitter {
+        values.insert("jitter".to_string(), j.to_string());
+    }
+    values.insert("loss".to_string(), quick.loss.to_string());
+    Ok((info, values))
+}
+
+/// Returns the buffered measurement, running a new one when the buffer is
+/// missing, out of date or was taken on another network.
+async fn get_info(config: &Config, force: bool) -> Result<Fast, String> {
+    if force {
+        info!("Forced a new measurement");
+        return get_new_internet_info(config).await;
+    }
+    let store = store::open(config)?;
+    let battery_low = config.battery.enabled && battery::is_low(&config.battery);
+    let ttl = buffer_ttl(config, battery_low);
+    let connection = get_connection(config).await;
+    let hold_back = hold_back_reason(config, battery_low, connection.as_ref());
+    debug!("Buffer ttl: {}s, held back: {:?}", ttl, hold_back);
+    // Check if there's an up to date buffered file
+    let elapsed = match store.age(&SystemClock) {
+        Ok(e) => {
+            debug!("Buffered file age: {}s", e);
+            e
+        }
         Err(e) => {
-            return Err(format!("{}", e));
+            return match hold_back {
+                Some(reason) => Err(format!("Buffered file doesn't exist and {}: {}", reason, e)),
+                None => {
+                    info!("Buffered file doesn't exist");
+                    match get_new_internet_info(config).await {
+                        Ok(i) => Ok(i),
+                        Err(e2) => Err(format!(
+                            "File didn't exist: Error: {}. Tried to create it: Error: {}",
+                            e, e2
+                        )),
+                    }
+                }
+            };
         }
     };
-    match write_buffered_file(&path, &info) {
-        Ok(_) => (),
+    let mut info = match store.latest() {
+        Ok(i) => i,
         Err(e) => {
-            return Err(format!("{}", e));
+            return match hold_back {
+                Some(reason) => Err(format!("Buffered file is unreadable and {}: {}", reason, e)),
+                None => {
+                    info!("Buffered file is unreadable, replacing it");
+                    get_new_internet_info(config).await
+                }
+            };
         }
+    };
+    let other_network = on_other_network(config, connection.as_ref(), &info);
+    if elapsed <= ttl && !other_network {
+        info!("Using buffered file: elapse = {}", elapsed);
+        return Ok(info);
     }
-    Ok(info)
+    match hold_back {
+        Some(reason) => {
+            info!("Buffered file is out of date, but {}", reason);
+            info.stale = true;
+            Ok(info)
+        }
+        None if other_network => {
+            info!(
+                "Buffered file is from network '{}' on '{}'",
+                info.network.unwrap_or_default(),
+                info.interface.unwrap_or_default()
+            );
+            get_new_internet_info(config).await
+        }
+        None => {
+            info!("Buffered file is out of date");
+            match get_new_internet_info(config).await {
+                Ok(i) => Ok(i),
+                Err(e) if elapsed <= config.stale.max_age_secs => {
+                    error!("{}", e);
+                    info!("Falling back to the buffered file: elapse = {}", elapsed);
+                    info.stale = true;
+                    Ok(info)
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+/// Whether `info` was measured on another network than the current one.
+fn on_other_network(config: &Config, connection: Option<&nm::Connection>, info: &Fast) -> bool {
+    let other_network = match (connection, &info.network) {
+        (Some(c), Some(n)) => c.name != *n,
+        _ => false,
+    };
+    // A failover to another interface is a change of network too
+    let interfaces = &config.interfaces;
+    let other_interface = (interfaces.bind.is_some() || !interfaces.names.is_empty())
+        && info.interface.is_some()
+        && test_interface(config) != info.interface;
+    other_network || other_interface
 }
 
-fn get_buffered_internet_info() -> Result<Fast, String> {
-    let path = match get_buffered_filename() {
-        Ok(p) => p,
+/// What a new test would do, given `min_test_interval_secs`.
+fn would_test(config: &Config) -> String {
+    if let Some(last) = testlock::last_started() {
+        let since = Local::now().timestamp() - last;
+        if (0..config.min_test_interval_secs as i64).contains(&since) {
+            return format!("Would reuse the result of the test started {}s ago", since);
+        }
+    }
+    let backends = match &config.race {
+        Some(race) => {
+            let names: Vec<&str> = race.backends.iter().map(|b| b.name()).collect();
+            format!("race the {} backends", names.join(", "))
+        }
+        None => format!("run the {} backend", config.backend.name()),
+    };
+    match config.sampling.count {
+        0 | 1 => format!("Would {}", backends),
+        n => format!("Would {} {} times", backends, n),
+    }
+}
+
+/// The facts `get_info`, or the measure command with `measure`, goes by and
+/// what it would decide, last, without testing or writing anything.
+async fn dry_run(config: &Config, force: bool, measure: bool) -> Vec<String> {
+    if force {
+        return vec!["A new test is forced".to_string(), would_test(config)];
+    }
+    let mut lines = Vec::new();
+    let battery_low = config.battery.enabled && battery::is_low(&config.battery);
+    let connection = get_connection(config).await;
+    let hold_back = hold_back_reason(config, battery_low, connection.as_ref());
+    if let Some(reason) = hold_back {
+        lines.push(format!("Tests are held back, {}", reason));
+    }
+    if measure {
+        lines.push(match hold_back {
+            Some(_) => "Would skip the measurement".to_string(),
+            None => would_test(config),
+        });
+        return lines;
+    }
+    let ttl = buffer_ttl(config, battery_low);
+    let stored = store::open(config).and_then(|s| Ok((s.age(&SystemClock)?, s.latest()?)));
+    let (age, info) = match stored {
+        Ok(s) => s,
         Err(e) => {
-            return Err(format!("{}", e));
+            lines.push(format!("No usable stored measurement: {}", e));
+            lines.push(match hold_back {
+                Some(_) => "Would fail, showing nothing".to_string(),
+                None => would_test(config),
+            });
+            return lines;
         }
     };
-    let file_contents = match fs::read_to_string(&path) {
-        Ok(f) => f,
+    lines.push(format!(
+        "Stored measurement is {} old, ttl {}",
+        format_age(age),
+        format_age(ttl)
+    ));
+    let other_network = on_other_network(config, connection.as_ref(), &info);
+    if other_network {
+        lines.push(format!(
+            "It was taken on network '{}' on '{}'",
+            info.network.unwrap_or_default(),
+            info.interface.unwrap_or_default()
+        ));
+    }
+    lines.push(if age <= ttl && !other_network {
+        format!(
+            "Would reuse the stored measurement (age {})",
+            format_age(age)
+        )
+    } else if hold_back.is_some() {
+        format!(
+            "Would show the out of date measurement (age {})",
+            format_age(age)
+        )
+    } else {
+        would_test(config)
+    });
+    lines
+}
+
+/// Formats an age in its largest whole unit, e.g. `45m`, `23h` or `3d`.
+fn format_age(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+/// Program the backend runs with a hint on installing it, `None` for the
+/// built-in one.
+fn backend_program(config: &Config) -> Option<(PathBuf, &'static str)> {
+    match config.backend {
+        Backend::Fast => Some((
+            config.fast.path.clone(),
+            "install it with `npm install --global fast-cli` or set fast.path",
+        )),
+        Backend::Native | Backend::Mock => None,
+        Backend::Speedtest => Some((
+            PathBuf::from("speedtest"),
+            "install Ookla's CLI from https://www.speedtest.net/apps/cli",
+        )),
+        Backend::Exec => config
+            .exec
+            .as_ref()
+            .and_then(|e| e.command.first())
+            .map(|c| (PathBuf::from(c), "check exec.command")),
+        Backend::Iperf3 => Some((PathBuf::from("iperf3"), "install the iperf3 package")),
+    }
+}
+
+/// Whether `program` exists, searching `PATH` for bare names.
+fn is_installed(program: &Path) -> bool {
+    if program.components().count() > 1 {
+        return program.is_file();
+    }
+    let paths = match env::var_os("PATH") {
+        Some(p) => p,
+        None => return false,
+    };
+    env::split_paths(&paths).any(|d| {
+        let file = d.join(program);
+        file.is_file() || (cfg!(windows) && file.with_extension("exe").is_file())
+    })
+}
+
+fn bar_markup(output: OutputFormat) -> Option<Markup> {
+    match output {
+        OutputFormat::Polybar => Some(Markup::Polybar),
+        OutputFormat::Lemonbar => Some(Markup::Lemonbar),
+        OutputFormat::Dzen2 => Some(Markup::Dzen2),
+        OutputFormat::Xmobar => Some(Markup::Xmobar),
+        OutputFormat::Tmux => Some(Markup::Tmux),
+        OutputFormat::Pango | OutputFormat::Swaybar | OutputFormat::Waybar => Some(Markup::Pango),
+        OutputFormat::Argos => Some(Markup::Argos),
+        OutputFormat::Json | OutputFormat::Jsonl | OutputFormat::PromTextfile => None,
+    }
+}
+
+/// When a measurement failed because the backend's program isn't installed,
+/// logs how to install it and returns a line telling the bar so.
+fn render_missing_backend(config: &Config) -> Option<String> {
+    let (program, hint) = backend_program(config)?;
+    if is_installed(&program) {
+        return None;
+    }
+    error!("'{}' isn't installed, {}", program.display(), hint);
+    let markup = bar_markup(config.output)?;
+    let icon = icons::icon_set(&config.icons).missing;
+    let line = format!(
+        "{} {} missing",
+        markup.colored(threshold::Level::Bad.color(&config.theme), &icon),
+        program.display()
+    );
+    Some(output::wrap_line(config, &line))
+}
+
+/// The line shown when the route died, saying where.
+fn render_offline(config: &Config) -> Option<String> {
+    if !config.route.enabled {
+        return None;
+    }
+    let diagnosis = route::load()?;
+    let markup = bar_markup(config.output)?;
+    let icon = icons::icon_set(&config.icons).bad;
+    let line = format!(
+        "{} {}: {}",
+        markup.colored(threshold::Level::Bad.color(&config.theme), &icon),
+        locale::word(&config.locale, "offline"),
+        diagnosis.cause.label()
+    );
+    Some(output::wrap_line(config, &line))
+}
+
+/// A frame of the spinner shown while a test has been running for `elapsed`.
+fn render_progress(config: &Config, frame: usize, elapsed: Duration) -> Option<String> {
+    bar_markup(config.output)?;
+    let progress = &config.progress;
+    let mut values = HashMap::new();
+    if !progress.spinner.is_empty() {
+        let glyph = &progress.spinner[frame % progress.spinner.len()];
+        values.insert("spinner".to_string(), glyph.clone());
+    }
+    values.insert(
+        "testing".to_string(),
+        locale::word(&config.locale, "testing"),
+    );
+    values.insert("elapsed".to_string(), elapsed.as_secs().to_string());
+    let line = format::render(&progress.format, &values);
+    Some(output::wrap_line(config, &line))
+}
+
+/// Awaits `test`, printing a spinner frame whenever it is still running
+/// after another `frame_ms`, so the bar doesn't sit on the previous value.
+async fn with_progress<T>(config: &Config, swaybar: bool, test: impl Future<Output = T>) -> T {
+    if !config.progress.enabled {
+        return test.await;
+    }
+    let every = Duration::from_millis(config.progress.frame_ms.max(100));
+    let start = Instant::now();
+    let mut frames = time::interval_at(time::Instant::now() + every, every);
+    tokio::pin!(test);
+    let mut frame = 0;
+    loop {
+        tokio::select! {
+            result = &mut test => return result,
+            _ = frames.tick() => {
+                if let Some(line) = render_progress(config, frame, start.elapsed()) {
+                    println!("{}{}", line, if swaybar { "," } else { "" });
+                }
+                frame += 1;
+            }
+        }
+    }
+}
+
+/// The measurement's detail, looking up what the backend didn't report.
+async fn render_detail(config: &Config, info: &Fast) -> String {
+    let ip_info = match ipinfo::get_ip_info(&config.ipinfo).await {
+        Ok(i) => Some(i),
         Err(e) => {
-            return Err(format!("Failed to read file: {}", e));
+            error!("{}", e);
+            None
         }
     };
-    let info = match toml::from_str(&file_contents) {
-        Ok(f) => f,
+    output::render_detail(info, ip_info.as_ref())
+}
+
+/// Renders `info` in the configured output. `live` runs the probes behind
+/// placeholders such as `{dns}`, otherwise only what's cached is shown.
+async fn render_output(
+    config: &Config,
+    info: &Fast,
+    values: HashMap<String, String>,
+    live: bool,
+) -> String {
+    if bar_markup(config.output).is_some() && quiet::is_quiet(&config.quiet) {
+        return output::wrap_line(config, &config.quiet.output);
+    }
+    match config.output {
+        OutputFormat::Polybar => render_bar(config, info, values, live, Markup::Polybar).await,
+        OutputFormat::Lemonbar => render_bar(config, info, values, live, Markup::Lemonbar).await,
+        OutputFormat::Dzen2 => render_bar(config, info, values, live, Markup::Dzen2).await,
+        OutputFormat::Xmobar => render_bar(config, info, values, live, Markup::Xmobar).await,
+        OutputFormat::Tmux => render_bar(config, info, values, live, Markup::Tmux).await,
+        OutputFormat::Pango => render_bar(config, info, values, live, Markup::Pango).await,
+        OutputFormat::Swaybar => {
+            output::render_swaybar(&render_bar(config, info, values, live, Markup::Pango).await)
+        }
+        OutputFormat::Waybar => {
+            let line = render_bar(config, info, values, live, Markup::Pango).await;
+            output::render_waybar(&line, &render_detail(config, info).await, info.stale)
+        }
+        OutputFormat::Argos => {
+            let line = render_bar(config, info, values, live, Markup::Argos).await;
+            let recent = match store::open(config).and_then(|s| s.history(None)) {
+                Ok(entries) => {
+                    let skip = entries.len().saturating_sub(ARGOS_HISTORY);
+                    entries[skip..].to_vec()
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    Vec::new()
+                }
+            };
+            let exe = env::current_exe()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|_| "rusting".to_string());
+            output::render_argos(&line, info, &recent, &exe)
+        }
+        OutputFormat::Json => output::render_json(info, true),
+        OutputFormat::Jsonl => output::render_json(info, false),
+        OutputFormat::PromTextfile => {
+            if let Err(e) = write_textfile(config, info) {
+                error!("{}", e);
+            }
+            String::new()
+        }
+    }
+}
+
+/// Writes `info` to the `prom_textfile` instead of printing it.
+fn write_textfile(config: &Config, info: &Fast) -> Result<(), String> {
+    let path = match &config.prom_textfile {
+        Some(p) => p,
+        None => return Err("prom-textfile output needs --textfile or prom_textfile".to_string()),
+    };
+    let age = match info.timestamp {
+        Some(t) => (Local::now().timestamp() - t).max(0) as u64,
+        None => 0,
+    };
+    output::write_prom_textfile(path, info, age)
+}
+
+/// Awaits `f` only when `enabled`.
+async fn optional<F: Future>(enabled: bool, f: F) -> Option<F::Output> {
+    if enabled {
+        Some(f.await)
+    } else {
+        None
+    }
+}
+
+async fn render_bar(
+    config: &Config,
+    info: &Fast,
+    mut values: HashMap<String, String>,
+    live: bool,
+    markup: Markup,
+) -> String {
+    let compact = markup == Markup::Tmux;
+    let mut template = match cycle::format(&config.cycle).or_else(|| config.format.clone()) {
+        Some(f) => f,
+        None if compact && info.upload_speed.is_some() => format::COMPACT_FORMAT_UPLOAD.to_string(),
+        None if compact => format::COMPACT_FORMAT.to_string(),
+        None if info.upload_speed.is_some() => format::DEFAULT_FORMAT_UPLOAD.to_string(),
+        None => format::DEFAULT_FORMAT.to_string(),
+    };
+    if config.show_ip {
+        template.push_str(" {ip}");
+    }
+
+    // Only look the ip up when something is going to use it
+    let needs_ip = config.vpn.expected_isp.is_some()
+        || ["{ip}", "{isp}", "{city}"]
+            .iter()
+            .any(|p| template.contains(p));
+    let ip_lookup = async {
+        if live {
+            ipinfo::get_ip_info(&config.ipinfo).await
+        } else {
+            ipinfo::get_cached_ip_info().await
+        }
+    };
+    let connection_lookup = async {
+        if live {
+            get_connection(config).await
+        } else {
+            None
+        }
+    };
+    // The probes are independent, run them all at once
+    let (
+        ip_info,
+        ping_results,
+        dns_result,
+        families,
+        legs,
+        work,
+        health,
+        modem_result,
+        endpoint_statuses,
+        connection,
+    ) = tokio::join!(
+        optional(needs_ip, ip_lookup),
+        optional(
+            live && config.ping.enabled,
+            ping::probe_targets(&config.ping, config.interfaces.bind.as_deref())
+        ),
+        optional(live && config.dns.enabled, dns::probe(&config.dns)),
+        optional(
+            live && config.dualstack.enabled,
+            ping::probe_families(&config.dualstack)
+        ),
+        optional(live && config.legs.enabled, ping::probe_legs(&config.legs)),
+        optional(
+            live && config.work.enabled,
+            ping::probe(
+                &config.work.target,
+                config.work.count,
+                Duration::from_millis(config.work.timeout_ms)
+            )
+        ),
+        optional(live && config.health.enabled, health::score(&config.health)),
+        optional(live && config.modem.enabled, modem::get_modem()),
+        optional(
+            live && config.endpoints.enabled,
+            endpoints::check_all(&config.endpoints)
+        ),
+        connection_lookup,
+    );
+
+    let mut isp = None;
+    if let Some(ip_info) = ip_info {
+        match ip_info {
+            Ok(i) => {
+                values.insert("ip".to_string(), i.ip);
+                values.insert("city".to_string(), i.city.unwrap_or_default());
+                isp = i.isp;
+                values.insert("isp".to_string(), isp.clone().unwrap_or_default());
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+    let vpn_active = config.vpn.enabled && vpn::is_vpn_active(&config.vpn, isp.as_deref());
+
+    let down_pct = config
+        .plan
+        .download
+        .and_then(|d| threshold::plan_percent(info.download_speed, d));
+    let up_pct = match (config.plan.upload, info.upload_speed) {
+        (Some(plan), Some(up)) => threshold::plan_percent(up, plan),
+        _ => None,
+    };
+    let mut latency = info.latency;
+    if let Some(results) = ping_results {
+        for r in &results {
+            let value = match r.latency {
+                Some(l) => l.to_string(),
+                None => "-".to_string(),
+            };
+            values.insert(format!("ping_{}", r.name), value);
+        }
+        match ping::aggregate(&results, config.ping.aggregate) {
+            Some(l) => latency = l,
+            None => error!("None of the ping targets answered"),
+        }
+    }
+    let prev_level = if config.hysteresis.enabled {
+        threshold::load_state()
+    } else {
+        None
+    };
+    let colors = if config.colors.adaptive {
+        match store::open(config) {
+            Ok(s) => threshold::adapt(&config.colors, s.as_ref(), info.network.as_deref()),
+            Err(e) => {
+                warn!("Keeping the configured thresholds, {}", e);
+                config.colors.clone()
+            }
+        }
+    } else {
+        config.colors.clone()
+    };
+    let latency_level = match prev_level {
+        Some(p) => {
+            threshold::latency_level_from(latency, Some(p.level), config.hysteresis.margin, &colors)
+        }
+        None => threshold::latency_level(latency, &colors),
+    };
+    let mut level = threshold::combine(
+        &colors,
+        latency_level,
+        info.download_speed,
+        info.upload_speed,
+    );
+    if config.plan.color {
+        for pct in [down_pct, up_pct].into_iter().flatten() {
+            level = level.max(threshold::percent_level(pct, &config.plan));
+        }
+    }
+    if let Some(dns_result) = dns_result {
+        match dns_result {
+            Ok(ms) => {
+                values.insert("dns".to_string(), ms.to_string());
+                if ms > config.dns.max_latency {
+                    level = level.max(threshold::Level::Warn);
+                }
+            }
+            Err(e) => {
+                error!("{}", e);
+                values.insert("dns".to_string(), "-".to_string());
+                level = threshold::Level::Bad;
+            }
+        }
+    }
+    if let Some(score) = health.flatten() {
+        values.insert("health".to_string(), score.to_string());
+        level = level.max(health::level(&config.health, score));
+    }
+    if let Some(statuses) = endpoint_statuses {
+        let down: Vec<&str> = statuses
+            .iter()
+            .filter(|s| !s.up)
+            .map(|s| s.name.as_str())
+            .collect();
+        let up = statuses.len() - down.len();
+        values.insert(
+            "endpoints".to_string(),
+            format!("{}/{}", up, statuses.len()),
+        );
+        values.insert("endpoints_down".to_string(), down.join(","));
+        if !down.is_empty() {
+            level = threshold::Level::Bad;
+        }
+    }
+    if config.latency.enabled {
+        if let Some(windows) = latency::load() {
+            values.extend(latency::placeholders(&windows.combined()));
+        }
+    }
+    if let Some(families) = families {
+        let mark = |l: Option<u32>| if l.is_some() { "\u{2714}" } else { "\u{2718}" };
+        let v4 = format!("v4{}", mark(families.ipv4));
+        let v6 = format!("v6{}", mark(families.ipv6));
+        values.insert("families".to_string(), format!("{} {}", v4, v6));
+        values.insert("ipv4".to_string(), v4);
+        values.insert("ipv6".to_string(), v6);
+        let show = |l: Option<u32>| l.map(|l| l.to_string()).unwrap_or("-".to_string());
+        values.insert("ipv4_latency".to_string(), show(families.ipv4));
+        values.insert("ipv6_latency".to_string(), show(families.ipv6));
+    }
+    if config.hysteresis.enabled {
+        level = threshold::settle(&config.hysteresis, prev_level, level);
+    }
+    let mut color = level.color(&config.theme);
+    if let Some(legs) = legs {
+        let show = |l: Option<u32>| match l {
+            Some(l) => format!("{}ms", l),
+            None => "-".to_string(),
+        };
+        values.insert("gateway_latency".to_string(), show(legs.gateway));
+        values.insert("internet_latency".to_string(), show(legs.internet));
+        values.insert(
+            "legs".to_string(),
+            format!("{}/{}", show(legs.gateway), show(legs.internet)),
+        );
+        // A bad local leg means Wi-Fi or LAN trouble rather than the ISP
+        if legs.gateway_is_bad(&config.legs) {
+            color = config.legs.gateway_color.as_str();
+        }
+    }
+    if let Some(result) = work {
+        let latency = match result {
+            Ok(l) => Some(l),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        };
+        let level = threshold::work_level(latency, &config.work);
+        let mut work_values = HashMap::new();
+        work_values.insert(
+            "icon".to_string(),
+            markup.colored(level.color(&config.theme), &config.work.icon),
+        );
+        work_values.insert(
+            "latency".to_string(),
+            latency.map_or("-".to_string(), |l| l.to_string()),
+        );
+        values.insert(
+            "work".to_string(),
+            format::render(&config.work.format, &work_values),
+        );
+    }
+    if let (true, Some(c)) = (info.stale, &config.stale.color) {
+        color = c.as_str();
+    }
+    let age = info
+        .timestamp
+        .map(|t| (Local::now().timestamp() - t).max(0) as u64);
+    if let Some(t) = info.timestamp {
+        if let Some(taken) = Local.timestamp_opt(t, 0).single() {
+            values.insert("taken".to_string(), taken.format("%H:%M").to_string());
+        }
+    }
+    if let Some(a) = age {
+        values.insert("age".to_string(), format!("{} ago", format_age(a)));
+    }
+    // Old measurements color the whole module, however good they were
+    let old = config
+        .age
+        .max_secs
+        .is_some_and(|max| age.is_some_and(|a| a > max));
+    if old {
+        color = config.age.color.as_str();
+    }
+    let icons = icons::icon_set(&config.icons);
+    // Speeds behind a VPN aren't comparable, make that obvious
+    let glyph = if vpn_active {
+        icons.vpn.as_str()
+    } else {
+        icons.for_level(level)
+    };
+    let icon = markup.colored(color, glyph);
+
+    values.insert("icon".to_string(), icon);
+    if vpn_active {
+        values.insert("vpn".to_string(), icons.vpn.clone());
+    }
+    values.insert("down_icon".to_string(), icons.down.clone());
+    values.insert("up_icon".to_string(), icons.up.clone());
+    values.insert("latency".to_string(), latency.to_string());
+    values.insert("download".to_string(), info.download_speed.to_string());
+    if let Some(up) = info.upload_speed {
+        values.insert("upload".to_string(), up.to_string());
+    }
+    values.insert("unit".to_string(), config.locale.speed_unit.clone());
+    if let Some(c) = connection {
+        values.insert("connection".to_string(), c.name);
+        values.insert("connection_type".to_string(), c.kind);
+        if c.metered {
+            let marker = locale::word(&config.locale, &config.nm.metered_marker);
+            values.insert("metered".to_string(), marker);
+        }
+    } else if let (false, Some(n)) = (live, &info.network) {
+        // The connection the measurement was taken on
+        values.insert("connection".to_string(), n.clone());
+    }
+    if info.stale {
+        let mut marker = config.schedule.stale_marker.clone();
+        if let (true, Some(t)) = (config.stale.show_age, info.timestamp) {
+            let age = (Local::now().timestamp() - t).max(0) as u64;
+            marker.push_str(&format!(" ({})", format_age(age)));
+        }
+        values.insert("stale".to_string(), marker);
+    }
+    if let Some(b) = info.backend {
+        values.insert("backend".to_string(), b.name().to_string());
+    }
+    if let Some(s) = &info.server {
+        values.insert("server".to_string(), s.clone());
+    }
+    if let Some(s) = &info.server_id {
+        values.insert("server_id".to_string(), s.clone());
+    }
+    if let Some(i) = &info.interface {
+        values.insert("interface".to_string(), i.clone());
+    }
+    if !config.interfaces.names.is_empty() {
+        let active = netdev::default_interface().ok();
+        let summary = interfaces::render_summary(&config.interfaces, active.as_deref());
+        values.insert("interfaces".to_string(), summary);
+    }
+    if let Some(spread) = info.spread {
+        values.insert(
+            "download_spread".to_string(),
+            format!("\u{b1}{}", spread.download),
+        );
+        values.insert(
+            "latency_spread".to_string(),
+            format!("\u{b1}{}", spread.latency),
+        );
+        if let Some(up) = spread.upload {
+            values.insert("upload_spread".to_string(), format!("\u{b1}{}", up));
+        }
+    }
+    if let Some(p) = down_pct {
+        values.insert("down_pct".to_string(), format!("{}%", p));
+    }
+    if let Some(p) = up_pct {
+        values.insert("up_pct".to_string(), format!("{}%", p));
+    }
+    if live && config.wifi.enabled {
+        match wifi::get_wifi_info(config.wifi.interface.as_deref()) {
+            Ok(w) => {
+                values.insert("ssid".to_string(), w.ssid.unwrap_or_default());
+                values.insert("signal".to_string(), w.signal.to_string());
+                values.insert("signal_dbm".to_string(), w.level.to_string());
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+    if let Some(modem) = modem_result {
+        match modem {
+            Ok(Some(m)) => {
+                values.insert("modem_signal".to_string(), m.signal.to_string());
+                values.insert("modem_tech".to_string(), m.technology);
+                values.insert("operator".to_string(), m.operator.unwrap_or_default());
+            }
+            Ok(None) => (),
+            Err(e) => error!("{}", e),
+        }
+    }
+    if config.budget.enabled {
+        let used = usage::format_bytes(budget::used());
+        values.insert("test_data".to_string(), used);
+    }
+    if live && config.usage.enabled {
+        match usage::update(&config.usage).await {
+            Ok(u) => {
+                let total = usage::format_bytes(u.total());
+                let total = match u.level(&config.usage) {
+                    Some(l) if l != threshold::Level::Good => {
+                        markup.colored(l.color(&config.theme), &total)
+                    }
+                    _ => total,
+                };
+                values.insert("usage".to_string(), total);
+                values.insert("usage_rx".to_string(), usage::format_bytes(u.rx_bytes));
+                values.insert("usage_tx".to_string(), usage::format_bytes(u.tx_bytes));
+                if let Some(t) = u.today_bytes {
+                    values.insert("usage_today".to_string(), usage::format_bytes(t));
+                }
+                if let Some(p) = u.percent(&config.usage) {
+                    values.insert("usage_pct".to_string(), format!("{}%", p));
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+    if template.contains("{link}") {
+        match netdev::resolve_interface(config.netdev.interface.as_deref()) {
+            Ok(iface) => {
+                if let Some(speed) = netdev::link_speed(&iface) {
+                    let link = netdev::format_link_speed(speed);
+                    values.insert("link".to_string(), locale::number(&config.locale, &link));
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    let mut line = format::render(&template, &values);
+    if config
+        .max_width
+        .is_some_and(|w| markup.visible_width(&line) > w)
+    {
+        line = format::abbreviate_units(&line, &config.locale.speed_unit);
+    }
+    if old {
+        line = markup.colored_all(&config.age.color, &line);
+    }
+    for (button, command) in click_actions(config) {
+        line = markup.clickable(button, &command, &line);
+    }
+    line
+}
+
+/// Mouse button and command of each configured click action.
+fn click_actions(config: &Config) -> Vec<(u8, String)> {
+    [
+        (1, &config.click),
+        (2, &config.middle_click),
+        (3, &config.right_click),
+        (4, &config.scroll),
+    ]
+    .into_iter()
+    .filter_map(|(button, command)| Some((button, command.clone()?)))
+    .filter(|(_, c)| !c.is_empty())
+    .collect()
+}
+
+/// Runs `action` each time the process gets the signal `kind`.
+#[cfg(unix)]
+fn on_signal(
+    kind: signal::unix::SignalKind,
+    name: &'static str,
+    action: impl Fn() -> Result<(), String> + Send + 'static,
+) {
+    let mut signals = match signal::unix::signal(kind) {
+        Ok(s) => s,
         Err(e) => {
-            error!("Failed to parse TOML: {}", e);
-            return Err(format!("{}", e));
+            warn!("Failed to listen for {}: {}", name, e);
+            return;
         }
     };
-    Ok(info)
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            if let Err(e) = action() {
+                error!("{}", e);
+            }
+        }
+    });
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Fast {
-    downloadSpeed: u32,
-    latency: u32,
-}
-
-fn main() {
-    let logfile = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M:%S)} [{t} {l} {M}:{L}] - {m}{n}",
-        )))
-        .build("/tmp/polybar-internet-speed.log")
-        .unwrap();
-    let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .build(
-            Root::builder()
-                .appender("logfile")
-                .build(log::LevelFilter::Info),
-        )
-        .unwrap();
-    let _handle = log4rs::init_config(config).unwrap();
-    let path = match get_buffered_filename() {
-        Ok(p) => p,
+/// Prints the bar output every `interval` seconds, including the live
+/// interface throughput. The speed test itself refreshes when the buffered
+/// file goes out of date, or shortly after the network changes.
+async fn run_daemon(
+    mut config: Config,
+    interval: Option<u64>,
+    quick: bool,
+    profile: Option<String>,
+    overrides: reload::Overrides,
+) {
+    let iface = match netdev::resolve_interface(config.netdev.interface.as_deref()) {
+        Ok(i) => i,
         Err(e) => {
             error!("{}", e);
             return;
         }
     };
-    // Check if there's an up to date buffered file
-    let info = match get_seconds_since_file_modified(&path) {
-        Ok(elapsed) => match elapsed {
-            0..=86400 => {
-                info!("Using buffered file: elapse = {}", elapsed);
-                let info = match get_buffered_internet_info() {
-                    Ok(f) => f,
-                    Err(e) => {
-                        error!("{}", e);
-                        return;
+    let mut sampler = netdev::Sampler::new(&iface);
+    let mut watcher = match reload::ConfigWatcher::new(profile, overrides) {
+        Ok(w) => Some(w),
+        Err(e) => {
+            warn!("Config changes need a restart, {}", e);
+            None
+        }
+    };
+    let mut network = netdev::NetworkWatch::new().await;
+    let mut changed_at: Option<Instant> = None;
+    let mut outages = outage::Tracker::new();
+    let mut latencies = latency::Recorder::new();
+    // Wall clock time of the last tick and when it was, by the monotonic
+    // clock, which stands still while the machine is suspended
+    let mut ticked = (Local::now().timestamp(), Instant::now());
+    let mut resumed_at: Option<Instant> = None;
+    let mut measured_at = match get_buffered_internet_info(&config) {
+        Ok(i) => i.timestamp,
+        Err(_) => None,
+    };
+    // The quiet hours SIGUSR1 toggles against, kept up to date on reloads
+    let quiet_config = Arc::new(Mutex::new(config.quiet.clone()));
+    #[cfg(unix)]
+    {
+        use signal::unix::SignalKind;
+        let quiet_config = quiet_config.clone();
+        on_signal(SignalKind::user_defined1(), "SIGUSR1", move || {
+            let quiet_config = match quiet_config.lock() {
+                Ok(c) => c.clone(),
+                Err(e) => return Err(format!("Failed to read the quiet hours: {}", e)),
+            };
+            quiet::toggle(&quiet_config).map(|_| ())
+        });
+        on_signal(SignalKind::user_defined2(), "SIGUSR2", cycle::advance);
+    }
+    // The swaybar protocol is one endless JSON array after a header
+    let swaybar = config.output == OutputFormat::Swaybar;
+    if swaybar {
+        println!("{{\"version\":1}}");
+        println!("[");
+    }
+    loop {
+        if let Some(new) = watcher.as_mut().and_then(|w| w.try_changed()) {
+            if new.netdev.interface != config.netdev.interface {
+                match netdev::resolve_interface(new.netdev.interface.as_deref()) {
+                    Ok(i) => sampler = netdev::Sampler::new(&i),
+                    Err(e) => error!("{}", e),
+                }
+            }
+            if let Ok(mut q) = quiet_config.lock() {
+                *q = new.quiet.clone();
+            }
+            config = new;
+        }
+        let config = &config;
+        let tick = async {
+            let slept = Local::now().timestamp() - ticked.0 - ticked.1.elapsed().as_secs() as i64;
+            ticked = (Local::now().timestamp(), Instant::now());
+            if slept > config.daemon.resume_jump_secs as i64 {
+                info!("Resumed after about {} suspended", format_age(slept as u64));
+                if config.daemon.revalidate_on_resume {
+                    resumed_at = Some(Instant::now());
+                }
+            }
+            let settle = Duration::from_secs(config.daemon.network_settle_secs);
+            if resumed_at.is_some_and(|t| t.elapsed() >= settle) {
+                match netdev::default_interface() {
+                    Ok(i) => {
+                        info!("Back online through '{}' after the resume", i);
+                        resumed_at = None;
                     }
-                };
-                info
-            }
-            _ => {
-                info!("Buffered file is out of date");
-                let info = match get_new_internet_info() {
-                    Ok(f) => f,
-                    Err(e) => {
-                        error!("{}", e);
-                        return;
+                    Err(e) => debug!("Not online yet after the resume: {}", e),
+                }
+            }
+            let resuming = resumed_at.is_some();
+            if config.daemon.retest_on_network_change {
+                if let Some(previous) = network.changed().await {
+                    info!("Network changed from '{}' to '{}'", previous, network.id);
+                    changed_at = Some(Instant::now());
+                }
+                if !resuming && changed_at.is_some_and(|t| t.elapsed() >= settle) {
+                    changed_at = None;
+                    let battery_low = config.battery.enabled && battery::is_low(&config.battery);
+                    let connection = get_connection(config).await;
+                    match hold_back_reason(config, battery_low, connection.as_ref()) {
+                        Some(reason) => info!("Not measuring the new network, {}", reason),
+                        None => {
+                            let test = get_new_internet_info(config);
+                            if let Err(e) = with_progress(config, swaybar, test).await {
+                                error!("{}", e);
+                            }
+                        }
                     }
-                };
-                info
+                }
             }
-        },
-        Err(e) => {
-            info!("Buffered file doesn't exist");
-            let info = match get_new_internet_info() {
-                Ok(i) => i,
-                Err(e2) => {
-                    error!(
-                        "File didn't exist: Error: {}. Tried to create it: Error: {}",
-                        e, e2
-                    );
-                    return;
+            // Reconnecting after a resume isn't an outage
+            if config.outage.enabled && !resuming {
+                outages.check(&config.outage, &config.route).await;
+            }
+            if config.latency.enabled {
+                latencies.check(&config.latency).await;
+            }
+            let mut values = HashMap::new();
+            match sampler.sample() {
+                Ok(Some(r)) => {
+                    let rx = format!("{:.1}", r.rx_mbps);
+                    let tx = format!("{:.1}", r.tx_mbps);
+                    values.insert("rx".to_string(), locale::number(&config.locale, &rx));
+                    values.insert("tx".to_string(), locale::number(&config.locale, &tx));
                 }
+                Ok(None) => (),
+                Err(e) => error!("{}", e),
+            }
+            let result = if resuming {
+                get_cached_info(config).await
+            } else if quick_only(config, quick, false) {
+                get_quick_info(config).await.map(|(info, quick_values)| {
+                    values.extend(quick_values);
+                    info
+                })
+            } else {
+                with_progress(config, swaybar, get_info(config, false)).await
             };
-            info
+            match result {
+                Ok(info) => {
+                    let line = render_output(config, &info, values, true).await;
+                    println!("{}{}", line, if swaybar { "," } else { "" });
+                    if info.timestamp != measured_at {
+                        measured_at = info.timestamp;
+                        if config.polybar_ipc.enabled {
+                            if let Err(e) = polybar::send_hook(&config.polybar_ipc).await {
+                                error!("{}", e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    if let Some(line) =
+                        render_missing_backend(config).or_else(|| render_offline(config))
+                    {
+                        println!("{}{}", line, if swaybar { "," } else { "" });
+                    }
+                }
+            }
+            let secs = interval.unwrap_or(config.daemon.interval_secs);
+            time::sleep(Duration::from_secs(secs)).await;
+        };
+        // Interrupting drops a test still in progress, killing its process
+        tokio::select! {
+            _ = tick => (),
+            _ = signal::ctrl_c() => {
+                info!("Interrupted, shutting down");
+                return;
+            }
+        }
+    }
+}
+
+async fn run_bandwidth(config: &Config, interface: Option<&str>, interval: u64) {
+    let iface = match netdev::resolve_interface(interface.or(config.netdev.interface.as_deref())) {
+        Ok(i) => i,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let icons = icons::icon_set(&config.icons);
+    let mut sampler = netdev::Sampler::new(&iface);
+    loop {
+        match sampler.sample() {
+            Ok(Some(r)) => println!(
+                "{} {} {} {} {} {} {}",
+                iface,
+                icons.down,
+                locale::number(&config.locale, &format!("{:.1}", r.rx_mbps)),
+                config.locale.speed_unit,
+                icons.up,
+                locale::number(&config.locale, &format!("{:.1}", r.tx_mbps)),
+                config.locale.speed_unit
+            ),
+            Ok(None) => (),
+            Err(e) => {
+                error!("{}", e);
+                eprintln!("{}", e);
+                return;
+            }
+        }
+        time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Logs a failed sd_notify, which shouldn't fail the measurement.
+fn sd_notify(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        error!("{}", e);
+    }
+}
+
+/// Measures once, leaving the output to the buffer and history, reporting the
+/// progress to systemd when started by it. Returns the exit code.
+async fn run_measure(config: &Config, force: bool) -> i32 {
+    sd_notify("READY=1");
+    if !force {
+        let battery_low = config.battery.enabled && battery::is_low(&config.battery);
+        let connection = get_connection(config).await;
+        if let Some(reason) = hold_back_reason(config, battery_low, connection.as_ref()) {
+            info!("Skipping the measurement, {}", reason);
+            sd_notify(&format!("STATUS=Skipped, {}", reason));
+            return 0;
+        }
+    }
+    sd_notify("STATUS=Measuring");
+    match get_new_internet_info(config).await {
+        Ok(info) => {
+            let status = format!("STATUS=Measured {} Mbps", info.download_speed);
+            info!("{}", status);
+            sd_notify(&status);
+            0
         }
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            sd_notify(&format!("STATUS=Failed: {}", e));
+            1
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(about = "Internet speed module for polybar")]
+struct Cli {
+    /// Apply the settings of `[profile.<name>]` from the config file
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Append the public ip to the output
+    #[arg(long, global = true)]
+    ip: bool,
+    /// Output format, overrides the config file
+    #[arg(long, global = true, value_enum)]
+    output: Option<OutputFormat>,
+    /// Also log to stderr, repeat for more detail
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Print every step to stdout, to see why the module shows nothing
+    #[arg(long, global = true)]
+    debug: bool,
+    /// Log only here, overrides the config file
+    #[arg(long, global = true, value_enum)]
+    log_to: Option<LogKind>,
+    /// Level of every log target, overrides the config file
+    #[arg(long, global = true, value_enum)]
+    log_level: Option<LogLevel>,
+    /// Abbreviate the units when the line is wider, overrides the config file
+    #[arg(long, global = true)]
+    max_width: Option<usize>,
+    /// File written by --output prom-textfile
+    #[arg(long, global = true)]
+    textfile: Option<PathBuf>,
+    /// Run a new speed test now, whatever the buffer, schedule or battery
+    #[arg(long, global = true)]
+    force: bool,
+    /// Probe only the latency of the `[quick]` targets, showing it with the
+    /// speeds of the last full test
+    #[arg(long, global = true, conflicts_with = "force")]
+    quick: bool,
+    /// Print why a test would or wouldn't run, without running it
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Measure once from a systemd timer, same as the measure command
+    #[arg(long)]
+    oneshot_systemd: bool,
+    #[command(subcommand)]
+    command: Option<Cmd>,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Keep running, printing a new line every interval
+    Daemon {
+        /// Seconds between lines, defaults to `daemon.interval_secs`
+        #[arg(long)]
+        interval: Option<u64>,
+    },
+    /// Run a speed test for the buffer and history, printing nothing
+    Measure,
+    /// Print the buffered measurement, never running a test or probe.
+    /// Probed placeholders such as {dns} or {ssid} stay empty
+    Render,
+    /// Switch quiet mode, which shows only `quiet.output`, on or off
+    Toggle,
+    /// Show the next of the `cycle.formats`, or `format` after the last
+    Cycle,
+    /// Print a summary of the buffered measurement: time, backend, speeds,
+    /// ISP and public ip
+    Detail {
+        /// Show it in a desktop notification instead
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Print the current RX/TX rates of an interface
+    Bandwidth {
+        /// Defaults to the interface holding the default route
+        #[arg(long)]
+        interface: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        interval: u64,
+    },
+    /// Nagios/Icinga plugin: print a status line and exit 0/1/2/3
+    Check,
+    /// Check the backend, directories, config and network, exiting 1 on a
+    /// failure
+    Doctor,
+    /// Terminal dashboard with live throughput and the latency history
+    Tui {
+        /// Defaults to the interface holding the default route
+        #[arg(long)]
+        interface: Option<String>,
+    },
+    /// Print completions for a shell, e.g. `rusting completions zsh > _rusting`
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Write or check the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCmd,
+    },
+    /// Work with the stored measurements
+    History {
+        #[command(subcommand)]
+        command: HistoryCmd,
+    },
+    /// List the outages the daemon recorded and the downtime per day
+    Outages {
+        /// Only include outages since this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// Latency percentiles and distribution the daemon sampled
+    LatencyStats,
+    /// A month's speeds, shortfalls against the plan, downtime and worst
+    /// hours, e.g. for an ISP support ticket
+    Report {
+        /// YYYY-MM, defaults to the current month
+        #[arg(long)]
+        month: Option<String>,
+        #[arg(long, value_enum, default_value = "text")]
+        format: report::ReportFormat,
+    },
+    /// Chart the download, upload and latency history to a PNG or SVG
+    Graph {
+        /// Days of history, overrides the config file
+        #[arg(long)]
+        days: Option<u32>,
+        /// .png or .svg file, overrides the config file
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Min/median/p95/max of the history per day or week
+    Stats {
+        #[arg(long, value_enum, default_value_t = stats::Period::Day)]
+        by: stats::Period,
+        /// Only include measurements since this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// Expose the measurements over HTTP
+    Serve {
+        /// Serve Prometheus metrics at /metrics
+        #[arg(long)]
+        prometheus: bool,
+        /// Serve a dashboard with the history and a button to run a test at /
+        #[arg(long)]
+        web: bool,
+        /// Serve the JSON API at /status, /history, /graph.svg, /refresh and
+        /// /run, implied by --web
+        #[arg(long)]
+        api: bool,
+        /// Address to listen on, overrides the config file
+        #[arg(long)]
+        listen: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /// Write the default config, every setting commented out
+    Init {
+        /// Overwrite an existing config
+        #[arg(long)]
+        force: bool,
+    },
+    /// Report unknown keys, bad colors and overlapping thresholds
+    Validate,
+}
+
+#[derive(Subcommand)]
+enum HistoryCmd {
+    /// Dump the stored measurements
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Only include measurements since this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+fn run_history_export(
+    config: &Config,
+    format: ExportFormat,
+    since: Option<&str>,
+) -> Result<(), String> {
+    let since = match since {
+        Some(s) => Some(history::parse_since(s)?),
+        None => None,
+    };
+    let entries = store::open(config)?.history(since)?;
+    match format {
+        ExportFormat::Csv => print!("{}", history::export_csv(&entries)),
+        ExportFormat::Json => println!("{}", history::export_json(&entries)?),
+    }
+    Ok(())
+}
+
+fn run_stats(config: &Config, by: stats::Period, since: Option<&str>) -> Result<(), String> {
+    let since = match since {
+        Some(s) => Some(history::parse_since(s)?),
+        None => None,
+    };
+    let entries = store::open(config)?.history(since)?;
+    print!("{}", stats::render_table(&entries, by));
+    Ok(())
+}
+
+fn run_report(
+    config: &Config,
+    month: Option<String>,
+    format: report::ReportFormat,
+) -> Result<(), String> {
+    let month = month.unwrap_or_else(report::current_month);
+    let (start, end) = report::month_range(&month)?;
+    // The current month only counts up to now
+    let end = end.min(Local::now().timestamp());
+    let entries: Vec<Fast> = store::open(config)?
+        .history(Some(start))?
+        .into_iter()
+        .filter(|e| e.timestamp.is_some_and(|t| t < end))
+        .collect();
+    let outages: Vec<outage::Outage> = outage::load(Some(start))?
+        .into_iter()
+        .filter(|o| o.start < end)
+        .collect();
+    print!(
+        "{}",
+        report::render(config, &month, (start, end), &entries, &outages, format)
+    );
+    Ok(())
+}
+
+fn run_graph(config: &Config, days: Option<u32>, path: Option<PathBuf>) -> Result<(), String> {
+    let days = days.unwrap_or(config.graph.days);
+    let path = match path.or_else(|| config.graph.path.clone()) {
+        Some(p) => p,
+        None => history::get_data_filename("graph.svg")?,
     };
+    let since = Local::now().timestamp() - days as i64 * 86400;
+    let entries = store::open(config)?.history(Some(since))?;
+    let size = (config.graph.width, config.graph.height);
+    graph::write(&entries, days, &path, size)?;
+    println!("{}", path.display());
+    Ok(())
+}
 
-    let icon = match info.latency {
-        0..=50 => r#"%{F#3cb703}%{F-}"#,
-        51..=150 => r#"%{F#f9dd04}%{F-}"#,
-        _ => r#"%{F#d60606}%{F-}"#,
+fn run_outages(since: Option<&str>) -> Result<(), String> {
+    let since = match since {
+        Some(s) => Some(history::parse_since(s)?),
+        None => None,
     };
+    print!("{}", outage::render_table(&outage::load(since)?));
+    Ok(())
+}
+
+/// The flags that override config settings, for applying them again after
+/// a reload.
+fn cli_overrides(cli: &Cli) -> reload::Overrides {
+    let show_ip = cli.ip;
+    let output = cli.output;
+    let textfile = cli.textfile.clone();
+    let max_width = cli.max_width;
+    Box::new(move |config| {
+        config.show_ip |= show_ip;
+        if let Some(o) = output {
+            config.output = o;
+        }
+        if textfile.is_some() {
+            config.prom_textfile = textfile.clone();
+        }
+        if max_width.is_some() {
+            config.max_width = max_width;
+        }
+    })
+}
+
+fn run_config(command: ConfigCmd) -> i32 {
+    match command {
+        ConfigCmd::Init { force } => match config::init(force) {
+            Ok(path) => {
+                println!("Wrote '{}'", path.display());
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+        ConfigCmd::Validate => match config::validate() {
+            Ok(problems) if problems.is_empty() => {
+                println!("Config is valid");
+                0
+            }
+            Ok(problems) => {
+                problems.iter().for_each(|p| println!("{}", p));
+                1
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+    }
+}
 
-    println!("{icon} {} ms  {} Mbps", info.latency, info.downloadSpeed);
+/// Sends the measurement to the output sinks, or prints why there's none.
+/// Exits nonzero when a sink fails.
+async fn show(
+    config: &Config,
+    result: Result<(Fast, HashMap<String, String>), String>,
+    live: bool,
+) {
+    match result {
+        Ok((info, values)) => {
+            if !sink::send_all(&sink::output(config, values, live), &info).await {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            error!("{}", e);
+            let line = render_missing_backend(config).or_else(|| render_offline(config));
+            if let Some(line) = line.filter(|_| config.sinks.bar) {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Some(Cmd::Completions { shell }) = cli.command {
+        let mut cmd = Cli::command();
+        let name = cmd.get_name().to_string();
+        clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+        return;
+    }
+    let config = config::load(cli.profile.as_deref());
+    let mut logging = match &config {
+        Ok(c) => c.logging.clone(),
+        Err(_) => LoggingConfig::default(),
+    };
+    if let Some(kind) = cli.log_to {
+        logging.targets = vec![LogTarget {
+            kind,
+            level: cli.log_level.unwrap_or(LogLevel::Info),
+            ..LogTarget::default()
+        }];
+    } else if let Some(level) = cli.log_level {
+        logging.targets.iter_mut().for_each(|t| t.level = level);
+    }
+    let verbose = match cli.verbose {
+        0 => None,
+        1 => Some(LogLevel::Info),
+        2 => Some(LogLevel::Debug),
+        _ => Some(LogLevel::Trace),
+    };
+    if let Some(level) = verbose {
+        logging.targets.push(LogTarget {
+            kind: LogKind::Stderr,
+            level,
+            ..LogTarget::default()
+        });
+    }
+    if cli.debug {
+        logging.targets.push(LogTarget {
+            kind: LogKind::Stdout,
+            level: LogLevel::Debug,
+            ..LogTarget::default()
+        });
+    }
+    if let Err(e) = logging::init(&logging) {
+        eprintln!("{}", e);
+    }
+    if let Ok(p) = config::get_config_filename() {
+        debug!("Config file: '{}'", p.display());
+    }
+    match cli.command {
+        Some(Cmd::Doctor) => process::exit(doctor::run_doctor(&config).await),
+        Some(Cmd::Config { command }) => process::exit(run_config(command)),
+        _ => {}
+    }
+    let mut config = match config {
+        Ok(c) => c,
+        Err(e) => {
+            error!("{}", e);
+            if cli.oneshot_systemd {
+                systemd::notify(&format!("STATUS=Failed: {}", e)).ok();
+                process::exit(1);
+            }
+            return;
+        }
+    };
+    let overrides = cli_overrides(&cli);
+    overrides(&mut config);
+
+    if cli.dry_run {
+        let measure = cli.oneshot_systemd || matches!(cli.command, Some(Cmd::Measure));
+        if cli.command.is_some() && !measure {
+            eprintln!("--dry-run only applies to printing the module and measure");
+            process::exit(1);
+        }
+        if !measure && quick_only(&config, cli.quick, cli.force) {
+            println!("Would probe the latency of the [quick] targets only");
+            return;
+        }
+        for line in dry_run(&config, cli.force, measure).await {
+            println!("{}", line);
+        }
+        return;
+    }
+    if cli.oneshot_systemd {
+        process::exit(run_measure(&config, cli.force).await);
+    }
+    let prints = matches!(
+        cli.command,
+        None | Some(Cmd::Render) | Some(Cmd::Daemon { .. })
+    );
+    if prints && config.output == OutputFormat::PromTextfile && config.prom_textfile.is_none() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--output prom-textfile needs --textfile, or prom_textfile in the config file",
+            )
+            .exit();
+    }
+    match cli.command {
+        None => {
+            let result = if quick_only(&config, cli.quick, cli.force) {
+                get_quick_info(&config).await
+            } else {
+                get_info(&config, cli.force)
+                    .await
+                    .map(|info| (info, HashMap::new()))
+            };
+            show(&config, result, true).await
+        }
+        Some(Cmd::Measure) => process::exit(run_measure(&config, cli.force).await),
+        Some(Cmd::Detail { notify }) => {
+            let detail = match get_cached_info(&config).await {
+                Ok(info) => render_detail(&config, &info).await,
+                Err(e) => {
+                    error!("{}", e);
+                    format!("No measurement yet: {}", e)
+                }
+            };
+            if !notify {
+                println!("{}", detail);
+            } else if let Err(e) = notify::show("Internet speed", &detail) {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::Toggle) => match quiet::toggle(&config.quiet) {
+            Ok(on) => println!("Quiet {}", if on { "on" } else { "off" }),
+            Err(e) => {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        Some(Cmd::Cycle) => match cycle::advance() {
+            Ok(_) => println!(
+                "{}",
+                cycle::format(&config.cycle)
+                    .or_else(|| config.format.clone())
+                    .unwrap_or_else(|| "Default format".to_string())
+            ),
+            Err(e) => {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        Some(Cmd::Render) => {
+            let result = get_cached_info(&config).await;
+            show(&config, result.map(|info| (info, HashMap::new())), false).await
+        }
+        Some(Cmd::Daemon { interval }) => {
+            run_daemon(config, interval, cli.quick, cli.profile, overrides).await
+        }
+        Some(Cmd::Bandwidth {
+            interface,
+            interval,
+        }) => run_bandwidth(&config, interface.as_deref(), interval).await,
+        Some(Cmd::History {
+            command: HistoryCmd::Export { format, since },
+        }) => {
+            if let Err(e) = run_history_export(&config, format, since.as_deref()) {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::Outages { since }) => {
+            if let Err(e) = run_outages(since.as_deref()) {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::LatencyStats) => {
+            let histogram = latency::load().unwrap_or_default().combined();
+            print!("{}", latency::render_stats(&histogram));
+        }
+        Some(Cmd::Report { month, format }) => {
+            if let Err(e) = run_report(&config, month, format) {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::Graph { days, path }) => {
+            if let Err(e) = run_graph(&config, days, path) {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::Stats { by, since }) => {
+            if let Err(e) = run_stats(&config, by, since.as_deref()) {
+                error!("{}", e);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::Check) => {
+            let (line, code) = match get_info(&config, cli.force).await {
+                Ok(info) => check::check(&config.check, &info),
+                Err(e) => {
+                    error!("{}", e);
+                    (format!("INTERNET UNKNOWN - {}", e), check::UNKNOWN)
+                }
+            };
+            println!("{}", line);
+            process::exit(code);
+        }
+        // Run before the config is required, to report parse errors
+        Some(Cmd::Doctor) | Some(Cmd::Config { .. }) | Some(Cmd::Completions { .. }) => {}
+        Some(Cmd::Tui { interface }) => {
+            if let Err(e) = tui::run_tui(&config, interface.as_deref()).await {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(Cmd::Serve {
+            prometheus,
+            web,
+            api,
+            listen,
+        }) => {
+            let listen = listen.unwrap_or_else(|| config.serve.listen.clone());
+            let options = serve::ServeOptions {
+                prometheus,
+                web,
+                api: api || web,
+            };
+            if let Err(e) = serve::run_serve(config, &listen, options, cli.profile, overrides).await
+            {
+                error!("{}", e);
+                eprintln!("{}", e);
+            }
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use crate::config::{MockConfig, MockPattern};
+use crate::{get_cache_filename, Fast};
+use log::info;
+use serde_json::Value;
+use std::fs;
+use std::time::Duration;
+use tokio::time;
+
+// The next step of the pattern
+const STEP_FILE_PATH: &str = ".polybar-internet-speed-mock";
+
+fn load_step() -> u64 {
+    get_cache_filename(STEP_FILE_PATH)
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|c| c.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn save_step(step: u64) -> Result<(), String> {
+    let path = get_cache_filename(STEP_FILE_PATH)?;
+    match fs::write(&path, step.to_string()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// A number in [-1, 1) derived from `seed` (splitmix64), so a step always
+/// jitters the same way.
+fn noise(seed: u64) -> f64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+fn jitter(value: u32, percent: f64, seed: u64) -> u32 {
+    (value as f64 * (1.0 + percent / 100.0 * noise(seed)))
+        .round()
+        .max(0.0) as u32
+}
+
+/// The `step`th line of the fixture, wrapping around.
+fn replay(config: &MockConfig, step: u64) -> Result<Fast, String> {
+    let path = match &config.fixture {
+        Some(p) => p,
+        None => return Err("The fixture pattern needs mock.fixture".to_string()),
+    };
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", path.display(), e));
+        }
+    };
+    let lines: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return Err(format!("'{}' has no measurements", path.display()));
+    }
+    let n = (step % lines.len() as u64) as usize;
+    let json: Value = match serde_json::from_str(lines[n]) {
+        Ok(j) => j,
+        Err(e) => {
+            return Err(format!(
+                "Failed to parse line {} of '{}': {}",
+                n + 1,
+                path.display(),
+                e
+            ));
+        }
+    };
+    if let Some(e) = json.get("error") {
+        return Err(format!("Mock failure: {}", e));
+    }
+    match serde_json::from_value::<Fast>(json) {
+        // Replayed like a fresh measurement
+        Ok(f) => Ok(Fast {
+            download_speed: f.download_speed,
+            upload_speed: f.upload_speed,
+            latency: f.latency,
+            user_ip: f.user_ip,
+            user_location: f.user_location,
+            ..Default::default()
+        }),
+        Err(e) => Err(format!(
+            "Line {} of '{}' isn't a measurement: {}",
+            n + 1,
+            path.display(),
+            e
+        )),
+    }
+}
+
+fn generate(config: &MockConfig, step: u64) -> Fast {
+    let (download, upload, latency) = match config.pattern {
+        MockPattern::Random => (
+            jitter(config.download, config.jitter_percent, step * 3),
+            config
+                .upload
+                .map(|u| jitter(u, config.jitter_percent, step * 3 + 1)),
+            jitter(config.latency, config.jitter_percent, step * 3 + 2),
+        ),
+        // Speeds fall towards zero while the latency climbs
+        MockPattern::Ramp => {
+            let steps = config.ramp_steps.max(1) as u64;
+            let left = steps - step % steps;
+            let scale = |v: u32| (v as u64 * left / steps) as u32;
+            (
+                scale(config.download),
+                config.upload.map(scale),
+                config.latency * (steps - left + 1) as u32,
+            )
+        }
+        MockPattern::Constant | MockPattern::Fixture => {
+            (config.download, config.upload, config.latency)
+        }
+    };
+    Fast {
+        download_speed: download,
+        upload_speed: upload,
+        latency,
+        ..Default::default()
+    }
+}
+
+/// The next result of the configured pattern, after `delay_ms`.
+pub async fn get_mock_info(config: &MockConfig) -> Result<Fast, String> {
+    let step = load_step();
+    save_step(step.wrapping_add(1))?;
+    time::sleep(Duration::from_millis(config.delay_ms)).await;
+    if config.fail_every > 0 && (step + 1).is_multiple_of(config.fail_every as u64) {
+        return Err(format!("Mock failure on test {}", step + 1));
+    }
+    let info = match config.pattern {
+        MockPattern::Fixture => replay(config, step)?,
+        _ => generate(config, step),
+    };
+    info!("Mock test {}: {:?}", step + 1, info);
+    Ok(info)
+}
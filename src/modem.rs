@@ -0,0 +1,87 @@
+use log::info;
+use std::collections::HashMap;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::{Connection as Bus, Proxy};
+
+const MM_SERVICE: &str = "org.freedesktop.ModemManager1";
+const MM_PATH: &str = "/org/freedesktop/ModemManager1";
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+const MODEM_INTERFACE: &str = "org.freedesktop.ModemManager1.Modem";
+const MODEM_3GPP_INTERFACE: &str = "org.freedesktop.ModemManager1.Modem.Modem3gpp";
+
+// MMModemAccessTechnology flags, by generation
+const ACCESS_5G: u32 = 1 << 15;
+const ACCESS_LTE: u32 = 1 << 14 | 1 << 16 | 1 << 17;
+const ACCESS_3G: u32 = 0x3fe0;
+const ACCESS_2G: u32 = 0x1e;
+
+type Properties = HashMap<String, HashMap<String, OwnedValue>>;
+
+#[derive(Debug, Clone)]
+pub struct Modem {
+    /// 0-100
+    pub signal: u32,
+    /// 5G, LTE, 3G or 2G, empty while not registered
+    pub technology: String,
+    pub operator: Option<String>,
+}
+
+/// The newest generation among the access technology flags.
+fn technology(flags: u32) -> &'static str {
+    if flags & ACCESS_5G != 0 {
+        "5G"
+    } else if flags & ACCESS_LTE != 0 {
+        "LTE"
+    } else if flags & ACCESS_3G != 0 {
+        "3G"
+    } else if flags & ACCESS_2G != 0 {
+        "2G"
+    } else {
+        ""
+    }
+}
+
+fn value<T>(properties: &Properties, interface: &str, name: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    let value = properties.get(interface)?.get(name)?.try_clone().ok()?;
+    T::try_from(value).ok()
+}
+
+/// Returns the first modem ModemManager knows, `None` without one.
+pub async fn get_modem() -> Result<Option<Modem>, String> {
+    let bus = match Bus::system().await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(format!("Failed to connect to the system bus: {}", e));
+        }
+    };
+    let manager = match Proxy::new(&bus, MM_SERVICE, MM_PATH, OBJECT_MANAGER).await {
+        Ok(p) => p,
+        Err(e) => return Err(format!("Failed to reach ModemManager: {}", e)),
+    };
+    // Every modem with all of its properties in one call
+    let objects: HashMap<OwnedObjectPath, Properties> =
+        match manager.call("GetManagedObjects", &()).await {
+            Ok(o) => o,
+            Err(e) => return Err(format!("Failed to list ModemManager's modems: {}", e)),
+        };
+    let mut paths: Vec<&OwnedObjectPath> = objects.keys().collect();
+    paths.sort_by_key(|p| p.as_str());
+    let properties = match paths.first().and_then(|p| objects.get(*p)) {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let signal: (u32, bool) =
+        value(properties, MODEM_INTERFACE, "SignalQuality").unwrap_or_default();
+    let flags: u32 = value(properties, MODEM_INTERFACE, "AccessTechnologies").unwrap_or(0);
+    let operator: Option<String> = value(properties, MODEM_3GPP_INTERFACE, "OperatorName");
+    let modem = Modem {
+        signal: signal.0,
+        technology: technology(flags).to_string(),
+        operator: operator.filter(|o| !o.is_empty()),
+    };
+    info!("ModemManager modem: {:?}", modem);
+    Ok(Some(modem))
+}
@@ -0,0 +1,194 @@
+use crate::config::MqttConfig;
+use crate::system::hostname;
+use crate::{format, Fast};
+use log::info;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const DISCONNECT: u8 = 0xe0;
+const KEEP_ALIVE_SECS: u16 = 30;
+
+/// Minimal MQTT 3.1.1 client, QoS 0 only, which is all a fire and forget
+/// sink needs.
+struct Client {
+    stream: TcpStream,
+}
+
+fn encode_remaining_length(mut len: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    encode_remaining_length(body.len(), &mut out);
+    out.extend_from_slice(body);
+    out
+}
+
+impl Client {
+    async fn connect(config: &MqttConfig, client_id: &str) -> Result<Client, String> {
+        let addr = format!("{}:{}", config.host, config.port);
+        let stream = match TcpStream::connect(&addr).await {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(format!(
+                    "Failed to connect to MQTT broker '{}': {}",
+                    addr, e
+                ));
+            }
+        };
+
+        let mut body = Vec::new();
+        encode_str("MQTT", &mut body);
+        // Protocol level 4 is MQTT 3.1.1
+        body.push(4);
+        let mut flags = 0x02; // clean session
+        if config.username.is_some() {
+            flags |= 0x80;
+        }
+        if config.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+        encode_str(client_id, &mut body);
+        if let Some(u) = &config.username {
+            encode_str(u, &mut body);
+        }
+        if let Some(p) = &config.password {
+            encode_str(p, &mut body);
+        }
+
+        let mut client = Client { stream };
+        client.send(&packet(CONNECT, &body)).await?;
+        let mut connack = [0u8; 4];
+        if let Err(e) = client.stream.read_exact(&mut connack).await {
+            return Err(format!("Failed to read MQTT CONNACK: {}", e));
+        }
+        if connack[0] != CONNACK || connack[3] != 0 {
+            return Err(format!(
+                "MQTT broker refused connection, return code: {}",
+                connack[3]
+            ));
+        }
+        Ok(client)
+    }
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        match self.stream.write_all(data).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to write to MQTT broker: {}", e)),
+        }
+    }
+
+    async fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<(), String> {
+        let mut body = Vec::new();
+        encode_str(topic, &mut body);
+        body.extend_from_slice(payload.as_bytes());
+        let header = if retain { PUBLISH | 0x01 } else { PUBLISH };
+        self.send(&packet(header, &body)).await
+    }
+
+    async fn disconnect(mut self) {
+        let _ = self.send(&[DISCONNECT, 0]).await;
+    }
+}
+
+/// Home Assistant MQTT discovery payload for one sensor.
+fn discovery_payload(node_id: &str, metric: &str, unit: &str, state_topic: &str) -> String {
+    let device_class = if unit == "ms" {
+        "duration"
+    } else {
+        "data_rate"
+    };
+    serde_json::json!({
+        "name": format!("Internet {}", metric),
+        "unique_id": format!("{}_internet_{}", node_id, metric),
+        "state_topic": state_topic,
+        "unit_of_measurement": unit,
+        "device_class": device_class,
+        "state_class": "measurement",
+        "device": {
+            "identifiers": [format!("{}_internet_speed", node_id)],
+            "name": format!("{} internet speed", node_id),
+        },
+    })
+    .to_string()
+}
+
+async fn publish_all(config: &MqttConfig, info: &Fast) -> Result<(), String> {
+    let node_id = hostname();
+    let client_id = config
+        .client_id
+        .clone()
+        .unwrap_or_else(|| format!("polybar-internet-speed-{}", node_id));
+    let mut client = Client::connect(config, &client_id).await?;
+
+    let mut metrics = vec![
+        ("download", "Mbit/s", info.download_speed),
+        ("latency", "ms", info.latency),
+    ];
+    if let Some(up) = info.upload_speed {
+        metrics.push(("upload", "Mbit/s", up));
+    }
+    let labels = info.labels.clone().into_iter().collect();
+    let prefix = format::render(&config.topic_prefix, &labels);
+    let prefix = prefix.trim_end_matches('/');
+    for (metric, unit, value) in metrics {
+        let topic = format!("{}/{}", prefix, metric);
+        if config.discovery {
+            let discovery_topic = format!(
+                "{}/sensor/{}/internet_{}/config",
+                config.discovery_prefix.trim_end_matches('/'),
+                node_id,
+                metric
+            );
+            client
+                .publish(
+                    &discovery_topic,
+                    &discovery_payload(&node_id, metric, unit, &topic),
+                    true,
+                )
+                .await?;
+        }
+        client
+            .publish(&topic, &value.to_string(), config.retain)
+            .await?;
+    }
+    client.disconnect().await;
+    info!("Published measurement to MQTT broker '{}'", config.host);
+    Ok(())
+}
+
+/// Publishes the measurement, giving up on the whole session after
+/// `timeout_secs`.
+pub async fn publish(config: &MqttConfig, info: &Fast) -> Result<(), String> {
+    let timeout = Duration::from_secs(config.timeout_secs);
+    match time::timeout(timeout, publish_all(config, info)).await {
+        Ok(r) => r,
+        Err(_) => Err(format!(
+            "Timed out talking to MQTT broker '{}' after {:?}",
+            config.host, timeout
+        )),
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::NativeConfig;
+use crate::{bound_http_client, Fast};
+use log::info;
+use std::time::{Duration, Instant};
+
+const LATENCY_SAMPLES: usize = 3;
+
+fn mbps(bytes: u64, elapsed: Duration) -> u32 {
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 {
+        return 0;
+    }
+    (bytes as f64 * 8.0 / secs / 1_000_000.0).round() as u32
+}
+
+/// Reads the whole body without keeping it, returning its size.
+async fn drain(mut resp: reqwest::Response) -> Result<u64, reqwest::Error> {
+    let mut bytes = 0;
+    while let Some(chunk) = resp.chunk().await? {
+        bytes += chunk.len() as u64;
+    }
+    Ok(bytes)
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> Result<reqwest::Response, reqwest::Error> {
+    client.get(url).send().await?.error_for_status()
+}
+
+async fn measure_latency(client: &reqwest::Client, url: &str) -> Result<u32, String> {
+    let mut best = Duration::MAX;
+    // The first request pays for DNS and the TLS handshake, keep the fastest
+    for _ in 0..LATENCY_SAMPLES {
+        let start = Instant::now();
+        let resp = match get(client, url).await {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(format!("Latency request to '{}' failed: {}", url, e));
+            }
+        };
+        if let Err(e) = drain(resp).await {
+            return Err(format!("Failed to read latency response: {}", e));
+        }
+        best = best.min(start.elapsed());
+    }
+    Ok(best.as_millis() as u32)
+}
+
+async fn measure_download(client: &reqwest::Client, url: &str) -> Result<u32, String> {
+    let start = Instant::now();
+    let resp = match get(client, url).await {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(format!("Download request to '{}' failed: {}", url, e));
+        }
+    };
+    let bytes = match drain(resp).await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(format!("Failed to read download response: {}", e));
+        }
+    };
+    let elapsed = start.elapsed();
+    info!("Downloaded {} bytes in {:?}", bytes, elapsed);
+    Ok(mbps(bytes, elapsed))
+}
+
+async fn measure_upload(client: &reqwest::Client, url: &str, size: usize) -> Result<u32, String> {
+    let payload = vec![0u8; size];
+    let start = Instant::now();
+    match client
+        .post(url)
+        .header("Content-Type", "application/octet-stream")
+        .body(payload)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+    {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(format!("Upload request to '{}' failed: {}", url, e));
+        }
+    }
+    let elapsed = start.elapsed();
+    info!("Uploaded {} bytes in {:?}", size, elapsed);
+    Ok(mbps(size as u64, elapsed))
+}
+
+/// Measures latency, download and (optionally) upload using plain HTTP
+/// requests, without depending on any external binary. Connections go
+/// through `interface` when given.
+pub async fn get_native_info(
+    config: &NativeConfig,
+    interface: Option<&str>,
+) -> Result<Fast, String> {
+    let client = bound_http_client(config.timeout_secs, interface)?;
+    let latency = measure_latency(&client, &config.latency_url).await?;
+    let download_speed = measure_download(&client, &config.download_url).await?;
+    let upload_speed = if config.upload {
+        Some(measure_upload(&client, &config.upload_url, config.upload_bytes).await?)
+    } else {
+        None
+    };
+
+    let server = reqwest::Url::parse(&config.download_url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string));
+    Ok(Fast {
+        download_speed,
+        upload_speed,
+        latency,
+        server,
+        ..Default::default()
+    })
+}
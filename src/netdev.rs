@@ -0,0 +1,270 @@
+use crate::wifi;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+#[cfg(target_os = "linux")]
+const PROC_NET_DEV: &str = "/proc/net/dev";
+#[cfg(target_os = "linux")]
+const PROC_NET_ROUTE: &str = "/proc/net/route";
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Counters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rates {
+    pub rx_mbps: f64,
+    pub tx_mbps: f64,
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_counters(interface: &str) -> Result<Counters, String> {
+    let contents = match fs::read_to_string(PROC_NET_DEV) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", PROC_NET_DEV, e));
+        }
+    };
+    // First two lines are headers
+    for line in contents.lines().skip(2) {
+        let (name, stats) = match line.split_once(':') {
+            Some(s) => s,
+            None => continue,
+        };
+        if name.trim() != interface {
+            continue;
+        }
+        let fields: Vec<u64> = stats
+            .split_whitespace()
+            .map(|f| f.parse().unwrap_or(0))
+            .collect();
+        if fields.len() < 9 {
+            return Err(format!(
+                "Malformed '{}' entry for '{}'",
+                PROC_NET_DEV, interface
+            ));
+        }
+        return Ok(Counters {
+            rx_bytes: fields[0],
+            tx_bytes: fields[8],
+        });
+    }
+    Err(format!(
+        "Interface '{}' not found in '{}'",
+        interface, PROC_NET_DEV
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_counters(interface: &str) -> Result<Counters, String> {
+    let networks = sysinfo::Networks::new_with_refreshed_list();
+    match networks.iter().find(|(name, _)| name.as_str() == interface) {
+        Some((_, data)) => Ok(Counters {
+            rx_bytes: data.total_received(),
+            tx_bytes: data.total_transmitted(),
+        }),
+        None => Err(format!("Interface '{}' not found", interface)),
+    }
+}
+
+/// Returns the interface holding the default route.
+#[cfg(target_os = "linux")]
+pub fn default_interface() -> Result<String, String> {
+    let contents = match fs::read_to_string(PROC_NET_ROUTE) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", PROC_NET_ROUTE, e));
+        }
+    };
+    for line in contents.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 1 && fields[1] == "00000000" {
+            return Ok(fields[0].to_string());
+        }
+    }
+    Err("No default route found".to_string())
+}
+
+/// Returns the interface owning the address outgoing traffic comes from,
+/// which is the default route's without a routing table to read.
+#[cfg(not(target_os = "linux"))]
+pub fn default_interface() -> Result<String, String> {
+    // Connecting a UDP socket only picks the route, nothing is sent
+    let local = std::net::UdpSocket::bind("0.0.0.0:0")
+        .and_then(|s| s.connect("192.0.2.1:9").map(|_| s))
+        .and_then(|s| s.local_addr());
+    let local = match local {
+        Ok(a) => a.ip(),
+        Err(_) => return Err("No default route found".to_string()),
+    };
+    let networks = sysinfo::Networks::new_with_refreshed_list();
+    for (name, data) in networks.iter() {
+        if data.ip_networks().iter().any(|n| n.addr == local) {
+            return Ok(name.clone());
+        }
+    }
+    Err(format!("No interface has the address '{}'", local))
+}
+
+pub fn resolve_interface(interface: Option<&str>) -> Result<String, String> {
+    match interface {
+        Some(i) => Ok(i.to_string()),
+        None => default_interface(),
+    }
+}
+
+/// Whether the interface is up, `unknown` counting as up since tunnels and
+/// some drivers never report their state. Always true off Linux.
+pub fn is_up(interface: &str) -> bool {
+    if !cfg!(target_os = "linux") {
+        return true;
+    }
+    let path = format!("{}/{}/operstate", SYS_CLASS_NET, interface);
+    match fs::read_to_string(path) {
+        Ok(s) => matches!(s.trim(), "up" | "unknown"),
+        Err(_) => false,
+    }
+}
+
+/// Negotiated link speed in Mbit/s, `None` for interfaces without one such as
+/// Wi-Fi or a disconnected cable, and off Linux.
+pub fn link_speed(interface: &str) -> Option<u32> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let path = format!("{}/{}/speed", SYS_CLASS_NET, interface);
+    // Reads fail with EINVAL and report -1 when there's no link
+    match fs::read_to_string(path) {
+        Ok(s) => s
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|s| *s > 0)
+            .map(|s| s as u32),
+        Err(_) => None,
+    }
+}
+
+/// Formats a link speed the way it's usually advertised, e.g. `100M`, `1G`
+/// or `2.5G`.
+pub fn format_link_speed(mbps: u32) -> String {
+    if mbps < 1000 {
+        return format!("{}M", mbps);
+    }
+    let gbps = format!("{:.1}", mbps as f64 / 1000.0);
+    format!("{}G", gbps.trim_end_matches(".0"))
+}
+
+/// Turns consecutive counter readings into rates.
+pub struct Sampler {
+    interface: String,
+    last: Option<(Counters, Instant)>,
+}
+
+impl Sampler {
+    pub fn new(interface: &str) -> Self {
+        Sampler {
+            interface: interface.to_string(),
+            last: None,
+        }
+    }
+
+    /// Returns `None` on the first call, since there is nothing to compare
+    /// against yet.
+    pub fn sample(&mut self) -> Result<Option<Rates>, String> {
+        let now = Instant::now();
+        let counters = read_counters(&self.interface)?;
+        let rates = self.last.map(|(prev, then)| {
+            let secs = now.duration_since(then).as_secs_f64().max(f64::EPSILON);
+            Rates {
+                rx_mbps: counters.rx_bytes.saturating_sub(prev.rx_bytes) as f64 * 8.0
+                    / secs
+                    / 1_000_000.0,
+                tx_mbps: counters.tx_bytes.saturating_sub(prev.tx_bytes) as f64 * 8.0
+                    / secs
+                    / 1_000_000.0,
+            }
+        });
+        self.last = Some((counters, now));
+        Ok(rates)
+    }
+}
+
+/// Returns the gateway of the default route.
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Result<Ipv4Addr, String> {
+    let contents = match fs::read_to_string(PROC_NET_ROUTE) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", PROC_NET_ROUTE, e));
+        }
+    };
+    for line in contents.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 2 && fields[1] == "00000000" {
+            // Stored as a little endian hex number
+            return match u32::from_str_radix(fields[2], 16) {
+                Ok(g) => Ok(Ipv4Addr::from(g.to_le_bytes())),
+                Err(e) => Err(format!("Malformed gateway '{}': {}", fields[2], e)),
+            };
+        }
+    }
+    Err("No default route found".to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn default_gateway() -> Result<Ipv4Addr, String> {
+    Err("The default gateway is only known on Linux".to_string())
+}
+
+/// The default route's interface and gateway.
+fn default_route() -> (String, String) {
+    let iface = default_interface().unwrap_or_default();
+    let gateway = match default_gateway() {
+        Ok(g) => g.to_string(),
+        Err(_) => String::new(),
+    };
+    (iface, gateway)
+}
+
+/// Adds the SSID to the route, running `iwgetid` off the async threads.
+async fn identify((iface, gateway): &(String, String)) -> String {
+    let name = iface.clone();
+    let ssid = tokio::task::spawn_blocking(move || wifi::get_wifi_info(Some(&name)))
+        .await
+        .ok()
+        .and_then(|w| w.ok())
+        .and_then(|w| w.ssid)
+        .unwrap_or_default();
+    format!("{} via {} {}", iface, gateway, ssid)
+}
+
+/// Notices a change of network by its default route and SSID. The SSID is
+/// only read again once the route changed, rather than on every check.
+pub struct NetworkWatch {
+    route: (String, String),
+    pub id: String,
+}
+
+impl NetworkWatch {
+    pub async fn new() -> NetworkWatch {
+        let route = default_route();
+        let id = identify(&route).await;
+        NetworkWatch { route, id }
+    }
+
+    /// The previous network's id when it changed since the last call.
+    pub async fn changed(&mut self) -> Option<String> {
+        let route = default_route();
+        if route == self.route {
+            return None;
+        }
+        let id = identify(&route).await;
+        self.route = route;
+        Some(std::mem::replace(&mut self.id, id))
+    }
+}
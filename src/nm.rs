@@ -0,0 +1,77 @@
+use log::info;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection as Bus, Proxy};
+
+const NM_SERVICE: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const ACTIVE_INTERFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+// NMMetered: yes and guess-yes
+const METERED_YES: u32 = 1;
+const METERED_GUESS_YES: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub name: String,
+    /// wifi, ethernet, wwan, vpn or NetworkManager's own type name
+    pub kind: String,
+    pub metered: bool,
+}
+
+fn kind(nm_type: &str) -> String {
+    match nm_type {
+        "802-11-wireless" => "wifi",
+        "802-3-ethernet" => "ethernet",
+        "gsm" | "cdma" => "wwan",
+        "vpn" | "wireguard" => "vpn",
+        t => t,
+    }
+    .to_string()
+}
+
+async fn proxy<'a>(bus: &Bus, path: &'a str, interface: &'a str) -> Result<Proxy<'a>, String> {
+    match Proxy::new(bus, NM_SERVICE, path, interface).await {
+        Ok(p) => Ok(p),
+        Err(e) => Err(format!("Failed to reach NetworkManager: {}", e)),
+    }
+}
+
+async fn property<T>(proxy: &Proxy<'_>, name: &str) -> Result<T, String>
+where
+    T: TryFrom<zbus::zvariant::OwnedValue>,
+    T::Error: Into<zbus::Error>,
+{
+    match proxy.get_property(name).await {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!(
+            "Failed to get NetworkManager property '{}': {}",
+            name, e
+        )),
+    }
+}
+
+/// Returns NetworkManager's primary connection, `None` when offline.
+pub async fn get_connection() -> Result<Option<Connection>, String> {
+    let bus = match Bus::system().await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(format!("Failed to connect to the system bus: {}", e));
+        }
+    };
+    let manager = proxy(&bus, NM_PATH, NM_SERVICE).await?;
+    let primary: OwnedObjectPath = property(&manager, "PrimaryConnection").await?;
+    if primary.as_str() == "/" {
+        return Ok(None);
+    }
+    let metered: u32 = property(&manager, "Metered").await?;
+
+    let active = proxy(&bus, primary.as_str(), ACTIVE_INTERFACE).await?;
+    let name: String = property(&active, "Id").await?;
+    let nm_type: String = property(&active, "Type").await?;
+    let connection = Connection {
+        name,
+        kind: kind(&nm_type),
+        metered: metered == METERED_YES || metered == METERED_GUESS_YES,
+    };
+    info!("NetworkManager connection: {:?}", connection);
+    Ok(Some(connection))
+}
@@ -0,0 +1,78 @@
+use crate::config::{NotifyConfig, Thresholds};
+use crate::system::SystemClock;
+use crate::{get_cache_filename, get_seconds_since_file_modified, Fast};
+use log::info;
+use notify_rust::Notification;
+use std::fs;
+
+// Only the modification time matters, it marks the last notification sent
+const COOLDOWN_FILE_PATH: &str = ".polybar-internet-speed-notified";
+
+/// Lists every configured floor the measurement falls below.
+pub fn degraded_reasons(config: &Thresholds, info: &Fast) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if let Some(min) = config.min_download {
+        if info.download_speed < min {
+            reasons.push(format!(
+                "download {} Mbps < {} Mbps",
+                info.download_speed, min
+            ));
+        }
+    }
+    if let (Some(min), Some(up)) = (config.min_upload, info.upload_speed) {
+        if up < min {
+            reasons.push(format!("upload {} Mbps < {} Mbps", up, min));
+        }
+    }
+    if let Some(max) = config.max_latency {
+        if info.latency > max {
+            reasons.push(format!("latency {} ms > {} ms", info.latency, max));
+        }
+    }
+    reasons
+}
+
+/// Sends a desktop notification when the connection is degraded, at most
+/// once per cooldown period.
+pub fn notify_if_degraded(config: &NotifyConfig, info: &Fast) -> Result<(), String> {
+    let reasons = degraded_reasons(&config.thresholds, info);
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    let path = get_cache_filename(COOLDOWN_FILE_PATH)?;
+    if let Ok(elapsed) = get_seconds_since_file_modified(&path, &SystemClock) {
+        if elapsed < config.cooldown_secs {
+            info!("Connection degraded but notified {}s ago", elapsed);
+            return Ok(());
+        }
+    }
+    match Notification::new()
+        .summary("Internet connection degraded")
+        .body(&reasons.join("\n"))
+        .icon("network-error")
+        .show()
+    {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(format!("Failed to send notification: {}", e));
+        }
+    }
+    info!("Sent degraded connection notification: {:?}", reasons);
+    match fs::write(&path, "") {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// Shows `body` in a desktop notification, e.g. the measurement's detail.
+pub fn show(summary: &str, body: &str) -> Result<(), String> {
+    match Notification::new()
+        .summary(summary)
+        .body(body)
+        .icon("network-wireless")
+        .show()
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to send notification: {}", e)),
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::{OutageConfig, RouteConfig};
+use crate::history::get_data_filename;
+use crate::system::SystemRunner;
+use crate::{format_age, get_cache_filename, ping, route};
+use chrono::{Local, NaiveDate, TimeZone};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write as _};
+use std::time::{Duration, Instant};
+
+const OUTAGES_FILE_PATH: &str = "outages.jsonl";
+// Start of the outage in progress, so a restarted daemon carries on with it
+const DOWN_FILE_PATH: &str = ".polybar-internet-speed-down";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Outage {
+    /// Unix time
+    pub start: i64,
+    pub end: i64,
+    /// Where the route died, when it was traced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cause: Option<route::Cause>,
+}
+
+/// Follows reachability across daemon ticks, recording each outage once the
+/// internet is back.
+pub struct Tracker {
+    probed_at: Option<Instant>,
+    down: Option<Outage>,
+}
+
+fn load_down() -> Option<Outage> {
+    let path = get_cache_filename(DOWN_FILE_PATH).ok()?;
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn save_down(down: Option<&Outage>) -> Result<(), String> {
+    let path = get_cache_filename(DOWN_FILE_PATH)?;
+    let result = match down {
+        Some(o) => match serde_json::to_string(o) {
+            Ok(json) => fs::write(&path, json),
+            Err(e) => return Err(format!("Failed to convert to JSON: {}", e)),
+        },
+        None => match fs::remove_file(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        },
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+fn append(outage: &Outage) -> Result<(), String> {
+    let path = get_data_filename(OUTAGES_FILE_PATH)?;
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+    }
+    let line = match serde_json::to_string(outage) {
+        Ok(l) => l,
+        Err(e) => return Err(format!("Failed to convert to JSON: {}", e)),
+    };
+    let mut file = match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Failed to open '{}': {}", path.display(), e)),
+    };
+    match writeln!(file, "{}", line) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path.display(), e)),
+    }
+}
+
+impl Tracker {
+    pub fn new() -> Tracker {
+        Tracker {
+            probed_at: None,
+            down: load_down(),
+        }
+    }
+
+    /// Probes the target once `probe_secs` passed since the last probe,
+    /// starting or ending an outage when reachability changed.
+    pub async fn check(&mut self, config: &OutageConfig, route_config: &RouteConfig) {
+        let interval = Duration::from_secs(config.probe_secs);
+        if self.probed_at.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.probed_at = Some(Instant::now());
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let reachable = ping::probe(&config.target, 1, timeout).await.is_ok();
+        let now = Local::now().timestamp();
+        match (reachable, self.down.take()) {
+            (false, None) => {
+                warn!("'{}' is unreachable, outage started", config.target);
+                let cause = if route_config.enabled {
+                    route::diagnose(&SystemRunner, route_config).await
+                } else {
+                    None
+                };
+                let down = Outage {
+                    start: now,
+                    end: now,
+                    cause,
+                };
+                if let Err(e) = save_down(Some(&down)) {
+                    error!("{}", e);
+                }
+                self.down = Some(down);
+            }
+            (false, down) => self.down = down,
+            (true, Some(mut outage)) => {
+                let start = outage.start;
+                info!(
+                    "'{}' is reachable again after {}",
+                    config.target,
+                    format_age((now - start) as u64)
+                );
+                if let Err(e) = save_down(None) {
+                    error!("{}", e);
+                }
+                if now - start < config.min_secs as i64 {
+                    return;
+                }
+                outage.end = now;
+                if let Err(e) = append(&outage) {
+                    error!("{}", e);
+                }
+            }
+            (true, None) => (),
+        }
+    }
+}
+
+/// Every recorded outage ending at or after `since`, plus the one still in
+/// progress ending now.
+pub fn load(since: Option<i64>) -> Result<Vec<Outage>, String> {
+    let path = get_data_filename(OUTAGES_FILE_PATH)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+    let mut outages = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Outage>(line) {
+            Ok(o) if since.is_none_or(|s| o.end >= s) => outages.push(o),
+            Ok(_) => (),
+            Err(e) => warn!("Skipping line {} of '{}': {}", n + 1, path.display(), e),
+        }
+    }
+    if let Some(mut down) = load_down() {
+        down.end = Local::now().timestamp();
+        outages.push(down);
+    }
+    Ok(outages)
+}
+
+fn local_date(t: i64) -> Option<NaiveDate> {
+    Local.timestamp_opt(t, 0).single().map(|d| d.date_naive())
+}
+
+/// Splits each outage at local midnight, summing the downtime of each day.
+fn per_day(outages: &[Outage]) -> BTreeMap<NaiveDate, (usize, i64)> {
+    let mut days: BTreeMap<NaiveDate, (usize, i64)> = BTreeMap::new();
+    for o in outages {
+        let mut start = o.start;
+        let mut first = true;
+        while start < o.end {
+            let date = match local_date(start) {
+                Some(d) => d,
+                None => break,
+            };
+            let next_midnight = date
+                .succ_opt()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .and_then(|d| Local.from_local_datetime(&d).earliest())
+                .map(|d| d.timestamp())
+                .unwrap_or(o.end);
+            let end = o.end.min(next_midnight);
+            let day = days.entry(date).or_default();
+            if first {
+                day.0 += 1;
+                first = false;
+            }
+            day.1 += end - start;
+            start = end;
+        }
+    }
+    days
+}
+
+/// Each outage with its duration and cause, then the downtime per day.
+pub fn render_table(outages: &[Outage]) -> String {
+    if outages.is_empty() {
+        return "No outages recorded\n".to_string();
+    }
+    let mut out = String::new();
+    let _ = writeln!(out, "{:<20} {:>9}  Cause", "Start", "Duration");
+    for o in outages {
+        let _ = writeln!(
+            out,
+            "{:<20} {:>9}  {}",
+            format_time(o.start),
+            format_duration(o.end - o.start),
+            o.cause.map(|c| c.label()).unwrap_or("-")
+        );
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "{:<10} {:>7} {:>9} {:>8}",
+        "Day", "Outages", "Down", "Uptime"
+    );
+    let mut total = 0;
+    for (date, (count, down)) in per_day(outages) {
+        total += down;
+        let uptime = 100.0 * (1.0 - down as f64 / 86400.0);
+        let _ = writeln!(
+            out,
+            "{:<10} {:>7} {:>9} {:>7.2}%",
+            date.format("%Y-%m-%d"),
+            count,
+            format_duration(down),
+            uptime
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{} outage(s), {} down in total",
+        outages.len(),
+        format_duration(total)
+    );
+    out
+}
+
+fn format_time(t: i64) -> String {
+    match Local.timestamp_opt(t, 0).single() {
+        Some(d) => d.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => t.to_string(),
+    }
+}
+
+/// e.g. `4m 10s` or `2h 5m`.
+fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
@@ -0,0 +1,248 @@
+use crate::config::{Config, OutputFormat};
+use crate::history::format_timestamp;
+use crate::ipinfo::IpInfo;
+use crate::{format_age, Fast};
+use chrono::{Local, TimeZone};
+use log::error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Serializes the full measurement, flagging whether it came from the buffer.
+pub fn render_json(info: &Fast, pretty: bool) -> String {
+    let mut value = match serde_json::to_value(info) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to convert to JSON: {}", e);
+            return String::new();
+        }
+    };
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("cacheHit".to_string(), serde_json::Value::Bool(info.cached));
+        obj.insert("stale".to_string(), serde_json::Value::Bool(info.stale));
+    }
+    let json = if pretty {
+        serde_json::to_string_pretty(&value)
+    } else {
+        serde_json::to_string(&value)
+    };
+    match json {
+        Ok(j) => j,
+        Err(e) => {
+            error!("Failed to convert to JSON: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// Builds an Argos/xbar (BitBar) plugin: `line` goes in the menu bar, the
+/// details, recent `history` and actions in its dropdown. `exe` is run with
+/// `measure` to test on demand.
+pub fn render_argos(line: &str, info: &Fast, history: &[Fast], exe: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", line.replace('|', "/"));
+    let _ = writeln!(out, "---");
+    let _ = writeln!(out, "Download {} Mbps", info.download_speed);
+    if let Some(up) = info.upload_speed {
+        let _ = writeln!(out, "Upload {} Mbps", up);
+    }
+    let _ = writeln!(out, "Latency {} ms", info.latency);
+    if info.timestamp.is_some() {
+        let _ = writeln!(out, "Taken {}", format_timestamp(info.timestamp));
+    }
+    if !history.is_empty() {
+        let _ = writeln!(out, "History");
+        for e in history.iter().rev() {
+            let up = match e.upload_speed {
+                Some(u) => format!(" / {}", u),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                out,
+                "--{}  {}{} Mbps  {} ms | font=monospace",
+                format_timestamp(e.timestamp),
+                e.download_speed,
+                up,
+                e.latency
+            );
+        }
+    }
+    let _ = writeln!(out, "---");
+    let _ = writeln!(
+        out,
+        "Run test now | bash='{}' param1=measure param2=--force terminal=false refresh=true",
+        exe
+    );
+    let _ = writeln!(out, "Open fast.com | href=https://fast.com");
+    let _ = writeln!(out, "Open speedtest.net | href=https://www.speedtest.net");
+    out
+}
+
+/// Multi-line summary of the measurement for tooltips and notifications.
+/// `ip_info` fills in what the backend didn't report.
+pub fn render_detail(info: &Fast, ip_info: Option<&IpInfo>) -> String {
+    let mut out = String::new();
+    if let Some(t) = info.timestamp {
+        let taken = match Local.timestamp_opt(t, 0).single() {
+            Some(d) => d.format("%Y-%m-%d %H:%M").to_string(),
+            None => t.to_string(),
+        };
+        let age = (Local::now().timestamp() - t).max(0) as u64;
+        let stale = if info.stale { ", stale" } else { "" };
+        let _ = writeln!(
+            out,
+            "Last test: {} ({} ago{})",
+            taken,
+            format_age(age),
+            stale
+        );
+    }
+    if let Some(b) = info.backend {
+        let _ = writeln!(out, "Backend: {}", b.name());
+    }
+    if let Some(s) = &info.server {
+        let _ = writeln!(out, "Server: {}", s);
+    }
+    if let Some(i) = &info.interface {
+        let _ = writeln!(out, "Interface: {}", i);
+    }
+    let _ = writeln!(out, "Download: {} Mbps", info.download_speed);
+    if let Some(up) = info.upload_speed {
+        let _ = writeln!(out, "Upload: {} Mbps", up);
+    }
+    let _ = writeln!(out, "Latency: {} ms", info.latency);
+    if let Some(spread) = info.spread {
+        let _ = writeln!(out, "Jitter: \u{b1}{} ms", spread.latency);
+    }
+    if let Some(isp) = ip_info.and_then(|i| i.isp.as_deref()) {
+        let _ = writeln!(out, "ISP: {}", isp);
+    }
+    if let Some(ip) = info.user_ip.as_deref().or(ip_info.map(|i| i.ip.as_str())) {
+        let _ = writeln!(out, "Public IP: {}", ip);
+    }
+    let location = info
+        .user_location
+        .as_deref()
+        .or(ip_info.and_then(|i| i.city.as_deref()));
+    if let Some(l) = location {
+        let _ = writeln!(out, "Location: {}", l);
+    }
+    out.trim_end().to_string()
+}
+
+/// A Waybar custom module's JSON, with the Pango marked up `line` and the
+/// detail as its tooltip.
+pub fn render_waybar(line: &str, detail: &str, stale: bool) -> String {
+    let tooltip = detail
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let module = serde_json::json!({
+        "text": line,
+        "tooltip": tooltip,
+        "class": if stale { "stale" } else { "fresh" },
+    });
+    module.to_string()
+}
+
+/// Wraps a bar line the way the configured output needs it, without a
+/// tooltip for waybar.
+pub fn wrap_line(config: &Config, line: &str) -> String {
+    match config.output {
+        OutputFormat::Swaybar => render_swaybar(line),
+        OutputFormat::Waybar => render_waybar(line, "", false),
+        _ => line.to_string(),
+    }
+}
+
+/// Wraps a Pango marked up line in a swaybar protocol status line.
+pub fn render_swaybar(line: &str) -> String {
+    let blocks = serde_json::json!([{
+        "name": "internet-speed",
+        "full_text": line,
+        "markup": "pango",
+    }]);
+    blocks.to_string()
+}
+
+/// `name` as a valid Prometheus label name, `my-host` as `my_host` and
+/// `1site` as `_1site`.
+fn label_name(name: &str) -> String {
+    let mut label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        label.insert(0, '_');
+    }
+    label
+}
+
+pub fn render_prometheus(info: &Fast, age: u64) -> String {
+    let labels: Vec<String> = info
+        .labels
+        .iter()
+        .map(|(k, v)| {
+            let v = v
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", label_name(k), v)
+        })
+        .collect();
+    let labels = if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    };
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, value: f64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    };
+    gauge(
+        "internet_download_mbps",
+        "Measured download speed in Mbps.",
+        info.download_speed as f64,
+    );
+    if let Some(up) = info.upload_speed {
+        gauge(
+            "internet_upload_mbps",
+            "Measured upload speed in Mbps.",
+            up as f64,
+        );
+    }
+    gauge(
+        "internet_latency_ms",
+        "Measured latency in milliseconds.",
+        info.latency as f64,
+    );
+    gauge(
+        "internet_measurement_age_seconds",
+        "Seconds since the last measurement.",
+        age as f64,
+    );
+    out
+}
+
+/// Writes the metrics for node_exporter's textfile collector. The file is
+/// renamed into place so the collector never reads a partial write.
+pub fn write_prom_textfile(path: &Path, info: &Fast, age: u64) -> Result<(), String> {
+    let tmp = path.with_extension(format!("prom.{}.tmp", std::process::id()));
+    if let Err(e) = fs::write(&tmp, render_prometheus(info, age)) {
+        return Err(format!("Failed to write '{}': {}", tmp.display(), e));
+    }
+    match fs::rename(&tmp, path) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(format!(
+                "Failed to move '{}' to '{}': {}",
+                tmp.display(),
+                path.display(),
+                e
+            ))
+        }
+    }
+}
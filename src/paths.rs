@@ -0,0 +1,36 @@
+use directories::BaseDirs;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Returns `$var` when set so XDG setups keep working everywhere, otherwise
+/// the platform's directory: `~/.cache` on Linux, `~/Library/Caches` on macOS
+/// and `%LOCALAPPDATA%` on Windows, and likewise for the others.
+fn base_dir(var: &str, platform: fn(&BaseDirs) -> &Path) -> Result<PathBuf, String> {
+    if let Ok(x) = env::var(var) {
+        return Ok(PathBuf::from(x));
+    }
+    match BaseDirs::new() {
+        Some(b) => Ok(platform(&b).to_path_buf()),
+        None => Err(format!("Failed to get {} or the home directory", var)),
+    }
+}
+
+pub fn cache_dir() -> Result<PathBuf, String> {
+    base_dir("XDG_CACHE_HOME", BaseDirs::cache_dir)
+}
+
+pub fn config_dir() -> Result<PathBuf, String> {
+    base_dir("XDG_CONFIG_HOME", BaseDirs::config_dir)
+}
+
+pub fn data_dir() -> Result<PathBuf, String> {
+    base_dir("XDG_DATA_HOME", BaseDirs::data_dir)
+}
+
+/// Logs and the like, `~/.local/state` on Linux and the local data directory
+/// elsewhere since other platforms have no state directory.
+pub fn state_dir() -> Result<PathBuf, String> {
+    base_dir("XDG_STATE_HOME", |b| {
+        b.state_dir().unwrap_or(b.data_local_dir())
+    })
+}
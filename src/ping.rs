@@ -0,0 +1,312 @@
+use crate::config::{Aggregate, DualStackConfig, LegsConfig, PingConfig, QuickConfig};
+use crate::netdev;
+use log::{info, warn};
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::{self, TcpSocket, TcpStream};
+use tokio::time;
+
+/// Stands for the default route's gateway in target addresses.
+const GATEWAY: &str = "gateway";
+const DEFAULT_PORT: u16 = 443;
+
+#[derive(Debug, Clone)]
+pub struct PingResult {
+    pub name: String,
+    /// Round trip in milliseconds, `None` when the target didn't answer
+    pub latency: Option<u32>,
+}
+
+/// Resolves `host[:port]`, where host may be `gateway`.
+pub async fn resolve(address: &str) -> Result<Vec<SocketAddr>, String> {
+    let (host, port) = match address.rsplit_once(':') {
+        // Bare IPv6 addresses contain colons too
+        Some((h, p)) if !h.contains(':') || h.ends_with(']') => match p.parse() {
+            Ok(p) => (h.trim_matches(|c| c == '[' || c == ']').to_string(), p),
+            Err(_) => (address.to_string(), DEFAULT_PORT),
+        },
+        _ => (address.to_string(), DEFAULT_PORT),
+    };
+    let host = if host == GATEWAY {
+        netdev::default_gateway()?.to_string()
+    } else {
+        host
+    };
+    let addrs = net::lookup_host((host.as_str(), port)).await;
+    match addrs {
+        Ok(a) => Ok(a.collect()),
+        Err(e) => Err(format!("Failed to resolve '{}': {}", address, e)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bind_device(socket: &TcpSocket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_device(_: &TcpSocket, interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("binding to '{}' is only supported on Linux", interface),
+    ))
+}
+
+async fn connect(addr: &SocketAddr, interface: Option<&str>) -> io::Result<TcpStream> {
+    let interface = match interface {
+        Some(i) => i,
+        None => return TcpStream::connect(addr).await,
+    };
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    bind_device(&socket, interface)?;
+    socket.connect(*addr).await
+}
+
+/// Times a TCP handshake, which needs no privileges unlike ICMP. A refused
+/// connection still took a full round trip, so it counts as an answer.
+pub async fn probe_addr(addr: &SocketAddr, timeout: Duration) -> Result<Duration, String> {
+    probe_addr_via(addr, timeout, None).await
+}
+
+/// Like `probe_addr`, through `interface` when given.
+pub async fn probe_addr_via(
+    addr: &SocketAddr,
+    timeout: Duration,
+    interface: Option<&str>,
+) -> Result<Duration, String> {
+    let start = Instant::now();
+    match time::timeout(timeout, connect(addr, interface)).await {
+        Ok(Ok(_)) => Ok(start.elapsed()),
+        Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(start.elapsed()),
+        Ok(Err(e)) => Err(format!("Failed to reach '{}': {}", addr, e)),
+        Err(_) => Err(format!("Timed out reaching '{}'", addr)),
+    }
+}
+
+/// Probes `address` `count` times, returning the average.
+pub async fn probe(address: &str, count: u32, timeout: Duration) -> Result<u32, String> {
+    probe_via(address, count, timeout, None).await
+}
+
+/// Like `probe`, through `interface` when given.
+pub async fn probe_via(
+    address: &str,
+    count: u32,
+    timeout: Duration,
+    interface: Option<&str>,
+) -> Result<u32, String> {
+    let addr = match resolve(address).await?.into_iter().next() {
+        Some(a) => a,
+        None => {
+            return Err(format!("No address found for '{}'", address));
+        }
+    };
+    let mut total = Duration::ZERO;
+    for _ in 0..count.max(1) {
+        total += probe_addr_via(&addr, timeout, interface).await?;
+    }
+    Ok((total.as_secs_f64() * 1000.0 / count.max(1) as f64).round() as u32)
+}
+
+/// Like `probe`, logging failures as a missing answer.
+async fn probe_or_none(
+    address: &str,
+    count: u32,
+    timeout: Duration,
+    interface: Option<&str>,
+) -> Option<u32> {
+    match probe_via(address, count, timeout, interface).await {
+        Ok(l) => Some(l),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    }
+}
+
+/// Probes every configured target concurrently, through `interface` when
+/// given.
+pub async fn probe_targets(config: &PingConfig, interface: Option<&str>) -> Vec<PingResult> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let handles: Vec<_> = config
+        .targets
+        .iter()
+        .map(|t| {
+            let target = t.clone();
+            let count = config.count;
+            let interface = interface.map(str::to_string);
+            tokio::spawn(async move {
+                let interface = interface.as_deref();
+                let latency = probe_or_none(&target.address, count, timeout, interface).await;
+                PingResult {
+                    name: target.name,
+                    latency,
+                }
+            })
+        })
+        .collect();
+    let mut results = Vec::with_capacity(handles.len());
+    for h in handles {
+        if let Ok(r) = h.await {
+            results.push(r);
+        }
+    }
+    info!("Ping results: {:?}", results);
+    results
+}
+
+/// Worst or average latency of the targets that answered.
+pub fn aggregate(results: &[PingResult], how: Aggregate) -> Option<u32> {
+    let latencies: Vec<u32> = results.iter().filter_map(|r| r.latency).collect();
+    if latencies.is_empty() {
+        return None;
+    }
+    match how {
+        Aggregate::Worst => latencies.iter().max().copied(),
+        Aggregate::Average => Some(latencies.iter().sum::<u32>() / latencies.len() as u32),
+    }
+}
+
+/// What `--quick` measured in place of a speed test.
+#[derive(Debug, Clone, Copy)]
+pub struct Quick {
+    pub latency: Option<u32>,
+    /// Mean difference between consecutive round trips, in milliseconds
+    pub jitter: Option<u32>,
+    /// Percent of the probes that got no answer
+    pub loss: u32,
+}
+
+/// Round trips to `address` in milliseconds, `None` for each lost probe.
+async fn sample(
+    address: String,
+    count: u32,
+    timeout: Duration,
+    interface: Option<String>,
+) -> Vec<Option<f64>> {
+    let addr = match resolve(&address).await.map(|a| a.into_iter().next()) {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            warn!("No address found for '{}'", address);
+            return vec![None; count as usize];
+        }
+        Err(e) => {
+            warn!("{}", e);
+            return vec![None; count as usize];
+        }
+    };
+    let mut samples = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        match probe_addr_via(&addr, timeout, interface.as_deref()).await {
+            Ok(d) => samples.push(Some(d.as_secs_f64() * 1000.0)),
+            Err(e) => {
+                warn!("{}", e);
+                samples.push(None);
+            }
+        }
+    }
+    samples
+}
+
+/// Probes every quick target `count` times, the targets concurrently.
+pub async fn probe_quick(config: &QuickConfig, interface: Option<&str>) -> Quick {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let count = config.count.max(1);
+    let handles: Vec<_> = config
+        .targets
+        .iter()
+        .map(|t| {
+            tokio::spawn(sample(
+                t.clone(),
+                count,
+                timeout,
+                interface.map(str::to_string),
+            ))
+        })
+        .collect();
+    let mut results = Vec::new();
+    let mut jitters = Vec::new();
+    let (mut sent, mut lost) = (0, 0);
+    for (target, h) in config.targets.iter().zip(handles) {
+        let samples = h.await.unwrap_or_default();
+        sent += samples.len();
+        lost += samples.iter().filter(|s| s.is_none()).count();
+        let answered: Vec<f64> = samples.into_iter().flatten().collect();
+        let latency = if answered.is_empty() {
+            None
+        } else {
+            Some((answered.iter().sum::<f64>() / answered.len() as f64).round() as u32)
+        };
+        if answered.len() > 1 {
+            let diffs: f64 = answered.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+            jitters.push(diffs / (answered.len() - 1) as f64);
+        }
+        results.push(PingResult {
+            name: target.clone(),
+            latency,
+        });
+    }
+    let quick = Quick {
+        latency: aggregate(&results, config.aggregate),
+        jitter: match jitters.len() {
+            0 => None,
+            n => Some((jitters.iter().sum::<f64>() / n as f64).round() as u32),
+        },
+        loss: (100 * lost).checked_div(sent).unwrap_or(0) as u32,
+    };
+    info!("Quick probes: {:?}", quick);
+    quick
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Legs {
+    pub gateway: Option<u32>,
+    pub internet: Option<u32>,
+}
+
+impl Legs {
+    /// The local leg is at fault when the gateway is slow or unreachable.
+    pub fn gateway_is_bad(&self, config: &LegsConfig) -> bool {
+        match self.gateway {
+            Some(l) => l > config.gateway_max_latency,
+            None => true,
+        }
+    }
+}
+
+/// Measures latency to the gateway and to an internet host concurrently, to
+/// tell local network problems apart from ISP ones.
+pub async fn probe_legs(config: &LegsConfig) -> Legs {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let (gateway, internet) = tokio::join!(
+        probe_or_none(&config.gateway, config.count, timeout, None),
+        probe_or_none(&config.internet, config.count, timeout, None)
+    );
+    let legs = Legs { gateway, internet };
+    info!("Latency legs: {:?}", legs);
+    legs
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Families {
+    pub ipv4: Option<u32>,
+    pub ipv6: Option<u32>,
+}
+
+/// Probes an IPv4 and an IPv6 target concurrently, so broken IPv6 on a dual
+/// stack network doesn't go unnoticed.
+pub async fn probe_families(config: &DualStackConfig) -> Families {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let (ipv4, ipv6) = tokio::join!(
+        probe_or_none(&config.ipv4, 1, timeout, None),
+        probe_or_none(&config.ipv6, 1, timeout, None)
+    );
+    let families = Families { ipv4, ipv6 };
+    info!("Address families: {:?}", families);
+    families
+}
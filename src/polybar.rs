@@ -0,0 +1,26 @@
+use crate::config::PolybarIpcConfig;
+use tokio::process::Command;
+
+/// Triggers the module's hook so the bar shows a new measurement right away.
+pub async fn send_hook(config: &PolybarIpcConfig) -> Result<(), String> {
+    let action = format!("#{}.hook.{}", config.module, config.hook);
+    let output = match Command::new(&config.polybar_msg)
+        .args(["action", &action])
+        .output()
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(format!(
+                "Failed to execute '{}': {}",
+                config.polybar_msg.display(),
+                e
+            ));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("polybar-msg failed: {}", stderr.trim()));
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use crate::config::QuietConfig;
+use crate::{get_cache_filename, schedule};
+use chrono::{Local, TimeZone};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// Exists while toggled away from the quiet hours
+const TOGGLE_FILE_PATH: &str = ".polybar-internet-speed-quiet";
+
+/// A toggle overriding the quiet hours until they next start or end.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct Toggle {
+    on: bool,
+    /// Unix timestamp the override lasts until, forever without hours
+    until: Option<i64>,
+}
+
+impl Default for Toggle {
+    // An empty file from older versions meant on
+    fn default() -> Self {
+        Toggle {
+            on: true,
+            until: None,
+        }
+    }
+}
+
+fn toggled() -> Option<Toggle> {
+    let path = get_cache_filename(TOGGLE_FILE_PATH).ok()?;
+    let toggle: Toggle = toml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    match toggle.until {
+        Some(until) if Local::now().timestamp() >= until => None,
+        _ => Some(toggle),
+    }
+}
+
+fn scheduled(config: &QuietConfig) -> bool {
+    schedule::in_any(&config.hours, Local::now().time())
+}
+
+/// Whether the module should render `quiet.output` instead of the
+/// measurement, during quiet hours or while toggled on.
+pub fn is_quiet(config: &QuietConfig) -> bool {
+    match toggled() {
+        Some(t) => t.on,
+        None => scheduled(config),
+    }
+}
+
+/// Flips whether it's quiet, returning whether it's now on. During quiet
+/// hours that lasts until they end, otherwise until they start.
+pub fn toggle(config: &QuietConfig) -> Result<bool, String> {
+    let path = get_cache_filename(TOGGLE_FILE_PATH)?;
+    let on = !is_quiet(config);
+    let result = if on == scheduled(config) {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    } else {
+        let now = Local::now().naive_local();
+        let until = schedule::next_change(&config.hours, now)
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map(|t| t.timestamp());
+        let toggle = Toggle { on, until };
+        match toml::to_string(&toggle) {
+            Ok(c) => fs::write(&path, c),
+            Err(e) => return Err(format!("Failed to serialize the toggle: {}", e)),
+        }
+    };
+    match result {
+        Ok(_) => {
+            info!("Quiet toggled {}", if on { "on" } else { "off" });
+            Ok(on)
+        }
+        Err(e) => Err(format!("Failed to toggle '{}': {}", path, e)),
+    }
+}
@@ -0,0 +1,168 @@
+use crate::config::{self, Config};
+use log::{debug, error, info, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::time;
+
+/// Editors write the file in several steps, give them time to finish
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Reapplies the command line flags on top of a reloaded config.
+pub type Overrides = Box<dyn Fn(&mut Config) + Send + Sync>;
+
+/// Watches the config file, handing out the new config after each change
+/// that parses. A config that doesn't parse is logged and skipped.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: UnboundedReceiver<()>,
+    current: toml::Value,
+    profile: Option<String>,
+    overrides: Overrides,
+}
+
+/// Parsed file contents, an empty table when it's missing or broken.
+fn read_value(path: &Path) -> toml::Value {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|c| toml::from_str(&c).ok())
+        .unwrap_or_else(|| toml::Value::Table(toml::map::Map::new()))
+}
+
+/// Keeps tokens and passwords out of the log.
+fn is_secret(path: &str) -> bool {
+    path.ends_with("token") || path.ends_with("password")
+}
+
+/// Collects every setting that differs between `old` and `new`.
+fn diff(old: &toml::Value, new: &toml::Value, path: &str, out: &mut Vec<String>) {
+    let (old, new) = match (old, new) {
+        (toml::Value::Table(o), toml::Value::Table(n)) => (o, n),
+        _ => {
+            if old != new && is_secret(path) {
+                out.push(format!("{} changed", path));
+            } else if old != new {
+                out.push(format!("{} changed from {} to {}", path, old, new));
+            }
+            return;
+        }
+    };
+    let key_path = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+    let empty = toml::Value::Table(toml::map::Map::new());
+    for (key, o) in old {
+        match new.get(key) {
+            Some(n) => diff(o, n, &key_path(key), out),
+            None if o.is_table() => diff(o, &empty, &key_path(key), out),
+            None => out.push(format!("{} unset", key_path(key))),
+        }
+    }
+    for (key, n) in new {
+        if old.contains_key(key) {
+            continue;
+        }
+        if n.is_table() {
+            diff(&empty, n, &key_path(key), out);
+        } else if is_secret(key) {
+            out.push(format!("{} set", key_path(key)));
+        } else {
+            out.push(format!("{} set to {}", key_path(key), n));
+        }
+    }
+}
+
+impl ConfigWatcher {
+    /// Watches the config file's directory, as editors often replace the
+    /// file instead of writing to it.
+    pub fn new(profile: Option<String>, overrides: Overrides) -> Result<ConfigWatcher, String> {
+        let path = config::get_config_filename()?;
+        let dir = match path.parent() {
+            Some(d) => d.to_path_buf(),
+            None => return Err(format!("'{}' has no directory", path.display())),
+        };
+        let (tx, events) = mpsc::unbounded_channel();
+        let file = path.clone();
+        let handler = move |event: notify::Result<Event>| match event {
+            Ok(e) if !matches!(e.kind, EventKind::Access(_)) && e.paths.contains(&file) => {
+                let _ = tx.send(());
+            }
+            Ok(_) => (),
+            Err(e) => warn!("Config watch failed: {}", e),
+        };
+        let mut watcher = match notify::recommended_watcher(handler) {
+            Ok(w) => w,
+            Err(e) => return Err(format!("Failed to watch the config: {}", e)),
+        };
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            return Err(format!("Failed to watch '{}': {}", dir.display(), e));
+        }
+        info!("Watching '{}' for changes", path.display());
+        Ok(ConfigWatcher {
+            current: read_value(&path),
+            path,
+            _watcher: watcher,
+            events,
+            profile,
+            overrides,
+        })
+    }
+
+    /// Waits for a change to the file that parses.
+    pub async fn changed(&mut self) -> Config {
+        loop {
+            if self.events.recv().await.is_none() {
+                // The watcher is gone, nothing will change anymore
+                std::future::pending::<()>().await;
+            }
+            time::sleep(SETTLE).await;
+            while self.events.try_recv().is_ok() {}
+            if let Some(c) = self.reload() {
+                return c;
+            }
+        }
+    }
+
+    /// The new config, when the file changed since the last call.
+    pub fn try_changed(&mut self) -> Option<Config> {
+        let mut changed = false;
+        while self.events.try_recv().is_ok() {
+            changed = true;
+        }
+        if !changed {
+            return None;
+        }
+        self.reload()
+    }
+
+    fn reload(&mut self) -> Option<Config> {
+        let mut config = match config::load(self.profile.as_deref()) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Keeping the current config, {}", e);
+                return None;
+            }
+        };
+        let value = read_value(&self.path);
+        let mut changes = Vec::new();
+        diff(&self.current, &value, "", &mut changes);
+        self.current = value;
+        if changes.is_empty() {
+            debug!(
+                "'{}' changed, none of its settings did",
+                self.path.display()
+            );
+            return None;
+        }
+        for change in &changes {
+            info!("Config reloaded, {}", change);
+        }
+        (self.overrides)(&mut config);
+        Some(config)
+    }
+}
@@ -0,0 +1,301 @@
+use crate::config::Config;
+use crate::outage::Outage;
+use crate::stats::percentile;
+use crate::Fast;
+use chrono::{Datelike, Local, NaiveDate, TimeZone, Timelike};
+use std::fmt::Write;
+
+/// Hours listed as the worst of the day
+const WORST_HOURS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Markdown,
+}
+
+/// First and one past the last second of a local `YYYY-MM` month.
+pub fn month_range(month: &str) -> Result<(i64, i64), String> {
+    let first = match NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(format!(
+                "Invalid month '{}', expected YYYY-MM: {}",
+                month, e
+            ))
+        }
+    };
+    let next = match first.month() {
+        12 => NaiveDate::from_ymd_opt(first.year() + 1, 1, 1),
+        m => NaiveDate::from_ymd_opt(first.year(), m + 1, 1),
+    };
+    let midnight = |d: Option<NaiveDate>| {
+        d.and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|d| Local.from_local_datetime(&d).earliest())
+            .map(|d| d.timestamp())
+    };
+    match (midnight(Some(first)), midnight(next)) {
+        (Some(s), Some(e)) => Ok((s, e)),
+        _ => Err(format!("Invalid local month '{}'", month)),
+    }
+}
+
+pub fn current_month() -> String {
+    Local::now().format("%Y-%m").to_string()
+}
+
+/// Writes headings and tables as Markdown or aligned plain text.
+struct Writer {
+    format: ReportFormat,
+    out: String,
+}
+
+impl Writer {
+    fn heading(&mut self, text: &str) {
+        match self.format {
+            ReportFormat::Markdown => {
+                let _ = writeln!(self.out, "## {}\n", text);
+            }
+            ReportFormat::Text => {
+                let _ = writeln!(self.out, "{}\n{}\n", text, "-".repeat(text.chars().count()));
+            }
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        match self.format {
+            ReportFormat::Markdown => {
+                let _ = writeln!(self.out, "- {}", text);
+            }
+            ReportFormat::Text => {
+                let _ = writeln!(self.out, "{}", text);
+            }
+        }
+    }
+
+    fn table(&mut self, rows: &[Vec<String>]) {
+        let columns = rows.first().map(Vec::len).unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in rows {
+            for (w, c) in widths.iter_mut().zip(row) {
+                *w = (*w).max(c.chars().count());
+            }
+        }
+        for (n, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (c, w))| match i {
+                    0 => format!("{:<w$}", c, w = w),
+                    _ => format!("{:>w$}", c, w = w),
+                })
+                .collect();
+            match self.format {
+                ReportFormat::Markdown => {
+                    let _ = writeln!(self.out, "| {} |", cells.join(" | "));
+                    if n == 0 {
+                        let rule: Vec<String> = widths
+                            .iter()
+                            .enumerate()
+                            .map(|(i, w)| match i {
+                                0 => "-".repeat(*w),
+                                _ => format!("{}:", "-".repeat(w.saturating_sub(1))),
+                            })
+                            .collect();
+                        let _ = writeln!(self.out, "| {} |", rule.join(" | "));
+                    }
+                }
+                ReportFormat::Text => {
+                    let _ = writeln!(self.out, "{}", cells.join("  ").trim_end());
+                }
+            }
+        }
+    }
+
+    fn blank(&mut self) {
+        self.out.push('\n');
+    }
+}
+
+fn average(values: &[u32]) -> f64 {
+    values.iter().map(|&v| v as f64).sum::<f64>() / values.len().max(1) as f64
+}
+
+/// Average, median, 5th percentile, min and max.
+fn metric_row(name: &str, values: &mut [u32]) -> Vec<String> {
+    if values.is_empty() {
+        return vec![
+            name.to_string(),
+            "-".into(),
+            "-".into(),
+            "-".into(),
+            "-".into(),
+            "-".into(),
+        ];
+    }
+    values.sort_unstable();
+    vec![
+        name.to_string(),
+        format!("{:.1}", average(values)),
+        percentile(values, 50.0).to_string(),
+        percentile(values, 5.0).to_string(),
+        values[0].to_string(),
+        values[values.len() - 1].to_string(),
+    ]
+}
+
+fn format_duration(secs: i64) -> String {
+    match secs.max(0) {
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+fn format_time(t: i64) -> String {
+    match Local.timestamp_opt(t, 0).single() {
+        Some(d) => d.format("%Y-%m-%d %H:%M").to_string(),
+        None => t.to_string(),
+    }
+}
+
+/// Speeds, shortfalls against the plan, downtime and the worst hours of the
+/// day over `month`, from the measurements and outages within it.
+pub fn render(
+    config: &Config,
+    month: &str,
+    range: (i64, i64),
+    entries: &[Fast],
+    outages: &[Outage],
+    format: ReportFormat,
+) -> String {
+    let (start, end) = range;
+    let mut w = Writer {
+        format,
+        out: String::new(),
+    };
+    let title = format!("Internet service report for {}", month);
+    match format {
+        ReportFormat::Markdown => {
+            let _ = writeln!(w.out, "# {}\n", title);
+        }
+        ReportFormat::Text => {
+            let _ = writeln!(w.out, "{}\n{}\n", title, "=".repeat(title.len()));
+        }
+    }
+    let plan = &config.plan;
+    match (plan.download, plan.upload) {
+        (Some(d), Some(u)) => w.line(&format!("Advertised plan: {} Mbps down, {} Mbps up", d, u)),
+        (Some(d), None) => w.line(&format!("Advertised plan: {} Mbps down", d)),
+        _ => (),
+    }
+    w.line(&format!(
+        "Measurements: {}, from {} to {}",
+        entries.len(),
+        format_time(start),
+        format_time(end - 1)
+    ));
+    w.blank();
+
+    w.heading("Speeds");
+    let mut down: Vec<u32> = entries.iter().map(|e| e.download_speed).collect();
+    let mut up: Vec<u32> = entries.iter().filter_map(|e| e.upload_speed).collect();
+    let mut latency: Vec<u32> = entries.iter().map(|e| e.latency).collect();
+    w.table(&[
+        ["Metric", "Average", "Median", "5th pct", "Min", "Max"]
+            .map(String::from)
+            .to_vec(),
+        metric_row("Download Mbps", &mut down),
+        metric_row("Upload Mbps", &mut up),
+        metric_row("Latency ms", &mut latency),
+    ]);
+    w.blank();
+
+    if !entries.is_empty() && (plan.download.is_some() || plan.upload.is_some()) {
+        w.heading("Against the plan");
+        let shortfalls = [
+            ("Download", plan.download, &down),
+            ("Upload", plan.upload, &up),
+        ];
+        for (name, advertised, values) in shortfalls {
+            let advertised = match advertised {
+                Some(a) if !values.is_empty() => a,
+                _ => continue,
+            };
+            let warn = advertised * plan.warn_percent / 100;
+            let below = values.iter().filter(|&&v| v < advertised).count();
+            let far_below = values.iter().filter(|&&v| v < warn).count();
+            let percent = |n: usize| 100.0 * n as f64 / values.len() as f64;
+            w.line(&format!(
+                "{}: {} of {} tests ({:.1}%) below the advertised {} Mbps, {} ({:.1}%) below {}% of it",
+                name,
+                below,
+                values.len(),
+                percent(below),
+                advertised,
+                far_below,
+                percent(far_below),
+                plan.warn_percent
+            ));
+        }
+        w.blank();
+    }
+
+    w.heading("Downtime");
+    let clipped: Vec<(i64, i64)> = outages
+        .iter()
+        .map(|o| (o.start.max(start), o.end.min(end)))
+        .filter(|(s, e)| s < e)
+        .collect();
+    let total: i64 = clipped.iter().map(|(s, e)| e - s).sum();
+    let availability = 100.0 * (1.0 - total as f64 / (end - start).max(1) as f64);
+    w.line(&format!(
+        "{} outage(s), {} down in total, {:.3}% available",
+        clipped.len(),
+        format_duration(total),
+        availability
+    ));
+    if let Some((s, e)) = clipped.iter().max_by_key(|(s, e)| e - s) {
+        w.line(&format!(
+            "Longest: {} from {}",
+            format_duration(e - s),
+            format_time(*s)
+        ));
+    }
+    w.blank();
+
+    w.heading("Worst hours");
+    let mut hours: Vec<(u32, Vec<&Fast>)> = (0..24).map(|h| (h, Vec::new())).collect();
+    for e in entries {
+        if let Some(t) = e.timestamp.and_then(|t| Local.timestamp_opt(t, 0).single()) {
+            hours[t.hour() as usize].1.push(e);
+        }
+    }
+    let mut hours: Vec<(u32, f64, f64, usize)> = hours
+        .into_iter()
+        .filter(|(_, es)| !es.is_empty())
+        .map(|(h, es)| {
+            let down: Vec<u32> = es.iter().map(|e| e.download_speed).collect();
+            let latency: Vec<u32> = es.iter().map(|e| e.latency).collect();
+            (h, average(&down), average(&latency), es.len())
+        })
+        .collect();
+    hours.sort_by(|a, b| a.1.total_cmp(&b.1));
+    if hours.is_empty() {
+        w.line("No measurements");
+    } else {
+        let mut rows = vec![["Hour", "Download Mbps", "Latency ms", "Tests"]
+            .map(String::from)
+            .to_vec()];
+        for (h, down, latency, n) in hours.iter().take(WORST_HOURS) {
+            rows.push(vec![
+                format!("{:02}:00-{:02}:00", h, (h + 1) % 24),
+                format!("{:.1}", down),
+                format!("{:.1}", latency),
+                n.to_string(),
+            ]);
+        }
+        w.table(&rows);
+    }
+    w.out
+}
@@ -0,0 +1,161 @@
+use crate::config::RouteConfig;
+use crate::get_cache_filename;
+use crate::ping;
+use crate::system::CommandRunner;
+use chrono::Local;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time;
+
+// The last diagnosis, removed once a measurement succeeds
+const ROUTE_FILE_PATH: &str = ".polybar-internet-speed-route.toml";
+
+/// Where the route to the internet died.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cause {
+    /// Not even the gateway answered, Wi-Fi or LAN trouble
+    Lan,
+    /// It died within the first `isp_hops` hops
+    Isp,
+    /// It died further out
+    Upstream,
+}
+
+impl Cause {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Cause::Lan => "lan",
+            Cause::Isp => "isp",
+            Cause::Upstream => "upstream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Diagnosis {
+    pub cause: Cause,
+    /// Each hop's address, empty when it didn't answer
+    pub hops: Vec<String>,
+    pub timestamp: i64,
+}
+
+/// Each hop's address from `traceroute -n` output, `None` for the silent
+/// ones.
+fn parse_hops(output: &str) -> Vec<Option<IpAddr>> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.next()?.parse::<u32>().ok()?;
+            Some(fields.next().and_then(|f| f.parse().ok()))
+        })
+        .collect()
+}
+
+/// The route dies after the last hop that answered.
+fn classify(hops: &[Option<IpAddr>], isp_hops: usize) -> Cause {
+    match hops.iter().rposition(Option::is_some) {
+        None => Cause::Lan,
+        Some(last) if last < isp_hops => Cause::Isp,
+        Some(_) => Cause::Upstream,
+    }
+}
+
+/// Runs a bounded traceroute to `target` and works out where it died.
+pub async fn trace(runner: &dyn CommandRunner, config: &RouteConfig) -> Result<Diagnosis, String> {
+    let mut command = Command::new(&config.traceroute);
+    command
+        .arg("-n")
+        .args(["-q", "1"])
+        .args(["-m", &config.max_hops.to_string()])
+        .args(["-w", &config.timeout_secs.to_string()])
+        .arg(&config.target);
+    // Every hop may wait out its timeout
+    let bound = Duration::from_secs(config.timeout_secs * config.max_hops as u64 + 5);
+    let output = match time::timeout(bound, runner.output(command)).await {
+        Ok(Ok(o)) => o,
+        Ok(Err(e)) => {
+            return Err(format!(
+                "Failed to execute '{}': {}",
+                config.traceroute.display(),
+                e
+            ));
+        }
+        Err(_) => {
+            return Err(format!("traceroute to '{}' timed out", config.target));
+        }
+    };
+    let hops = parse_hops(&String::from_utf8_lossy(&output.stdout));
+    if hops.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("traceroute failed:\n{}", stderr));
+    }
+    Ok(Diagnosis {
+        cause: classify(&hops, config.isp_hops),
+        hops: hops
+            .iter()
+            .map(|h| h.map(|a| a.to_string()).unwrap_or_default())
+            .collect(),
+        timestamp: Local::now().timestamp(),
+    })
+}
+
+/// After a failed measurement, checks whether the internet is reachable at
+/// all and traces where the route dies when it isn't. The diagnosis is kept
+/// for the error output.
+pub async fn diagnose(runner: &dyn CommandRunner, config: &RouteConfig) -> Option<Cause> {
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let address = format!("{}:{}", config.target, config.port);
+    if ping::probe(&address, 1, timeout).await.is_ok() {
+        info!("'{}' is reachable, the route is fine", address);
+        clear();
+        return None;
+    }
+    let diagnosis = match trace(runner, config).await {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+    info!(
+        "Route to '{}' dies at the {}, hops: {}",
+        config.target,
+        diagnosis.cause.label(),
+        diagnosis.hops.join(" ")
+    );
+    if let Err(e) = save(&diagnosis) {
+        warn!("{}", e);
+    }
+    Some(diagnosis.cause)
+}
+
+fn save(diagnosis: &Diagnosis) -> Result<(), String> {
+    let path = get_cache_filename(ROUTE_FILE_PATH)?;
+    let contents = match toml::to_string(diagnosis) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Failed to convert to TOML: {}", e)),
+    };
+    match fs::write(&path, contents) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// The diagnosis of the latest failure, none once a measurement succeeded.
+pub fn load() -> Option<Diagnosis> {
+    let path = get_cache_filename(ROUTE_FILE_PATH).ok()?;
+    let contents = fs::read_to_string(path).ok()?;
+    toml::from_str(&contents).ok()
+}
+
+pub fn clear() {
+    if let Ok(path) = get_cache_filename(ROUTE_FILE_PATH) {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::stats::percentile;
+use crate::Fast;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// Half the range of the samples kept after outlier rejection.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Spread {
+    pub download: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload: Option<u32>,
+    pub latency: u32,
+}
+
+/// Drops values further than `k` median absolute deviations from the median.
+pub fn reject_outliers(values: &[u32], k: f64) -> Vec<u32> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let median = percentile(&sorted, 50.0) as f64;
+    let mut deviations: Vec<u32> = sorted
+        .iter()
+        .map(|v| (*v as f64 - median).abs().round() as u32)
+        .collect();
+    deviations.sort_unstable();
+    let mad = percentile(&deviations, 50.0) as f64;
+    if mad == 0.0 {
+        return sorted;
+    }
+    sorted
+        .into_iter()
+        .filter(|v| (*v as f64 - median).abs() <= k * mad)
+        .collect()
+}
+
+fn median_and_spread(values: &[u32], k: f64) -> (u32, u32) {
+    let kept = reject_outliers(values, k);
+    if kept.len() < values.len() {
+        info!("Dropped outliers: {:?} kept {:?}", values, kept);
+    }
+    let median = percentile(&kept, 50.0);
+    let spread = match (kept.first(), kept.last()) {
+        (Some(min), Some(max)) => (max - min).div_ceil(2),
+        _ => 0,
+    };
+    (median, spread)
+}
+
+/// Merges several runs into one measurement holding the median of each
+/// metric.
+pub fn combine(mut samples: Vec<Fast>, k: f64) -> Option<Fast> {
+    if samples.len() <= 1 {
+        return samples.pop();
+    }
+    let downloads: Vec<u32> = samples.iter().map(|s| s.download_speed).collect();
+    let uploads: Vec<u32> = samples.iter().filter_map(|s| s.upload_speed).collect();
+    let latencies: Vec<u32> = samples.iter().map(|s| s.latency).collect();
+
+    let (download, download_spread) = median_and_spread(&downloads, k);
+    let (latency, latency_spread) = median_and_spread(&latencies, k);
+    let upload = if uploads.is_empty() {
+        None
+    } else {
+        Some(median_and_spread(&uploads, k))
+    };
+
+    let mut info = samples.swap_remove(0);
+    info.download_speed = download;
+    info.upload_speed = upload.map(|(u, _)| u);
+    info.latency = latency;
+    info.spread = Some(Spread {
+        download: download_spread,
+        upload: upload.map(|(_, s)| s),
+        latency: latency_spread,
+    });
+    Some(info)
+}
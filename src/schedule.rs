@@ -0,0 +1,62 @@
+use crate::config::ScheduleConfig;
+use chrono::{Days, NaiveDateTime, NaiveTime};
+use log::error;
+
+/// Parses a local `HH:MM-HH:MM` window.
+fn parse_window(window: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let (start, end) = match window.split_once('-') {
+        Some(p) => p,
+        None => {
+            return Err(format!("Invalid window '{}', expected HH:MM-HH:MM", window));
+        }
+    };
+    let parse = |t: &str| match NaiveTime::parse_from_str(t.trim(), "%H:%M") {
+        Ok(t) => Ok(t),
+        Err(e) => Err(format!(
+            "Invalid time '{}' in window '{}': {}",
+            t, window, e
+        )),
+    };
+    Ok((parse(start)?, parse(end)?))
+}
+
+/// Whether `time` falls in any of `windows`, logging the malformed ones.
+pub fn in_any(windows: &[String], time: NaiveTime) -> bool {
+    windows.iter().any(|w| match parse_window(w) {
+        // A window ending before it starts wraps past midnight
+        Ok((start, end)) if start <= end => start <= time && time < end,
+        Ok((start, end)) => time >= start || time < end,
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    })
+}
+
+/// The first time after `now` that `in_any` changes, the end of the
+/// current window or the start of the next, or `None` if it never does.
+pub fn next_change(windows: &[String], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let current = in_any(windows, now.time());
+    let mut edges: Vec<NaiveTime> = windows
+        .iter()
+        .filter_map(|w| parse_window(w).ok())
+        .flat_map(|(start, end)| [start, end])
+        .collect();
+    edges.sort();
+    edges.dedup();
+    let today = now.date();
+    let tomorrow = today.checked_add_days(Days::new(1))?;
+    [today, tomorrow]
+        .iter()
+        .flat_map(|d| edges.iter().map(move |t| d.and_time(*t)))
+        .filter(|t| *t > now)
+        .find(|t| in_any(windows, t.time()) != current)
+}
+
+/// Whether a new speed test may run at `time`.
+pub fn tests_allowed(config: &ScheduleConfig, time: NaiveTime) -> bool {
+    if !config.windows.is_empty() && !in_any(&config.windows, time) {
+        return false;
+    }
+    !in_any(&config.blackouts, time)
+}
@@ -0,0 +1,443 @@
+use crate::config::{Backend, Config};
+use crate::reload::{ConfigWatcher, Overrides};
+use crate::system::SystemClock;
+use crate::{get_buffered_internet_info, get_info, graph, history, output, store};
+use log::{error, info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::{signal, time};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Between the progress events of /run
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+const DASHBOARD: &str = include_str!("dashboard.html");
+/// Limits on the request line and headers, so a client can't hold a
+/// connection or memory
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most recent measurements the dashboard charts
+const DASHBOARD_HISTORY: usize = 500;
+
+pub struct ServeOptions {
+    pub prometheus: bool,
+    pub web: bool,
+    pub api: bool,
+}
+
+struct Server {
+    /// Follows the config file
+    config: watch::Receiver<Config>,
+    options: ServeOptions,
+    /// Set while a test requested through /refresh runs
+    testing: AtomicBool,
+}
+
+impl Server {
+    fn config(&self) -> Config {
+        self.config.borrow().clone()
+    }
+}
+
+/// Clears `testing` when dropped, so a panicking test doesn't leave it set.
+struct Testing(Arc<Server>);
+
+impl Drop for Testing {
+    fn drop(&mut self) {
+        self.0.testing.store(false, Ordering::SeqCst);
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        warn!("Failed to write response: {}", e);
+    }
+}
+
+async fn status(stream: &mut TcpStream, server: &Server) {
+    let measurement = match get_buffered_internet_info(&server.config()) {
+        Ok(i) => output::render_json(&i, false),
+        Err(_) => "null".to_string(),
+    };
+    let body = format!(
+        "{{\"testing\": {}, \"measurement\": {}}}",
+        server.testing.load(Ordering::SeqCst),
+        measurement
+    );
+    respond(stream, "200 OK", "application/json", &body).await
+}
+
+/// Decodes the `%XX` escapes of a query string value.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| percent_decode(v))
+}
+
+/// Without `since` only the most recent measurements are returned.
+async fn recent_history(stream: &mut TcpStream, server: &Server, query: &str) {
+    let since = match query_param(query, "since").map(|s| history::parse_since(&s)) {
+        Some(Ok(s)) => Some(s),
+        Some(Err(e)) => {
+            respond(stream, "400 Bad Request", "text/plain", &e).await;
+            return;
+        }
+        None => None,
+    };
+    let entries = store::open(&server.config()).and_then(|s| s.history(since));
+    let body = entries.and_then(|entries| {
+        let skip = match since {
+            Some(_) => 0,
+            None => entries.len().saturating_sub(DASHBOARD_HISTORY),
+        };
+        history::export_json(&entries[skip..])
+    });
+    match body {
+        Ok(b) => respond(stream, "200 OK", "application/json", &b).await,
+        Err(e) => respond(stream, "500 Internal Server Error", "text/plain", &e).await,
+    }
+}
+
+async fn graph_svg(stream: &mut TcpStream, server: &Server, query: &str) {
+    let config = server.config();
+    let days = match query_param(query, "days").map(|d| d.parse::<u32>()) {
+        Some(Ok(d)) => d,
+        Some(Err(e)) => {
+            let body = format!("Invalid days: {}", e);
+            respond(stream, "400 Bad Request", "text/plain", &body).await;
+            return;
+        }
+        None => config.graph.days,
+    };
+    let since = chrono::Local::now().timestamp() - days as i64 * 86400;
+    let size = (config.graph.width, config.graph.height);
+    let body = store::open(&config)
+        .and_then(|s| s.history(Some(since)))
+        .and_then(|entries| graph::render_svg(&entries, days, size));
+    match body {
+        Ok(b) => respond(stream, "200 OK", "image/svg+xml", &b).await,
+        Err(e) => respond(stream, "500 Internal Server Error", "text/plain", &e).await,
+    }
+}
+
+/// Starts a measurement in the background, unless one is already running.
+fn start_test(server: &Arc<Server>) -> bool {
+    if server.testing.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let testing = Testing(server.clone());
+    tokio::spawn(async move {
+        if let Err(e) = get_info(&testing.0.config(), true).await {
+            error!("{}", e);
+        }
+    });
+    true
+}
+
+/// Writes one server-sent event, false once the client is gone.
+async fn send_event(stream: &mut TcpStream, event: &str, data: &str) -> bool {
+    let message = format!("event: {}\ndata: {}\n\n", event, data);
+    stream.write_all(message.as_bytes()).await.is_ok()
+}
+
+/// Starts a measurement with the `backend` of the query, or the configured
+/// one, streaming its progress as server-sent events until the result is in.
+/// The test carries on if the client goes away. Refused unless a token
+/// protects it, as anyone reaching the server could start tests otherwise.
+async fn run_test(stream: &mut TcpStream, server: &Arc<Server>, query: &str) {
+    let mut config = server.config();
+    if config.serve.token.is_none() {
+        let body = "/run needs serve.token to be set\n";
+        respond(stream, "403 Forbidden", "text/plain", body).await;
+        return;
+    }
+    if let Some(name) = query_param(query, "backend") {
+        match Backend::from_name(&name) {
+            Some(b) => {
+                config.backend = b;
+                config.race = None;
+            }
+            None => {
+                let body = format!("Unknown backend '{}'\n", name);
+                respond(stream, "400 Bad Request", "text/plain", &body).await;
+                return;
+            }
+        }
+    }
+    if server.testing.swap(true, Ordering::SeqCst) {
+        respond(stream, "409 Conflict", "text/plain", "Already testing\n").await;
+        return;
+    }
+    let testing = Testing(server.clone());
+    let mut test = tokio::spawn(async move {
+        let _testing = testing;
+        get_info(&config, true).await
+    });
+    let headers = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if stream.write_all(headers.as_bytes()).await.is_err() {
+        return;
+    }
+    let start = Instant::now();
+    if !send_event(stream, "start", "{}").await {
+        return;
+    }
+    let mut progress =
+        time::interval_at(time::Instant::now() + PROGRESS_INTERVAL, PROGRESS_INTERVAL);
+    let result = loop {
+        tokio::select! {
+            joined = &mut test => break joined,
+            _ = progress.tick() => {
+                let data = format!("{{\"elapsed_secs\": {}}}", start.elapsed().as_secs());
+                if !send_event(stream, "progress", &data).await {
+                    info!("Client left /run, the test carries on");
+                    return;
+                }
+            }
+        }
+    };
+    let (event, data) = match result {
+        Ok(Ok(i)) => ("result", output::render_json(&i, false)),
+        Ok(Err(e)) => ("error", serde_json::json!({ "error": e }).to_string()),
+        Err(e) => {
+            error!("Test task failed: {}", e);
+            (
+                "error",
+                serde_json::json!({ "error": e.to_string() }).to_string(),
+            )
+        }
+    };
+    send_event(stream, event, &data).await;
+}
+
+/// Compares without returning early, so the time taken doesn't tell how
+/// much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Compares the `Authorization` header against the configured token, if any.
+fn authorized(config: &Config, authorization: Option<&str>) -> bool {
+    match &config.serve.token {
+        Some(t) => authorization
+            .and_then(|a| a.strip_prefix("Bearer "))
+            .is_some_and(|a| constant_time_eq(a.as_bytes(), t.as_bytes())),
+        None => true,
+    }
+}
+
+/// Reads the request line and the `Authorization` header, failing once
+/// they're over `MAX_HEAD_BYTES`.
+async fn read_head<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<(String, Option<String>), String> {
+    let mut remaining = MAX_HEAD_BYTES;
+    let mut read_line = async |line: &mut String| match reader.take(remaining).read_line(line).await
+    {
+        Ok(n) if n as u64 == remaining && !line.ends_with('\n') => {
+            Err("Request head too large".to_string())
+        }
+        Ok(n) => {
+            remaining -= n as u64;
+            Ok(n)
+        }
+        Err(e) => Err(format!("Failed to read request: {}", e)),
+    };
+    let mut request_line = String::new();
+    read_line(&mut request_line).await?;
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        if read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    Ok((request_line, authorization))
+}
+
+async fn handle(mut stream: TcpStream, server: Arc<Server>) {
+    let mut reader = BufReader::new(&mut stream);
+    let (request_line, authorization) =
+        match time::timeout(READ_TIMEOUT, read_head(&mut reader)).await {
+            Ok(Ok(h)) => h,
+            Ok(Err(e)) => {
+                warn!("{}", e);
+                return;
+            }
+            Err(_) => {
+                warn!("Timed out reading the request");
+                return;
+            }
+        };
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("GET").to_string();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    // The query may hold the dashboard's token
+    let redacted = if query.is_empty() { "" } else { "?…" };
+    info!("Request: {} {}{}", method, path, redacted);
+
+    let options = &server.options;
+    let api = options.api
+        && matches!(
+            path.as_str(),
+            "/status" | "/history" | "/graph.svg" | "/refresh" | "/run"
+        );
+    if api && !authorized(&server.config(), authorization.as_deref()) {
+        respond(
+            &mut stream,
+            "401 Unauthorized",
+            "text/plain",
+            "Unauthorized\n",
+        )
+        .await;
+        return;
+    }
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/") if options.web => {
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", DASHBOARD).await
+        }
+        ("GET", "/status") if options.api => status(&mut stream, &server).await,
+        ("GET", "/history") if options.api => recent_history(&mut stream, &server, &query).await,
+        ("POST", "/run") if options.api => run_test(&mut stream, &server, &query).await,
+        ("GET", "/graph.svg") if options.api => graph_svg(&mut stream, &server, &query).await,
+        ("POST", "/refresh") if options.api => {
+            if start_test(&server) {
+                respond(&mut stream, "202 Accepted", "text/plain", "Testing\n").await
+            } else {
+                respond(
+                    &mut stream,
+                    "409 Conflict",
+                    "text/plain",
+                    "Already testing\n",
+                )
+                .await
+            }
+        }
+        ("GET", "/metrics") if options.prometheus => {
+            // Scrapes only read the store, the refresher task does the testing
+            let measured =
+                store::open(&server.config()).and_then(|s| Ok((s.age(&SystemClock)?, s.latest()?)));
+            match measured {
+                Ok((age, i)) => {
+                    respond(
+                        &mut stream,
+                        "200 OK",
+                        "text/plain; version=0.0.4",
+                        &output::render_prometheus(&i, age),
+                    )
+                    .await
+                }
+                Err(e) => respond(&mut stream, "503 Service Unavailable", "text/plain", &e).await,
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "Not found\n").await,
+    }
+}
+
+/// Serves until interrupted, cancelling any measurement still running.
+pub async fn run_serve(
+    config: Config,
+    listen: &str,
+    options: ServeOptions,
+    profile: Option<String>,
+    overrides: Overrides,
+) -> Result<(), String> {
+    if !options.prometheus && !options.web && !options.api {
+        return Err("Nothing to serve, pass --prometheus, --web or --api".to_string());
+    }
+    let listener = match TcpListener::bind(listen).await {
+        Ok(l) => l,
+        Err(e) => {
+            return Err(format!("Failed to bind '{}': {}", listen, e));
+        }
+    };
+    info!("Listening on '{}'", listen);
+
+    let (sender, receiver) = watch::channel(config);
+    let reloader = match ConfigWatcher::new(profile, overrides) {
+        Ok(mut w) => Some(tokio::spawn(async move {
+            loop {
+                sender.send_replace(w.changed().await);
+            }
+        })),
+        Err(e) => {
+            warn!("Config changes need a restart, {}", e);
+            None
+        }
+    };
+    let refresh_config = receiver.clone();
+    let refresher = tokio::spawn(async move {
+        loop {
+            let config = refresh_config.borrow().clone();
+            if let Err(e) = get_info(&config, false).await {
+                error!("{}", e);
+            }
+            time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+
+    let server = Arc::new(Server {
+        config: receiver,
+        options,
+        testing: AtomicBool::new(false),
+    });
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((s, _)) => {
+                    tokio::spawn(handle(s, server.clone()));
+                }
+                Err(e) => warn!("Failed to accept connection: {}", e),
+            },
+            _ = signal::ctrl_c() => {
+                info!("Interrupted, shutting down");
+                break;
+            }
+        }
+    }
+    refresher.abort();
+    if let Some(r) = reloader {
+        r.abort();
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use crate::config::{
+    Config, InfluxConfig, MqttConfig, NotifyConfig, OutputFormat, StatsdConfig, WebhookConfig,
+};
+use crate::{influx, mqtt, notify, statsd, store, webhook, Fast};
+use log::error;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+pub type SinkFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+/// Somewhere a new measurement goes once it's taken.
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn send(&self, info: Arc<Fast>) -> SinkFuture;
+}
+
+/// The module line on stdout, or the textfile for `prom-textfile`. Runs the
+/// placeholders' probes when live.
+struct Bar(Config, HashMap<String, String>, bool);
+
+impl Sink for Bar {
+    fn name(&self) -> &'static str {
+        "bar"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let (config, values, live) = (self.0.clone(), self.1.clone(), self.2);
+        Box::pin(async move {
+            if config.output == OutputFormat::PromTextfile {
+                return crate::write_textfile(&config, &info);
+            }
+            println!(
+                "{}",
+                crate::render_output(&config, &info, values, live).await
+            );
+            Ok(())
+        })
+    }
+}
+
+/// The latest measurement in the store, which the bar shows until the next.
+struct Buffer(Config);
+
+impl Sink for Buffer {
+    fn name(&self) -> &'static str {
+        "buffer"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { store::open(&config)?.save(&info) })
+    }
+}
+
+struct History(Config);
+
+impl Sink for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { store::open(&config)?.append(&info) })
+    }
+}
+
+struct Notify(NotifyConfig);
+
+impl Sink for Notify {
+    fn name(&self) -> &'static str {
+        "notify"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { notify::notify_if_degraded(&config, &info) })
+    }
+}
+
+struct Influx(InfluxConfig);
+
+impl Sink for Influx {
+    fn name(&self) -> &'static str {
+        "influxdb"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { influx::push(&config, &info).await })
+    }
+}
+
+struct Mqtt(MqttConfig);
+
+impl Sink for Mqtt {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { mqtt::publish(&config, &info).await })
+    }
+}
+
+struct Statsd(StatsdConfig);
+
+impl Sink for Statsd {
+    fn name(&self) -> &'static str {
+        "statsd"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { statsd::send(&config, &info).await })
+    }
+}
+
+struct Webhook(WebhookConfig);
+
+impl Sink for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send(&self, info: Arc<Fast>) -> SinkFuture {
+        let config = self.0.clone();
+        Box::pin(async move { webhook::alert_if_degraded(&config, &info).await })
+    }
+}
+
+/// Where a measurement is shown, the bar unless `sinks.bar` is off.
+/// `values` are the quick probes' placeholders, `live` runs the others'.
+pub fn output(config: &Config, values: HashMap<String, String>, live: bool) -> Vec<Box<dyn Sink>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if config.sinks.bar {
+        sinks.push(Box::new(Bar(config.clone(), values, live)));
+    }
+    sinks
+}
+
+/// Every sink the config enables for a new measurement.
+pub fn pipeline(config: &Config) -> Vec<Box<dyn Sink>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if config.sinks.buffer {
+        sinks.push(Box::new(Buffer(config.clone())));
+    }
+    if config.history.enabled {
+        sinks.push(Box::new(History(config.clone())));
+    }
+    if config.notify.enabled {
+        sinks.push(Box::new(Notify(config.notify.clone())));
+    }
+    if let Some(c) = &config.influxdb {
+        sinks.push(Box::new(Influx(c.clone())));
+    }
+    if let Some(c) = &config.mqtt {
+        sinks.push(Box::new(Mqtt(c.clone())));
+    }
+    if let Some(c) = &config.statsd {
+        sinks.push(Box::new(Statsd(c.clone())));
+    }
+    if let Some(c) = &config.webhook {
+        sinks.push(Box::new(Webhook(c.clone())));
+    }
+    sinks
+}
+
+/// Sends `info` to all `sinks` at once, each in its own task so a failing
+/// or panicking one only logs its error. Returns whether they all succeeded.
+pub async fn send_all(sinks: &[Box<dyn Sink>], info: &Fast) -> bool {
+    let info = Arc::new(info.clone());
+    let mut tasks = JoinSet::new();
+    let mut succeeded = true;
+    for sink in sinks {
+        let (name, send) = (sink.name(), sink.send(info.clone()));
+        tasks.spawn(async move { (name, send.await) });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((_, Ok(_))) => continue,
+            Ok((name, Err(e))) => error!("The {} sink failed: {}", name, e),
+            Err(e) => error!("A sink task failed: {}", e),
+        }
+        succeeded = false;
+    }
+    succeeded
+}
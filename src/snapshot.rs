@@ -0,0 +1,144 @@
+use crate::config::SnapshotConfig;
+use crate::netdev;
+use crate::wifi;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+#[cfg(target_os = "linux")]
+const PROC_LOADAVG: &str = "/proc/loadavg";
+const SAMPLE_DURATION: Duration = Duration::from_secs(1);
+// USER_HZ is 100 on every mainstream Linux architecture
+const CLOCK_TICKS_PER_SEC: f64 = 100.0;
+const MAX_HEAVY_PROCESSES: usize = 5;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProcessUsage {
+    pub name: String,
+    pub cpu_percent: f32,
+}
+
+/// What the machine was doing right before a measurement, so odd results can
+/// be explained later.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub load_average: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wifi_signal: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_mbps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_mbps: Option<f64>,
+    pub heavy_processes: Vec<ProcessUsage>,
+}
+
+#[cfg(target_os = "linux")]
+fn read_load_average() -> Result<f32, String> {
+    let contents = match fs::read_to_string(PROC_LOADAVG) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", PROC_LOADAVG, e));
+        }
+    };
+    match contents.split_whitespace().next().map(|l| l.parse()) {
+        Some(Ok(l)) => Ok(l),
+        _ => Err(format!("Malformed '{}': '{}'", PROC_LOADAVG, contents)),
+    }
+}
+
+/// Windows has no load average and always reports 0.
+#[cfg(not(target_os = "linux"))]
+fn read_load_average() -> Result<f32, String> {
+    Ok(sysinfo::System::load_average().one as f32)
+}
+
+/// Returns cumulative CPU ticks per pid, along with the process name.
+fn read_process_ticks() -> HashMap<u32, (String, u64)> {
+    let mut ticks = HashMap::new();
+    let entries = match fs::read_dir("/proc") {
+        Ok(e) => e,
+        Err(_) => return ticks,
+    };
+    for entry in entries.flatten() {
+        let pid: u32 = match entry.file_name().to_string_lossy().parse() {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        // The name is in parenthesis and may contain spaces
+        let (open, close) = match (stat.find('('), stat.rfind(')')) {
+            (Some(o), Some(c)) if o < c => (o, c),
+            _ => continue,
+        };
+        let name = stat[open + 1..close].to_string();
+        let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+        // utime and stime are fields 14 and 15 of the whole line
+        if fields.len() < 13 {
+            continue;
+        }
+        let utime: u64 = fields[11].parse().unwrap_or(0);
+        let stime: u64 = fields[12].parse().unwrap_or(0);
+        ticks.insert(pid, (name, utime + stime));
+    }
+    ticks
+}
+
+pub async fn take_snapshot(config: &SnapshotConfig) -> Snapshot {
+    let mut snapshot = Snapshot::default();
+    match read_load_average() {
+        Ok(l) => snapshot.load_average = l,
+        Err(e) => warn!("{}", e),
+    }
+    if let Ok(w) = wifi::get_wifi_info(None) {
+        snapshot.wifi_signal = Some(w.signal);
+    }
+
+    let mut sampler = match netdev::default_interface() {
+        Ok(i) => Some(netdev::Sampler::new(&i)),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    };
+    if let Some(s) = sampler.as_mut() {
+        let _ = s.sample();
+    }
+    let before = read_process_ticks();
+    let start = Instant::now();
+    time::sleep(SAMPLE_DURATION).await;
+    let after = read_process_ticks();
+    let secs = start.elapsed().as_secs_f64();
+    if let Some(s) = sampler.as_mut() {
+        if let Ok(Some(r)) = s.sample() {
+            snapshot.rx_mbps = Some(r.rx_mbps);
+            snapshot.tx_mbps = Some(r.tx_mbps);
+        }
+    }
+
+    let mut heavy: Vec<ProcessUsage> = after
+        .iter()
+        .filter_map(|(pid, (name, ticks))| {
+            let prev = before.get(pid).map(|(_, t)| *t)?;
+            let cpu = ticks.saturating_sub(prev) as f64 / CLOCK_TICKS_PER_SEC / secs * 100.0;
+            if cpu < config.heavy_process_cpu_percent as f64 {
+                return None;
+            }
+            Some(ProcessUsage {
+                name: name.clone(),
+                cpu_percent: cpu as f32,
+            })
+        })
+        .collect();
+    heavy.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+    heavy.truncate(MAX_HEAVY_PROCESSES);
+    snapshot.heavy_processes = heavy;
+    info!("Environment snapshot: {:?}", snapshot);
+    snapshot
+}
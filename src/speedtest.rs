@@ -0,0 +1,114 @@
+use crate::config::SpeedtestConfig;
+use crate::system::CommandRunner;
+use crate::Fast;
+use log::{error, info};
+use serde::Deserialize;
+use tokio::process::Command;
+
+#[derive(Debug, Deserialize)]
+struct Ping {
+    latency: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Transfer {
+    /// Bytes per second
+    bandwidth: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Interface {
+    #[serde(rename = "externalIp")]
+    external_ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Server {
+    id: u64,
+    name: String,
+    location: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Result {
+    ping: Ping,
+    download: Transfer,
+    upload: Option<Transfer>,
+    interface: Option<Interface>,
+    server: Option<Server>,
+}
+
+fn to_mbps(bytes_per_sec: u64) -> u32 {
+    (bytes_per_sec as f64 * 8.0 / 1_000_000.0).round() as u32
+}
+
+/// Runs Ookla's `speedtest` CLI against `server`, or the one it picks. The
+/// process is killed if the measurement is cancelled.
+async fn run(
+    runner: &dyn CommandRunner,
+    server: Option<u64>,
+    interface: Option<&str>,
+) -> std::result::Result<Fast, String> {
+    let mut command = Command::new("speedtest");
+    command.args(["--format=json", "--accept-license", "--accept-gdpr"]);
+    if let Some(id) = server {
+        command.arg(format!("--server-id={}", id));
+    }
+    if let Some(i) = interface {
+        command.arg(format!("--interface={}", i));
+    }
+    let output = match runner.output(command).await {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(format!("Failed to execute speedtest: {}", e));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("\tCommand failed:\n{}", &stderr));
+    }
+
+    let o = String::from_utf8_lossy(&output.stdout);
+    info!("Command output: {}", o);
+    let r: Result = match serde_json::from_str(&o) {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(format!("Failed to parse JSON: {}", e));
+        }
+    };
+    Ok(Fast {
+        download_speed: to_mbps(r.download.bandwidth),
+        upload_speed: r.upload.map(|u| to_mbps(u.bandwidth)),
+        latency: r.ping.latency.round() as u32,
+        user_ip: r.interface.and_then(|i| i.external_ip),
+        server: r.server.as_ref().map(|s| match &s.location {
+            Some(l) => format!("{}, {}", s.name, l),
+            None => s.name.clone(),
+        }),
+        server_id: r.server.map(|s| s.id.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Tries the configured servers in order. Unless they're pinned, the CLI
+/// picks one itself once they all failed.
+pub async fn get_speedtest_info(
+    runner: &dyn CommandRunner,
+    config: &SpeedtestConfig,
+    interface: Option<&str>,
+) -> std::result::Result<Fast, String> {
+    let mut errors = Vec::new();
+    for id in &config.servers {
+        match run(runner, Some(*id), interface).await {
+            Ok(f) => return Ok(f),
+            Err(e) => {
+                error!("Server {} failed: {}", id, e);
+                errors.push(format!("{}: {}", id, e));
+            }
+        }
+    }
+    if config.pin && !config.servers.is_empty() {
+        return Err(format!("Every pinned server failed: {}", errors.join("; ")));
+    }
+    run(runner, None, interface).await
+}
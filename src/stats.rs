@@ -0,0 +1,116 @@
+use crate::Fast;
+use chrono::{Datelike, Local, TimeZone};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Period {
+    Day,
+    Week,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub min: u32,
+    pub median: u32,
+    pub p95: u32,
+    pub max: u32,
+}
+
+/// Nearest-rank percentile of an already sorted slice.
+pub fn percentile(sorted: &[u32], p: f64) -> u32 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn summarize(values: &mut [u32]) -> Option<Summary> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    Some(Summary {
+        min: values[0],
+        median: percentile(values, 50.0),
+        p95: percentile(values, 95.0),
+        max: values[values.len() - 1],
+    })
+}
+
+fn period_key(timestamp: i64, period: Period) -> Option<String> {
+    let t = Local.timestamp_opt(timestamp, 0).single()?;
+    Some(match period {
+        Period::Day => t.format("%Y-%m-%d").to_string(),
+        Period::Week => {
+            let w = t.iso_week();
+            format!("{}-W{:02}", w.year(), w.week())
+        }
+    })
+}
+
+/// Groups the measurements by day or ISO week, oldest first.
+pub fn group_by(entries: &[Fast], period: Period) -> BTreeMap<String, Vec<&Fast>> {
+    let mut groups: BTreeMap<String, Vec<&Fast>> = BTreeMap::new();
+    for e in entries {
+        if let Some(key) = e.timestamp.and_then(|t| period_key(t, period)) {
+            groups.entry(key).or_default().push(e);
+        }
+    }
+    groups
+}
+
+fn cell(summary: Option<Summary>) -> String {
+    match summary {
+        Some(s) => format!("{}/{}/{}/{}", s.min, s.median, s.p95, s.max),
+        None => "-".to_string(),
+    }
+}
+
+/// Renders min/median/p95/max of every metric per period as a table.
+pub fn render_table(entries: &[Fast], period: Period) -> String {
+    let mut rows = vec![[
+        "period".to_string(),
+        "n".to_string(),
+        "download Mbps".to_string(),
+        "upload Mbps".to_string(),
+        "latency ms".to_string(),
+    ]];
+    for (key, group) in group_by(entries, period) {
+        let mut down: Vec<u32> = group.iter().map(|e| e.download_speed).collect();
+        let mut up: Vec<u32> = group.iter().filter_map(|e| e.upload_speed).collect();
+        let mut latency: Vec<u32> = group.iter().map(|e| e.latency).collect();
+        rows.push([
+            key,
+            group.len().to_string(),
+            cell(summarize(&mut down)),
+            cell(summarize(&mut up)),
+            cell(summarize(&mut latency)),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (w, c) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(c.len());
+        }
+    }
+    let mut out = String::from("Values are min/median/p95/max\n");
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .enumerate()
+            .map(|(i, (c, w))| {
+                if i < 2 {
+                    format!("{:<w$}", c, w = w)
+                } else {
+                    format!("{:>w$}", c, w = w)
+                }
+            })
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
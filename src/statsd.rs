@@ -0,0 +1,83 @@
+use crate::config::{StatsdConfig, StatsdProtocol};
+use crate::{format, Fast};
+use chrono::Local;
+use log::info;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time;
+
+fn metrics(info: &Fast) -> Vec<(&'static str, u32)> {
+    let mut metrics = vec![("download", info.download_speed), ("latency", info.latency)];
+    if let Some(up) = info.upload_speed {
+        metrics.push(("upload", up));
+    }
+    metrics
+}
+
+/// A gauge per metric, one per line as StatsD accepts in a single datagram.
+fn statsd_lines(prefix: &str, info: &Fast) -> String {
+    metrics(info)
+        .iter()
+        .map(|(name, value)| format!("{}.{}:{}|g\n", prefix, name, value))
+        .collect()
+}
+
+/// Graphite's plaintext protocol, `<path> <value> <timestamp>` per line.
+fn graphite_lines(prefix: &str, info: &Fast, timestamp: i64) -> String {
+    metrics(info)
+        .iter()
+        .map(|(name, value)| format!("{}.{} {} {}\n", prefix, name, value, timestamp))
+        .collect()
+}
+
+async fn send_all(config: &StatsdConfig, addr: &str, info: &Fast) -> Result<(), String> {
+    let labels = info.labels.clone().into_iter().collect();
+    let prefix = format::render(&config.prefix, &labels);
+    let prefix = prefix.trim_end_matches('.');
+    match config.protocol {
+        StatsdProtocol::Statsd => {
+            let lines = statsd_lines(prefix, info);
+            let socket = match UdpSocket::bind("0.0.0.0:0").await {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Failed to open a UDP socket: {}", e)),
+            };
+            match socket.send_to(lines.as_bytes(), addr).await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(format!("Failed to send to StatsD '{}': {}", addr, e)),
+            }
+        }
+        StatsdProtocol::Graphite => {
+            let lines = graphite_lines(prefix, info, Local::now().timestamp());
+            let mut stream = match TcpStream::connect(addr).await {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Failed to connect to Graphite '{}': {}", addr, e)),
+            };
+            let result = match stream.write_all(lines.as_bytes()).await {
+                Ok(_) => stream.shutdown().await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(_) => Ok(()),
+                Err(e) => Err(format!("Failed to send to Graphite '{}': {}", addr, e)),
+            }
+        }
+    }
+}
+
+/// Sends the measurement's gauges, giving up after `timeout_secs`.
+pub async fn send(config: &StatsdConfig, info: &Fast) -> Result<(), String> {
+    let addr = format!("{}:{}", config.host, config.port());
+    let timeout = Duration::from_secs(config.timeout_secs);
+    match time::timeout(timeout, send_all(config, &addr, info)).await {
+        Ok(Ok(_)) => {
+            info!("Sent measurement to '{}'", addr);
+            Ok(())
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(format!(
+            "Timed out sending to '{}' after {:?}",
+            addr, timeout
+        )),
+    }
+}
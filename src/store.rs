@@ -0,0 +1,325 @@
+use crate::config::{Config, HistoryConfig, StoreBackend};
+use crate::system::Clock;
+use crate::{get_buffered_filename, get_seconds_since_file_modified, history, Fast};
+use log::error;
+use std::fs;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Measurements the memory store keeps, dropping the oldest
+const MEMORY_HISTORY: usize = 10000;
+
+/// Where the latest measurement and the history are kept.
+pub trait Store: Send {
+    /// The latest measurement, marked cached.
+    fn latest(&self) -> Result<Fast, String>;
+    /// Seconds since the latest measurement was saved.
+    fn age(&self, clock: &dyn Clock) -> Result<u64, String>;
+    fn save(&self, info: &Fast) -> Result<(), String>;
+    fn append(&self, info: &Fast) -> Result<(), String>;
+    /// Every measurement taken at or after `since` (unix time), oldest first.
+    fn history(&self, since: Option<i64>) -> Result<Vec<Fast>, String>;
+    /// Changes whenever the history does.
+    fn history_version(&self) -> u64;
+}
+
+pub fn open(config: &Config) -> Result<Box<dyn Store>, String> {
+    match config.store.backend {
+        StoreBackend::File => Ok(Box::new(FileStore::new(
+            get_buffered_filename()?,
+            &config.history,
+        ))),
+        StoreBackend::Sqlite => sqlite::open(config),
+        StoreBackend::Memory => Ok(Box::new(MemoryStore)),
+    }
+}
+
+/// The TOML buffer in the cache directory and the JSON lines history.
+pub struct FileStore {
+    buffer: String,
+    history: HistoryConfig,
+}
+
+impl FileStore {
+    pub fn new(buffer: String, history: &HistoryConfig) -> FileStore {
+        FileStore {
+            buffer,
+            history: history.clone(),
+        }
+    }
+}
+
+impl Store for FileStore {
+    fn latest(&self) -> Result<Fast, String> {
+        let contents = match fs::read_to_string(&self.buffer) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Failed to read file: {}", e)),
+        };
+        let value = match crate::check_buffer(&contents) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                // Keep the corrupt file around for debugging
+                let corrupt = format!("{}.corrupt", self.buffer);
+                if let Err(e2) = fs::rename(&self.buffer, &corrupt) {
+                    error!("Failed to move '{}' aside: {}", self.buffer, e2);
+                }
+                return Err(e);
+            }
+        };
+        // A newer version's buffer is left for it
+        let mut info = crate::parse_buffer(value)?;
+        info.cached = true;
+        Ok(info)
+    }
+
+    fn age(&self, clock: &dyn Clock) -> Result<u64, String> {
+        get_seconds_since_file_modified(&self.buffer, clock)
+    }
+
+    fn save(&self, info: &Fast) -> Result<(), String> {
+        crate::write_buffered_file(&self.buffer, info)
+    }
+
+    fn append(&self, info: &Fast) -> Result<(), String> {
+        history::append(&self.history, info)
+    }
+
+    fn history(&self, since: Option<i64>) -> Result<Vec<Fast>, String> {
+        history::load(&self.history, since)
+    }
+
+    fn history_version(&self) -> u64 {
+        history::get_history_filename(&self.history)
+            .ok()
+            .and_then(|p| fs::metadata(p).ok())
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Default)]
+struct Memory {
+    /// With the unix time it was saved
+    latest: Option<(Fast, i64)>,
+    history: Vec<Fast>,
+    appended: u64,
+}
+
+static MEMORY: Mutex<Option<Memory>> = Mutex::new(None);
+
+fn with_memory<T>(f: impl FnOnce(&mut Memory) -> T) -> T {
+    let mut memory = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    f(memory.get_or_insert_with(Memory::default))
+}
+
+/// Keeps everything in the process only, for a daemon or server that is
+/// scraped rather than read from disk.
+pub struct MemoryStore;
+
+impl Store for MemoryStore {
+    fn latest(&self) -> Result<Fast, String> {
+        with_memory(|m| match &m.latest {
+            Some((info, _)) => {
+                let mut info = info.clone();
+                info.cached = true;
+                Ok(info)
+            }
+            None => Err("No measurement taken yet".to_string()),
+        })
+    }
+
+    fn age(&self, clock: &dyn Clock) -> Result<u64, String> {
+        let saved = with_memory(|m| m.latest.as_ref().map(|(_, t)| *t));
+        match saved {
+            Some(t) => Ok((clock.now().timestamp() - t).max(0) as u64),
+            None => Err("No measurement taken yet".to_string()),
+        }
+    }
+
+    fn save(&self, info: &Fast) -> Result<(), String> {
+        let now = chrono::Local::now().timestamp();
+        with_memory(|m| m.latest = Some((info.clone(), now)));
+        Ok(())
+    }
+
+    fn append(&self, info: &Fast) -> Result<(), String> {
+        with_memory(|m| {
+            m.history.push(info.clone());
+            let extra = m.history.len().saturating_sub(MEMORY_HISTORY);
+            m.history.drain(..extra);
+            m.appended += 1;
+        });
+        Ok(())
+    }
+
+    fn history(&self, since: Option<i64>) -> Result<Vec<Fast>, String> {
+        Ok(with_memory(|m| {
+            m.history
+                .iter()
+                .filter(|e| match (since, e.timestamp) {
+                    (Some(s), Some(t)) => t >= s,
+                    _ => true,
+                })
+                .cloned()
+                .collect()
+        }))
+    }
+
+    fn history_version(&self) -> u64 {
+        with_memory(|m| m.appended)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::Store;
+    use crate::config::Config;
+    use crate::history::get_data_filename;
+    use crate::system::Clock;
+    use crate::Fast;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::fs;
+
+    const DATABASE_FILE_PATH: &str = "store.sqlite";
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS latest (
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            saved_at INTEGER NOT NULL,
+            measurement TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS history (
+            timestamp INTEGER,
+            measurement TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS history_timestamp ON history (timestamp);
+    ";
+
+    /// Measurements as JSON in a SQLite database.
+    pub struct SqliteStore {
+        connection: Connection,
+    }
+
+    pub fn open(config: &Config) -> Result<Box<dyn Store>, String> {
+        let path = match &config.store.path {
+            Some(p) => p.clone(),
+            None => get_data_filename(DATABASE_FILE_PATH)?,
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            if let Err(e) = fs::create_dir_all(dir) {
+                return Err(format!("Failed to create '{}': {}", dir.display(), e));
+            }
+        }
+        let connection = match Connection::open(&path) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Failed to open '{}': {}", path.display(), e)),
+        };
+        if let Err(e) = connection.execute_batch(SCHEMA) {
+            return Err(format!("Failed to set up '{}': {}", path.display(), e));
+        }
+        Ok(Box::new(SqliteStore { connection }))
+    }
+
+    fn sql_error(e: rusqlite::Error) -> String {
+        format!("SQLite store: {}", e)
+    }
+
+    fn to_json(info: &Fast) -> Result<String, String> {
+        match serde_json::to_string(info) {
+            Ok(j) => Ok(j),
+            Err(e) => Err(format!("Failed to convert to JSON: {}", e)),
+        }
+    }
+
+    impl Store for SqliteStore {
+        fn latest(&self) -> Result<Fast, String> {
+            let json: Option<String> = self
+                .connection
+                .query_row("SELECT measurement FROM latest WHERE id = 0", [], |r| {
+                    r.get(0)
+                })
+                .optional()
+                .map_err(sql_error)?;
+            let mut info: Fast = match json.map(|j| serde_json::from_str(&j)) {
+                Some(Ok(i)) => i,
+                Some(Err(e)) => return Err(format!("Incompatible stored measurement: {}", e)),
+                None => return Err("No measurement stored yet".to_string()),
+            };
+            info.cached = true;
+            Ok(info)
+        }
+
+        fn age(&self, clock: &dyn Clock) -> Result<u64, String> {
+            let saved: Option<i64> = self
+                .connection
+                .query_row("SELECT saved_at FROM latest WHERE id = 0", [], |r| r.get(0))
+                .optional()
+                .map_err(sql_error)?;
+            match saved {
+                Some(t) => Ok((clock.now().timestamp() - t).max(0) as u64),
+                None => Err("No measurement stored yet".to_string()),
+            }
+        }
+
+        fn save(&self, info: &Fast) -> Result<(), String> {
+            self.connection
+                .execute(
+                    "INSERT OR REPLACE INTO latest (id, saved_at, measurement) VALUES (0, ?1, ?2)",
+                    params![chrono::Local::now().timestamp(), to_json(info)?],
+                )
+                .map(|_| ())
+                .map_err(sql_error)
+        }
+
+        fn append(&self, info: &Fast) -> Result<(), String> {
+            self.connection
+                .execute(
+                    "INSERT INTO history (timestamp, measurement) VALUES (?1, ?2)",
+                    params![info.timestamp, to_json(info)?],
+                )
+                .map(|_| ())
+                .map_err(sql_error)
+        }
+
+        fn history(&self, since: Option<i64>) -> Result<Vec<Fast>, String> {
+            let mut statement = self
+                .connection
+                .prepare(
+                    "SELECT measurement FROM history
+                     WHERE ?1 IS NULL OR timestamp IS NULL OR timestamp >= ?1
+                     ORDER BY rowid",
+                )
+                .map_err(sql_error)?;
+            let rows = statement
+                .query_map([since], |r| r.get::<_, String>(0))
+                .map_err(sql_error)?;
+            let mut entries = Vec::new();
+            for json in rows {
+                match serde_json::from_str(&json.map_err(sql_error)?) {
+                    Ok(i) => entries.push(i),
+                    Err(e) => log::warn!("Skipping a stored measurement: {}", e),
+                }
+            }
+            Ok(entries)
+        }
+
+        fn history_version(&self) -> u64 {
+            self.connection
+                .query_row("SELECT COUNT(*) FROM history", [], |r| r.get::<_, i64>(0))
+                .map(|n| n as u64)
+                .unwrap_or(0)
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+mod sqlite {
+    use super::Store;
+    use crate::config::Config;
+
+    pub fn open(_: &Config) -> Result<Box<dyn Store>, String> {
+        Err("Built without SQLite, rebuild with `--features sqlite`".to_string())
+    }
+}
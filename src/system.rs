@@ -0,0 +1,51 @@
+use chrono::{DateTime, Local};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::process::Output;
+use tokio::process::Command;
+
+pub type CommandFuture = Pin<Box<dyn Future<Output = io::Result<Output>> + Send>>;
+
+/// Runs the backends' commands, so tests can script their outcome.
+pub trait CommandRunner: Send + Sync {
+    /// Runs `command` to completion, killing it when the future is dropped.
+    fn output(&self, command: Command) -> CommandFuture;
+}
+
+/// Spawns real processes.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, mut command: Command) -> CommandFuture {
+        command.kill_on_drop(true);
+        Box::pin(async move { command.output().await })
+    }
+}
+
+/// The current time, so tests can age files without waiting.
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// The machine's name, `localhost` when it can't be read.
+#[cfg(target_os = "linux")]
+pub fn hostname() -> String {
+    match std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(h) => h.trim().to_string(),
+        Err(_) => "localhost".to_string(),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hostname() -> String {
+    sysinfo::System::host_name().unwrap_or_else(|| "localhost".to_string())
+}
@@ -0,0 +1,38 @@
+#[cfg(target_os = "linux")]
+use std::env;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+/// Sends `state` (e.g. `READY=1`, `STATUS=...`) to the service manager. Does
+/// nothing when not started by systemd with `Type=notify`.
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) -> Result<(), String> {
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(p) => p,
+        Err(_) => return Ok(()),
+    };
+    // A leading '@' names a socket in the abstract namespace
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&path),
+    };
+    let addr = match addr {
+        Ok(a) => a,
+        Err(e) => return Err(format!("Invalid NOTIFY_SOCKET '{}': {}", path, e)),
+    };
+    let socket = match UnixDatagram::unbound() {
+        Ok(s) => s,
+        Err(e) => return Err(format!("Failed to create notify socket: {}", e)),
+    };
+    match socket.send_to_addr(state.as_bytes(), &addr) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to notify '{}': {}", path, e)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_state: &str) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::get_cache_filename;
+use log::info;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+use tokio::time;
+
+// Held while a test runs, holding the unix time the last one started
+const LOCK_FILE_PATH: &str = ".polybar-internet-speed-lock";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Exclusive right to run a speed test, across processes and within one.
+/// Released when dropped.
+pub struct TestLock {
+    file: File,
+}
+
+/// Waits for a test already running to finish.
+pub async fn lock() -> Result<TestLock, String> {
+    let path = get_cache_filename(LOCK_FILE_PATH)?;
+    let file = match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(format!("Failed to open '{}': {}", path, e)),
+    };
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(_) => return Ok(TestLock { file }),
+            Err(TryLockError::WouldBlock) => {
+                if !waiting {
+                    info!("Waiting for the test already running");
+                    waiting = true;
+                }
+                time::sleep(POLL_INTERVAL).await;
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("Failed to lock '{}': {}", path, e));
+            }
+        }
+    }
+}
+
+/// When the last test started, read without waiting for one running.
+pub fn last_started() -> Option<i64> {
+    let path = get_cache_filename(LOCK_FILE_PATH).ok()?;
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+impl TestLock {
+    /// When the last test started, `None` before the first one.
+    pub fn last_run(&mut self) -> Option<i64> {
+        let mut contents = String::new();
+        self.file.seek(SeekFrom::Start(0)).ok()?;
+        self.file.read_to_string(&mut contents).ok()?;
+        contents.trim().parse().ok()
+    }
+
+    pub fn record(&mut self, started: i64) -> Result<(), String> {
+        let result = self
+            .file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| write!(self.file, "{}", started));
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to record the test start: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,574 @@
+use super::*;
+use std::collections::VecDeque;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::sync::Mutex;
+use system::CommandFuture;
+
+/// Hands out the queued outputs in order, recording each program it ran.
+struct ScriptedRunner {
+    outputs: Mutex<VecDeque<io::Result<Output>>>,
+    programs: Mutex<Vec<String>>,
+}
+
+impl ScriptedRunner {
+    fn new(outputs: Vec<io::Result<Output>>) -> Arc<ScriptedRunner> {
+        Arc::new(ScriptedRunner {
+            outputs: Mutex::new(outputs.into()),
+            programs: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn programs(&self) -> Vec<String> {
+        self.programs.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn output(&self, command: Command) -> CommandFuture {
+        let program = command.as_std().get_program().to_string_lossy();
+        self.programs.lock().unwrap().push(program.to_string());
+        let output = self
+            .outputs
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("ran more commands than scripted");
+        Box::pin(async move { output })
+    }
+}
+
+struct FixedClock(DateTime<Local>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Local> {
+        self.0
+    }
+}
+
+fn exited(code: i32, stdout: &str, stderr: &str) -> io::Result<Output> {
+    Ok(Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: stderr.as_bytes().to_vec(),
+    })
+}
+
+/// A file in the temp directory, unique to this process and `name`.
+fn temp_file(name: &str) -> String {
+    let path = env::temp_dir().join(format!("rusting-test-{}-{}", process::id(), name));
+    fs::write(&path, "").unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn file_store(buffer: &str) -> store::FileStore {
+    store::FileStore::new(buffer.to_string(), &config::HistoryConfig::default())
+}
+
+/// A clock `secs` after `file` was last modified.
+fn aged(file: &str, secs: i64) -> FixedClock {
+    let modified: DateTime<Local> = fs::metadata(file).unwrap().modified().unwrap().into();
+    FixedClock(modified + chrono::Duration::seconds(secs))
+}
+
+#[test]
+fn file_age_follows_the_clock() {
+    let file = temp_file("age");
+    assert_eq!(
+        get_seconds_since_file_modified(&file, &aged(&file, 0)),
+        Ok(0)
+    );
+    assert_eq!(
+        get_seconds_since_file_modified(&file, &aged(&file, 90)),
+        Ok(90)
+    );
+    assert!(get_seconds_since_file_modified(&file, &aged(&file, -60)).is_err());
+    fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn file_age_needs_a_file() {
+    let clock = SystemClock;
+    let missing = env::temp_dir().join(format!("rusting-test-{}-missing", process::id()));
+    let err = get_seconds_since_file_modified(missing.to_str().unwrap(), &clock).unwrap_err();
+    assert!(err.starts_with("Failed to get metadata"), "{}", err);
+    let dir = env::temp_dir();
+    let err = get_seconds_since_file_modified(dir.to_str().unwrap(), &clock).unwrap_err();
+    assert!(err.ends_with("is not a file!"), "{}", err);
+}
+
+#[test]
+fn buffer_goes_stale_after_the_ttl() {
+    let file = temp_file("stale");
+    let config = Config::default();
+    let ttl = config.cache_ttl_secs as i64;
+    assert_eq!(
+        is_stale(&config, &file_store(&file), false, &aged(&file, ttl)),
+        Ok(false)
+    );
+    assert_eq!(
+        is_stale(&config, &file_store(&file), false, &aged(&file, ttl + 1)),
+        Ok(true)
+    );
+    fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn low_battery_stretches_the_ttl() {
+    let file = temp_file("battery");
+    let config = Config::default();
+    let past_ttl = aged(&file, config.cache_ttl_secs as i64 + 1);
+    assert_eq!(
+        is_stale(&config, &file_store(&file), true, &past_ttl),
+        Ok(false)
+    );
+    let past_battery_ttl = aged(&file, config.battery.ttl_secs as i64 + 1);
+    assert_eq!(
+        is_stale(&config, &file_store(&file), true, &past_battery_ttl),
+        Ok(true)
+    );
+    fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn missing_buffer_is_an_error() {
+    let missing = env::temp_dir().join(format!("rusting-test-{}-nobuffer", process::id()));
+    let config = Config::default();
+    assert!(is_stale(
+        &config,
+        &file_store(missing.to_str().unwrap()),
+        false,
+        &SystemClock
+    )
+    .is_err());
+}
+
+#[tokio::test]
+async fn fast_output_is_parsed() {
+    let runner = ScriptedRunner::new(vec![exited(
+        0,
+        r#"{"downloadSpeed": 120, "uploadSpeed": 30, "latency": 12}"#,
+        "",
+    )]);
+    let info = get_fast_info(runner.as_ref(), &FastConfig::default())
+        .await
+        .unwrap();
+    assert_eq!(info.download_speed, 120);
+    assert_eq!(info.upload_speed, Some(30));
+    assert_eq!(info.latency, 12);
+    assert_eq!(runner.programs(), vec!["fast".to_string()]);
+}
+
+#[tokio::test]
+async fn fast_failure_reports_stderr() {
+    let runner = ScriptedRunner::new(vec![exited(1, "", "Could not reach fast.com")]);
+    let err = get_fast_info(runner.as_ref(), &FastConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.contains("Could not reach fast.com"), "{}", err);
+}
+
+#[tokio::test]
+async fn fast_malformed_json_is_an_error() {
+    let runner = ScriptedRunner::new(vec![exited(0, "Downloading 42 Mbps", "")]);
+    let err = get_fast_info(runner.as_ref(), &FastConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.starts_with("Failed to parse JSON"), "{}", err);
+}
+
+#[tokio::test]
+async fn fast_missing_hints_at_installing_it() {
+    let runner = ScriptedRunner::new(vec![Err(io::Error::from(io::ErrorKind::NotFound))]);
+    let err = get_fast_info(runner.as_ref(), &FastConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.contains("install fast-cli"), "{}", err);
+}
+
+#[tokio::test]
+async fn samples_skip_failed_runs() {
+    let runner = ScriptedRunner::new(vec![
+        exited(0, r#"{"downloadSpeed": 100, "latency": 10}"#, ""),
+        exited(1, "", "timeout"),
+        exited(0, r#"{"downloadSpeed": 80, "latency": 20}"#, ""),
+        exited(0, r#"{"downloadSpeed": 90, "latency": 15}"#, ""),
+    ]);
+    let mut config = Config::default();
+    config.sampling.count = 4;
+    let runner: Arc<dyn CommandRunner> = runner;
+    let info = get_internet_info(&runner, &config).await.unwrap();
+    assert_eq!(info.download_speed, 90);
+    assert_eq!(info.latency, 15);
+    assert_eq!(info.backend, Some(Backend::Fast));
+}
+
+#[tokio::test]
+async fn every_sample_failing_keeps_the_last_error() {
+    let runner = ScriptedRunner::new(vec![exited(1, "", "first"), exited(0, "not json", "")]);
+    let mut config = Config::default();
+    config.sampling.count = 2;
+    let runner: Arc<dyn CommandRunner> = runner;
+    let err = get_internet_info(&runner, &config).await.unwrap_err();
+    assert!(err.starts_with("Failed to parse JSON"), "{}", err);
+}
+
+const SPEEDTEST_OUTPUT: &str = r#"{"ping": {"latency": 8.4}, "download": {"bandwidth": 12500000},
+    "server": {"id": 5678, "name": "Example ISP", "location": "Berlin"}}"#;
+
+#[tokio::test]
+async fn speedtest_falls_back_from_preferred_servers() {
+    let runner = ScriptedRunner::new(vec![
+        exited(1, "", "No servers"),
+        exited(0, SPEEDTEST_OUTPUT, ""),
+    ]);
+    let config = config::SpeedtestConfig {
+        servers: vec![1234],
+        pin: false,
+    };
+    let info = speedtest::get_speedtest_info(runner.as_ref(), &config, None)
+        .await
+        .unwrap();
+    assert_eq!(info.download_speed, 100);
+    assert_eq!(info.server.as_deref(), Some("Example ISP, Berlin"));
+    assert_eq!(info.server_id.as_deref(), Some("5678"));
+    assert_eq!(runner.programs().len(), 2);
+}
+
+#[tokio::test]
+async fn speedtest_pinned_servers_never_fall_back() {
+    let runner = ScriptedRunner::new(vec![exited(1, "", "No servers")]);
+    let config = config::SpeedtestConfig {
+        servers: vec![1234],
+        pin: true,
+    };
+    let err = speedtest::get_speedtest_info(runner.as_ref(), &config, None)
+        .await
+        .unwrap_err();
+    assert!(err.starts_with("Every pinned server failed"), "{}", err);
+}
+
+const TRACEROUTE_HEADER: &str = "traceroute to 1.1.1.1 (1.1.1.1), 8 hops max, 60 byte packets\n";
+
+async fn trace_cause(hops: &str) -> Result<route::Cause, String> {
+    let output = format!("{}{}", TRACEROUTE_HEADER, hops);
+    let runner = ScriptedRunner::new(vec![exited(0, &output, "")]);
+    route::trace(runner.as_ref(), &config::RouteConfig::default())
+        .await
+        .map(|d| d.cause)
+}
+
+#[tokio::test]
+async fn route_dies_on_the_lan_without_a_gateway() {
+    let cause = trace_cause(" 1  *\n 2  *\n").await;
+    assert_eq!(cause, Ok(route::Cause::Lan));
+}
+
+#[tokio::test]
+async fn route_dies_at_the_isp_within_its_hops() {
+    let hops = " 1  192.168.1.1  0.512 ms\n 2  *\n 3  10.0.0.1  5.104 ms\n 4  *\n";
+    assert_eq!(trace_cause(hops).await, Ok(route::Cause::Isp));
+}
+
+#[tokio::test]
+async fn route_dies_upstream_past_the_isp() {
+    let hops = " 1  192.168.1.1  0.512 ms\n 2  100.64.0.1  4.2 ms\n 3  10.0.0.1  5.1 ms\n 4  62.115.1.1  9.8 ms\n 5  *\n";
+    assert_eq!(trace_cause(hops).await, Ok(route::Cause::Upstream));
+}
+
+#[tokio::test]
+async fn route_without_hops_is_an_error() {
+    let runner = ScriptedRunner::new(vec![exited(2, "", "Name or service not known")]);
+    let err = route::trace(runner.as_ref(), &config::RouteConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.contains("Name or service not known"), "{}", err);
+}
+
+#[test]
+fn report_month_spans_to_the_next_one() {
+    let (start, end) = report::month_range("2024-12").unwrap();
+    let first = Local.timestamp_opt(start, 0).unwrap();
+    let next = Local.timestamp_opt(end, 0).unwrap();
+    assert_eq!(
+        first.format("%Y-%m-%d %H:%M").to_string(),
+        "2024-12-01 00:00"
+    );
+    assert_eq!(
+        next.format("%Y-%m-%d %H:%M").to_string(),
+        "2025-01-01 00:00"
+    );
+    assert!(report::month_range("2024-13").is_err());
+    assert!(report::month_range("May 2024").is_err());
+}
+
+#[test]
+fn latency_percentiles_catch_the_spikes() {
+    let mut histogram = latency::Histogram::default();
+    for _ in 0..98 {
+        histogram.record(Duration::from_millis(20));
+    }
+    histogram.record(Duration::from_millis(400));
+    histogram.record(Duration::from_millis(1500));
+    let near = |p: f64, ms: f64| {
+        let got = histogram.percentile(p).unwrap();
+        assert!(
+            (got - ms).abs() / ms < 0.04,
+            "p{} is {} not ~{}",
+            p,
+            got,
+            ms
+        );
+    };
+    near(50.0, 20.0);
+    near(95.0, 20.0);
+    near(99.0, 400.0);
+    near(100.0, 1500.0);
+    assert_eq!(histogram.total(), 100);
+}
+
+#[test]
+fn latency_windows_combine() {
+    let mut windows = latency::Windows::default();
+    windows.current.record(Duration::from_micros(10));
+    windows.current.since = 200;
+    windows.previous.record(Duration::from_micros(10));
+    windows.previous.lost = 3;
+    windows.previous.since = 100;
+    let combined = windows.combined();
+    assert_eq!(combined.total(), 2);
+    assert_eq!(combined.lost, 3);
+    assert_eq!(combined.since, 100);
+    assert_eq!(combined.percentile(50.0), Some(0.0105));
+}
+
+#[test]
+fn graph_needs_recent_measurements() {
+    let now = Local::now().timestamp();
+    let entry = |age: i64, download: u32| {
+        let mut e: Fast = serde_json::from_str(r#"{"downloadSpeed": 0, "latency": 15}"#).unwrap();
+        e.download_speed = download;
+        e.timestamp = Some(now - age);
+        e
+    };
+    let old = vec![entry(10 * 86400, 90)];
+    let err = graph::render_svg(&old, 7, (400, 300)).unwrap_err();
+    assert!(err.starts_with("No measurements"), "{}", err);
+    let recent = vec![entry(3600, 90), entry(7200, 80)];
+    let svg = graph::render_svg(&recent, 7, (400, 300)).unwrap();
+    assert!(svg.starts_with("<svg"), "{}", svg);
+}
+
+#[tokio::test]
+async fn fast_cant_be_bound_to_an_interface() {
+    let runner = ScriptedRunner::new(vec![]);
+    let mut config = Config::default();
+    config.interfaces.bind = Some("wlan0".to_string());
+    let err = get_backend_info(runner.as_ref(), &config, Backend::Fast)
+        .await
+        .unwrap_err();
+    assert!(err.starts_with("fast-cli can't be bound"), "{}", err);
+    assert!(runner.programs().is_empty());
+}
+
+#[test]
+fn progress_spins_through_the_frames() {
+    let mut config = Config::default();
+    config.progress.spinner = vec!["-".to_string(), "+".to_string()];
+    let line = |frame| render_progress(&config, frame, Duration::from_secs(12)).unwrap();
+    assert_eq!(line(0), "- testing… 12s");
+    assert_eq!(line(3), "+ testing… 12s");
+    config.output = OutputFormat::Json;
+    assert!(render_progress(&config, 0, Duration::ZERO).is_none());
+}
+
+#[test]
+fn labels_become_influx_tags() {
+    let mut info = Fast {
+        download_speed: 90,
+        latency: 12,
+        ..Default::default()
+    };
+    assert_eq!(
+        influx::line_protocol("speed", &info, 60),
+        "speed download=90i,latency=12i 60"
+    );
+    info.labels.insert("host".to_string(), "laptop".to_string());
+    info.labels
+        .insert("location".to_string(), "home office".to_string());
+    assert_eq!(
+        influx::line_protocol("speed", &info, 60),
+        "speed,host=laptop,location=home\\ office download=90i,latency=12i 60"
+    );
+}
+
+#[test]
+fn locale_translates_numbers_and_status_words() {
+    let mut config = Config::default();
+    config.locale.decimal_separator = Some(",".to_string());
+    config
+        .locale
+        .strings
+        .insert("testing".to_string(), "Test".to_string());
+    assert_eq!(locale::number(&config.locale, "12.5"), "12,5");
+    assert_eq!(locale::word(&config.locale, "offline"), "offline");
+    let line = render_progress(&config, 0, Duration::from_secs(3)).unwrap();
+    assert!(line.ends_with(" Test… 3s"));
+    assert_eq!(
+        format::abbreviate_units("15 ms 93 Mbit/s", "Mbit/s"),
+        "15ms 93M"
+    );
+    assert_eq!(format::abbreviate_units("15 ms 93", ""), "15ms 93");
+    assert_eq!(
+        format::abbreviate_units("Mbps Cafe 93 Mbps 8Mbps", "Mbps"),
+        "Mbps Cafe 93M 8M"
+    );
+}
+
+#[test]
+fn theme_overrides_its_palette() {
+    let mut theme = config::ThemeConfig::default();
+    assert_eq!(threshold::Level::Good.color(&theme), "#3cb703");
+    theme.palette = config::Palette::Nord;
+    theme.bad = Some("#ff0000".to_string());
+    assert_eq!(threshold::Level::Warn.color(&theme), "#ebcb8b");
+    assert_eq!(threshold::Level::Bad.color(&theme), "#ff0000");
+}
+
+#[tokio::test]
+async fn failing_sink_keeps_the_measurement() {
+    let mut config = Config::default();
+    config.store.backend = config::StoreBackend::Memory;
+    config.history.enabled = true;
+    // Nothing listens on port 1
+    config.statsd = Some(config::StatsdConfig {
+        host: "127.0.0.1".to_string(),
+        port: Some(1),
+        prefix: "internet_speed".to_string(),
+        protocol: config::StatsdProtocol::Graphite,
+        timeout_secs: 1,
+    });
+    let info = Fast {
+        download_speed: 77,
+        latency: 9,
+        ..Default::default()
+    };
+    let sinks = sink::pipeline(&config);
+    let names: Vec<&str> = sinks.iter().map(|s| s.name()).collect();
+    assert_eq!(names, ["buffer", "history", "statsd"]);
+    sink::send_all(&sinks, &info).await;
+    let store = store::open(&config).unwrap();
+    assert_eq!(store.latest().unwrap().download_speed, 77);
+    assert_eq!(store.history(None).unwrap().len(), 1);
+    config.sinks.buffer = false;
+    let names: Vec<&str> = sink::pipeline(&config).iter().map(|s| s.name()).collect();
+    assert_eq!(names, ["history", "statsd"]);
+}
+
+#[test]
+fn quiet_toggle_lasts_until_the_hours_change() {
+    let hours = vec!["22:00-07:00".to_string(), "12:00-13:00".to_string()];
+    let at = |s: &str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+    // Quiet overnight, until the window ends the next morning
+    let night = schedule::next_change(&hours, at("2024-05-01 23:30"));
+    assert_eq!(night, Some(at("2024-05-02 07:00")));
+    let morning = schedule::next_change(&hours, at("2024-05-01 08:00"));
+    assert_eq!(morning, Some(at("2024-05-01 12:00")));
+    assert_eq!(schedule::next_change(&[], at("2024-05-01 08:00")), None);
+}
+
+/// Points the cache and data directories somewhere empty for the tests
+/// sharing them, one at a time.
+async fn temp_dirs(name: &str) -> tokio::sync::MutexGuard<'static, ()> {
+    static DIRS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let guard = DIRS.lock().await;
+    let dir = env::temp_dir().join(format!("rusting-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    env::set_var("XDG_CACHE_HOME", &dir);
+    env::set_var("XDG_DATA_HOME", &dir);
+    guard
+}
+
+#[tokio::test]
+async fn failed_test_leaves_the_reused_result_stale() {
+    let _dirs = temp_dirs("coalesce").await;
+    let mut config = Config::default();
+    config.backend = config::Backend::Mock;
+    config.history.enabled = false;
+    config.snapshot.enabled = false;
+    let first = get_new_internet_info(&config).await.unwrap();
+    assert!(!first.stale);
+    // A request waiting on that test shares it
+    let shared = get_new_internet_info(&config).await.unwrap();
+    assert_eq!(shared.timestamp, first.timestamp);
+    assert!(!shared.stale);
+    // A later test started, but left nothing newer
+    let older = Fast {
+        timestamp: Some(Local::now().timestamp() - 30),
+        ..first
+    };
+    store::open(&config).unwrap().save(&older).unwrap();
+    let mut lock = testlock::lock().await.unwrap();
+    lock.record(Local::now().timestamp()).unwrap();
+    drop(lock);
+    let reused = get_new_internet_info(&config).await.unwrap();
+    assert_eq!(reused.timestamp, older.timestamp);
+    assert!(reused.stale);
+}
+
+#[test]
+fn only_a_corrupt_buffer_is_moved_aside() {
+    use store::Store;
+    let buffer = temp_file("buffer-version");
+    let store = file_store(&buffer);
+    store.save(&Fast::default()).unwrap();
+    let contents = fs::read_to_string(&buffer).unwrap();
+    let body = contents.split_once('\n').unwrap().1;
+    let newer = body.replace(
+        &format!("version = {}", BUFFER_VERSION),
+        &format!("version = {}", BUFFER_VERSION + 1),
+    );
+    let sum = format!("{}{:016x}\n", CHECKSUM_PREFIX, checksum(&newer));
+    fs::write(&buffer, format!("{}{}", sum, newer)).unwrap();
+    assert!(store.latest().is_err());
+    assert!(Path::new(&buffer).exists());
+    fs::write(&buffer, format!("{}{}", sum, body)).unwrap();
+    assert!(store.latest().is_err());
+    assert!(!Path::new(&buffer).exists());
+    assert!(Path::new(&format!("{}.corrupt", buffer)).exists());
+}
+
+#[tokio::test]
+async fn budget_counts_this_month_only() {
+    let _dirs = temp_dirs("budget").await;
+    let counters = |rx_bytes, tx_bytes| netdev::Counters { rx_bytes, tx_bytes };
+    let mut config = config::BudgetConfig {
+        enabled: true,
+        monthly_mb: 2,
+        ..Default::default()
+    };
+    let path = history::get_data_filename("budget.toml").unwrap();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    // Last month's usage starts over
+    fs::write(&path, "month = \"2000-01\"\nbytes = 9000000\ntests = 3\n").unwrap();
+    assert_eq!(budget::used(), 0);
+    assert!(!budget::exceeded(&config));
+    let used = budget::add(counters(100, 50), counters(1_200_100, 300_050)).unwrap();
+    assert_eq!(used, 1_500_000);
+    assert!(!budget::exceeded(&config));
+    budget::add(counters(0, 0), counters(400_000, 100_000)).unwrap();
+    assert_eq!(budget::used(), 2_000_000);
+    assert!(budget::exceeded(&config));
+    config.enabled = false;
+    assert!(!budget::exceeded(&config));
+}
+
+#[test]
+fn prometheus_label_names_are_made_valid() {
+    let mut info = Fast::default();
+    info.labels.insert("my-host".to_string(), "a".to_string());
+    info.labels.insert("1site".to_string(), "b".to_string());
+    let metrics = output::render_prometheus(&info, 0);
+    assert!(metrics.contains("{_1site=\"b\",my_host=\"a\"}"));
+}
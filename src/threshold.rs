@@ -0,0 +1,317 @@
+use crate::config::{
+    ColorPolicy, ColorsConfig, HysteresisConfig, Palette, PlanConfig, ThemeConfig, WorkConfig,
+};
+use crate::store::Store;
+use crate::{get_cache_filename, stats};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const LEVEL_FILE_PATH: &str = ".polybar-internet-speed-level.toml";
+const ADAPTIVE_FILE_PATH: &str = ".polybar-internet-speed-adaptive.toml";
+
+/// How healthy a metric is, which decides the icon color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Good,
+    Warn,
+    Bad,
+}
+
+/// Good, warn and bad colors of each palette.
+fn palette(palette: Palette) -> [&'static str; 3] {
+    match palette {
+        Palette::Default => ["#3cb703", "#f9dd04", "#d60606"],
+        Palette::ColorblindSafe => ["#0072b2", "#f0e442", "#d55e00"],
+        Palette::Monochrome => ["#eeeeee", "#aaaaaa", "#666666"],
+        Palette::Gruvbox => ["#b8bb26", "#fabd2f", "#fb4934"],
+        Palette::Nord => ["#a3be8c", "#ebcb8b", "#bf616a"],
+    }
+}
+
+impl Level {
+    pub fn color<'a>(&self, theme: &'a ThemeConfig) -> &'a str {
+        let [good, warn, bad] = palette(theme.palette);
+        let (color, default) = match self {
+            Level::Good => (&theme.good, good),
+            Level::Warn => (&theme.warn, warn),
+            Level::Bad => (&theme.bad, bad),
+        };
+        color.as_deref().unwrap_or(default)
+    }
+}
+
+pub fn latency_level(latency: u32, config: &ColorsConfig) -> Level {
+    latency_level_from(latency, None, 0, config)
+}
+
+/// Like `latency_level`, but boundaries move `margin` ms away from the
+/// `current` level so it takes a clear change to leave it.
+pub fn latency_level_from(
+    latency: u32,
+    current: Option<Level>,
+    margin: u32,
+    config: &ColorsConfig,
+) -> Level {
+    let (good_max, warn_max) = (config.latency_warn_above, config.latency_bad_above);
+    let (good_max, warn_max) = match current {
+        Some(Level::Good) => (good_max + margin, warn_max),
+        Some(Level::Warn) => (good_max.saturating_sub(margin), warn_max + margin),
+        Some(Level::Bad) => (good_max, warn_max.saturating_sub(margin)),
+        None => (good_max, warn_max),
+    };
+    if latency <= good_max {
+        Level::Good
+    } else if latency <= warn_max {
+        Level::Warn
+    } else {
+        Level::Bad
+    }
+}
+
+/// Level of the `[work]` target's latency, bad when it didn't answer.
+pub fn work_level(latency: Option<u32>, config: &WorkConfig) -> Level {
+    match latency {
+        Some(l) if l <= config.warn_above => Level::Good,
+        Some(l) if l <= config.bad_above => Level::Warn,
+        _ => Level::Bad,
+    }
+}
+
+/// Level of a speed, `None` without any threshold for it.
+fn speed_level(speed: u32, warn_below: Option<u32>, bad_below: Option<u32>) -> Option<Level> {
+    if warn_below.is_none() && bad_below.is_none() {
+        return None;
+    }
+    if bad_below.is_some_and(|b| speed < b) {
+        Some(Level::Bad)
+    } else if warn_below.is_some_and(|w| speed < w) {
+        Some(Level::Warn)
+    } else {
+        Some(Level::Good)
+    }
+}
+
+/// Combines the latency level with the speeds' as the policy says.
+pub fn combine(config: &ColorsConfig, latency: Level, download: u32, upload: Option<u32>) -> Level {
+    let down = speed_level(
+        download,
+        config.download_warn_below,
+        config.download_bad_below,
+    );
+    let up = upload.and_then(|u| speed_level(u, config.upload_warn_below, config.upload_bad_below));
+    match config.policy {
+        ColorPolicy::Latency => latency,
+        ColorPolicy::Download => down.unwrap_or(Level::Good),
+        ColorPolicy::Worst => [down, up].into_iter().flatten().fold(latency, Level::max),
+    }
+}
+
+/// Displayed level persisted between runs, plus the level waiting to
+/// replace it.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct LevelState {
+    pub level: Level,
+    pub pending: Option<Level>,
+    pub count: u32,
+}
+
+pub fn load_state() -> Option<LevelState> {
+    let path = get_cache_filename(LEVEL_FILE_PATH).ok()?;
+    let contents = fs::read_to_string(path).ok()?;
+    toml::from_str(&contents).ok()
+}
+
+fn save_state(state: &LevelState) -> Result<(), String> {
+    let path = get_cache_filename(LEVEL_FILE_PATH)?;
+    let toml = match toml::to_string(state) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(format!("Failed to convert to TOML: {}", e));
+        }
+    };
+    match fs::write(&path, toml) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// Only switches away from the previously displayed level once `candidate`
+/// has been seen for the configured number of consecutive samples.
+pub fn settle(config: &HysteresisConfig, prev: Option<LevelState>, candidate: Level) -> Level {
+    let state = match prev {
+        None => LevelState {
+            level: candidate,
+            pending: None,
+            count: 0,
+        },
+        Some(p) if p.level == candidate => LevelState {
+            level: candidate,
+            pending: None,
+            count: 0,
+        },
+        Some(p) => {
+            let count = if p.pending == Some(candidate) {
+                p.count + 1
+            } else {
+                1
+            };
+            if count >= config.samples {
+                info!("Level changed from {:?} to {:?}", p.level, candidate);
+                LevelState {
+                    level: candidate,
+                    pending: None,
+                    count: 0,
+                }
+            } else {
+                LevelState {
+                    level: p.level,
+                    pending: Some(candidate),
+                    count,
+                }
+            }
+        }
+    };
+    if let Err(e) = save_state(&state) {
+        warn!("{}", e);
+    }
+    state.level
+}
+
+/// Percentage of the advertised plan speed actually measured.
+pub fn plan_percent(measured: u32, advertised: u32) -> Option<u32> {
+    if advertised == 0 {
+        return None;
+    }
+    Some((measured as f64 / advertised as f64 * 100.0).round() as u32)
+}
+
+pub fn percent_level(percent: u32, plan: &PlanConfig) -> Level {
+    if percent >= plan.good_percent {
+        Level::Good
+    } else if percent >= plan.warn_percent {
+        Level::Warn
+    } else {
+        Level::Bad
+    }
+}
+
+/// Speed thresholds derived from the history, kept until the history or the
+/// settings they came from change.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Adaptive {
+    key: String,
+    download_warn_below: Option<u32>,
+    download_bad_below: Option<u32>,
+    upload_warn_below: Option<u32>,
+    upload_bad_below: Option<u32>,
+}
+
+fn save_adaptive(adaptive: &Adaptive) -> Result<(), String> {
+    let path = get_cache_filename(ADAPTIVE_FILE_PATH)?;
+    let toml = match toml::to_string(adaptive) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(format!("Failed to convert to TOML: {}", e));
+        }
+    };
+    match fs::write(&path, toml) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// Warn and bad thresholds at the configured percentiles of `values`, none
+/// with too few of them.
+fn percentiles(config: &ColorsConfig, mut values: Vec<u32>) -> (Option<u32>, Option<u32>) {
+    if values.len() < config.adaptive_min_samples.max(1) {
+        return (None, None);
+    }
+    values.sort_unstable();
+    (
+        Some(stats::percentile(&values, config.adaptive_warn_percentile)),
+        Some(stats::percentile(&values, config.adaptive_bad_percentile)),
+    )
+}
+
+fn compute_adaptive(
+    config: &ColorsConfig,
+    store: &dyn Store,
+    network: Option<&str>,
+    key: String,
+) -> Result<Adaptive, String> {
+    let entries: Vec<_> = store
+        .history(None)?
+        .into_iter()
+        .filter(|e| network.is_none() || e.network.as_deref() == network)
+        .collect();
+    let (download_warn_below, download_bad_below) =
+        percentiles(config, entries.iter().map(|e| e.download_speed).collect());
+    let (upload_warn_below, upload_bad_below) = percentiles(
+        config,
+        entries.iter().filter_map(|e| e.upload_speed).collect(),
+    );
+    info!(
+        "Adapted the thresholds to {} measurements: download {:?}/{:?}, upload {:?}/{:?}",
+        entries.len(),
+        download_warn_below,
+        download_bad_below,
+        upload_warn_below,
+        upload_bad_below
+    );
+    Ok(Adaptive {
+        key,
+        download_warn_below,
+        download_bad_below,
+        upload_warn_below,
+        upload_bad_below,
+    })
+}
+
+/// Replaces the speed thresholds with percentiles of the measurements taken
+/// on `network`, or all of them when it's unknown. Recomputed whenever the
+/// history changes, metrics without enough history keep their thresholds.
+pub fn adapt(config: &ColorsConfig, store: &dyn Store, network: Option<&str>) -> ColorsConfig {
+    let key = format!(
+        "{} {:?} {} {} {}",
+        store.history_version(),
+        network,
+        config.adaptive_warn_percentile,
+        config.adaptive_bad_percentile,
+        config.adaptive_min_samples
+    );
+    let cached = get_cache_filename(ADAPTIVE_FILE_PATH)
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|c| toml::from_str::<Adaptive>(&c).ok())
+        .filter(|a| a.key == key);
+    let adaptive = match cached {
+        Some(a) => {
+            debug!("Adaptive thresholds are up to date");
+            a
+        }
+        None => match compute_adaptive(config, store, network, key) {
+            Ok(a) => {
+                if let Err(e) = save_adaptive(&a) {
+                    warn!("{}", e);
+                }
+                a
+            }
+            Err(e) => {
+                warn!("Keeping the configured thresholds, {}", e);
+                return config.clone();
+            }
+        },
+    };
+    let mut adapted = config.clone();
+    if adaptive.download_warn_below.is_some() {
+        adapted.download_warn_below = adaptive.download_warn_below;
+        adapted.download_bad_below = adaptive.download_bad_below;
+    }
+    if adaptive.upload_warn_below.is_some() {
+        adapted.upload_warn_below = adaptive.upload_warn_below;
+        adapted.upload_bad_below = adaptive.upload_bad_below;
+    }
+    adapted
+}
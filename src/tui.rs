@@ -0,0 +1,270 @@
+use crate::config::Config;
+use crate::{get_buffered_internet_info, get_info, history, netdev, store, Fast};
+use log::error;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::symbols;
+use ratatui::text::Line;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const INPUT_POLL: Duration = Duration::from_millis(200);
+const THROUGHPUT_POINTS: usize = 120;
+const HISTORY_POINTS: usize = 100;
+
+struct App {
+    interface: String,
+    sampler: netdev::Sampler,
+    rx: VecDeque<f64>,
+    tx: VecDeque<f64>,
+    latest: Option<Fast>,
+    latency_history: Vec<(f64, f64)>,
+    test: Option<(JoinHandle<Result<Fast, String>>, Instant)>,
+    status: String,
+}
+
+impl App {
+    fn new(config: &Config, interface: String) -> Self {
+        let mut app = App {
+            sampler: netdev::Sampler::new(&interface),
+            interface,
+            rx: VecDeque::with_capacity(THROUGHPUT_POINTS),
+            tx: VecDeque::with_capacity(THROUGHPUT_POINTS),
+            latest: None,
+            latency_history: Vec::new(),
+            test: None,
+            status: String::new(),
+        };
+        app.load_history(config);
+        app
+    }
+
+    fn load_history(&mut self, config: &Config) {
+        match store::open(config).and_then(|s| s.history(None)) {
+            Ok(entries) => {
+                let skip = entries.len().saturating_sub(HISTORY_POINTS);
+                self.latency_history = entries
+                    .iter()
+                    .skip(skip)
+                    .enumerate()
+                    .map(|(i, e)| (i as f64, e.latency as f64))
+                    .collect();
+                self.latest = entries.into_iter().last();
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    fn sample(&mut self) {
+        match self.sampler.sample() {
+            Ok(Some(r)) => {
+                if self.rx.len() == THROUGHPUT_POINTS {
+                    self.rx.pop_front();
+                    self.tx.pop_front();
+                }
+                self.rx.push_back(r.rx_mbps);
+                self.tx.push_back(r.tx_mbps);
+            }
+            Ok(None) => (),
+            Err(e) => self.status = e,
+        }
+    }
+
+    fn start_test(&mut self, config: &Config) {
+        if self.test.is_some() {
+            return;
+        }
+        let config = config.clone();
+        let handle = tokio::spawn(async move { get_info(&config, true).await });
+        self.test = Some((handle, Instant::now()));
+        self.status = "Testing...".to_string();
+    }
+
+    async fn poll_test(&mut self, config: &Config) {
+        let finished = match &self.test {
+            Some((h, _)) => h.is_finished(),
+            None => false,
+        };
+        if !finished {
+            return;
+        }
+        let (handle, started) = match self.test.take() {
+            Some(t) => t,
+            None => return,
+        };
+        match handle.await {
+            Ok(Ok(_)) => {
+                self.status = format!("Test took {}s", started.elapsed().as_secs());
+                self.load_history(config);
+            }
+            Ok(Err(e)) => self.status = e,
+            Err(e) => self.status = e.to_string(),
+        }
+    }
+}
+
+fn summary(info: Option<&Fast>) -> Vec<Line<'static>> {
+    let info = match info {
+        Some(i) => i,
+        None => return vec![Line::from("No measurement yet, press r")],
+    };
+    let mut lines = vec![Line::from(format!(
+        "Download {} Mbps   Upload {}   Latency {} ms",
+        info.download_speed,
+        info.upload_speed
+            .map(|u| format!("{} Mbps", u))
+            .unwrap_or("-".to_string()),
+        info.latency
+    ))];
+    let mut details = vec![format!(
+        "Taken {}",
+        history::format_timestamp(info.timestamp)
+    )];
+    if let Some(b) = info.backend {
+        details.push(format!("backend {}", b.name()));
+    }
+    if let Some(ip) = &info.user_ip {
+        details.push(format!("ip {}", ip));
+    }
+    lines.push(Line::from(details.join("   ")));
+    lines
+}
+
+fn series(values: &VecDeque<f64>) -> Vec<(f64, f64)> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (i as f64, *v))
+        .collect()
+}
+
+fn max_of(points: &[(f64, f64)]) -> f64 {
+    points.iter().map(|p| p.1).fold(1.0, f64::max) * 1.1
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [top, middle, bottom, footer] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Percentage(50),
+        Constraint::Fill(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(
+        Paragraph::new(summary(app.latest.as_ref())).block(Block::bordered().title("Last test")),
+        top,
+    );
+
+    let rx = series(&app.rx);
+    let tx = series(&app.tx);
+    let top_rate = max_of(&rx).max(max_of(&tx));
+    let throughput = Chart::new(vec![
+        Dataset::default()
+            .name(format!("rx {:.1} Mbps", app.rx.back().unwrap_or(&0.0)))
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Green))
+            .data(&rx),
+        Dataset::default()
+            .name(format!("tx {:.1} Mbps", app.tx.back().unwrap_or(&0.0)))
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&tx),
+    ])
+    .block(Block::bordered().title(format!("{} throughput", app.interface)))
+    .x_axis(Axis::default().bounds([0.0, THROUGHPUT_POINTS as f64]))
+    .y_axis(
+        Axis::default()
+            .bounds([0.0, top_rate])
+            .labels([String::from("0"), format!("{:.0}", top_rate)]),
+    );
+    frame.render_widget(throughput, middle);
+
+    let top_latency = max_of(&app.latency_history);
+    let latency = Chart::new(vec![Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(Color::Yellow))
+        .data(&app.latency_history)])
+    .block(Block::bordered().title("Latency history (ms)"))
+    .x_axis(Axis::default().bounds([0.0, HISTORY_POINTS as f64]))
+    .y_axis(
+        Axis::default()
+            .bounds([0.0, top_latency])
+            .labels([String::from("0"), format!("{:.0}", top_latency)]),
+    );
+    frame.render_widget(latency, bottom);
+
+    let status = match &app.test {
+        Some((_, started)) => format!("Testing... {}s", started.elapsed().as_secs()),
+        None => app.status.clone(),
+    };
+    frame.render_widget(
+        Paragraph::new(format!("r: run test   q: quit   {}", status)),
+        footer,
+    );
+}
+
+async fn run(terminal: &mut DefaultTerminal, config: &Config, mut app: App) -> Result<(), String> {
+    let mut last_sample = Instant::now() - SAMPLE_INTERVAL;
+    loop {
+        if last_sample.elapsed() >= SAMPLE_INTERVAL {
+            app.sample();
+            last_sample = Instant::now();
+        }
+        app.poll_test(config).await;
+        if let Err(e) = terminal.draw(|f| draw(f, &app)) {
+            return Err(format!("Failed to draw: {}", e));
+        }
+        let ready = match event::poll(INPUT_POLL) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(format!("Failed to read input: {}", e));
+            }
+        };
+        if !ready {
+            continue;
+        }
+        if let Ok(Event::Key(key)) = event::read() {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    if let Some((h, _)) = &app.test {
+                        h.abort();
+                    }
+                    return Ok(());
+                }
+                KeyCode::Char('r') => app.start_test(config),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Full screen dashboard with live throughput, the last test and the latency
+/// history.
+pub async fn run_tui(config: &Config, interface: Option<&str>) -> Result<(), String> {
+    let iface = netdev::resolve_interface(interface.or(config.netdev.interface.as_deref()))?;
+    let mut app = App::new(config, iface);
+    if app.latest.is_none() {
+        if let Ok(i) = get_buffered_internet_info(config) {
+            app.latest = Some(i);
+        }
+    }
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, config, app).await;
+    ratatui::restore();
+    if let Err(e) = &result {
+        error!("{}", e);
+    }
+    result
+}
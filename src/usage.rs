@@ -0,0 +1,280 @@
+use crate::config::{UsageConfig, UsageSource};
+use crate::history::get_data_filename;
+use crate::netdev;
+use crate::threshold::Level;
+use chrono::{Datelike, Local};
+use log::{info, warn};
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use tokio::process::Command;
+
+const USAGE_FILE_PATH: &str = "usage.toml";
+const BYTES_PER_GB: f64 = 1_000_000_000.0;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct InterfaceUsage {
+    month: String,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    /// Kernel counters at the previous run, to add up the difference
+    last_rx: u64,
+    last_tx: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct State {
+    /// Month and level of the last cap notification, so each is sent once
+    alerted_month: Option<String>,
+    alerted_level: Option<Level>,
+    interfaces: BTreeMap<String, InterfaceUsage>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Both directions today, only known to vnstat
+    pub today_bytes: Option<u64>,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.rx_bytes + self.tx_bytes
+    }
+
+    /// Share of the monthly cap used so far.
+    pub fn percent(&self, config: &UsageConfig) -> Option<u32> {
+        let cap = config.cap_gb.filter(|c| *c > 0.0)?;
+        Some((self.total() as f64 / BYTES_PER_GB / cap * 100.0).round() as u32)
+    }
+
+    pub fn level(&self, config: &UsageConfig) -> Option<Level> {
+        let pct = self.percent(config)?;
+        Some(if pct >= 100 {
+            Level::Bad
+        } else if pct >= config.warn_percent {
+            Level::Warn
+        } else {
+            Level::Good
+        })
+    }
+}
+
+/// Formats a byte count with a decimal unit, e.g. `850M` or `12.3G`.
+pub fn format_bytes(bytes: u64) -> String {
+    let gb = bytes as f64 / BYTES_PER_GB;
+    if gb >= 1.0 {
+        format!("{:.1}G", gb)
+    } else {
+        format!("{:.0}M", gb * 1000.0)
+    }
+}
+
+fn load_state(path: &str) -> State {
+    match fs::read_to_string(path) {
+        Ok(c) => match toml::from_str(&c) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Failed to parse '{}', starting over: {}", path, e);
+                State::default()
+            }
+        },
+        Err(_) => State::default(),
+    }
+}
+
+fn save_state(path: &str, state: &State) -> Result<(), String> {
+    let toml = match toml::to_string(state) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(format!("Failed to convert to TOML: {}", e));
+        }
+    };
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+    }
+    match fs::write(path, toml) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+/// Adds the traffic since the previous run. Counters smaller than last
+/// time mean a reboot, in which case all of them are new traffic.
+fn accumulate(entry: &mut InterfaceUsage, month: &str, counters: netdev::Counters) {
+    if entry.month != month {
+        *entry = InterfaceUsage {
+            month: month.to_string(),
+            last_rx: counters.rx_bytes,
+            last_tx: counters.tx_bytes,
+            ..Default::default()
+        };
+        return;
+    }
+    let delta = |now: u64, last: u64| if now >= last { now - last } else { now };
+    entry.rx_bytes += delta(counters.rx_bytes, entry.last_rx);
+    entry.tx_bytes += delta(counters.tx_bytes, entry.last_tx);
+    entry.last_rx = counters.rx_bytes;
+    entry.last_tx = counters.tx_bytes;
+}
+
+/// Shows the notification off the async threads, it's a D-Bus round-trip.
+async fn notify_cap(config: &UsageConfig, usage: &Usage, level: Level) -> Result<(), String> {
+    let cap = config.cap_gb.unwrap_or_default();
+    let summary = if level == Level::Bad {
+        "Monthly data cap reached"
+    } else {
+        "Approaching monthly data cap"
+    };
+    let body = format!("{} of {} GB used", format_bytes(usage.total()), cap);
+    let mut notification = Notification::new();
+    notification
+        .summary(summary)
+        .body(&body)
+        .icon("network-transmit-receive");
+    match tokio::task::spawn_blocking(move || notification.show().map(|_| ())).await {
+        Ok(Ok(_)) => {
+            info!("Sent data cap notification: {}", body);
+            Ok(())
+        }
+        Ok(Err(e)) => Err(format!("Failed to send notification: {}", e)),
+        Err(e) => Err(format!("Failed to send notification: {}", e)),
+    }
+}
+
+fn counted_usage(interfaces: &[String], state: &mut State, month: &str) -> Usage {
+    let mut usage = Usage::default();
+    for iface in interfaces {
+        let entry = state.interfaces.entry(iface.clone()).or_default();
+        match netdev::read_counters(iface) {
+            Ok(c) => accumulate(entry, month, c),
+            Err(e) => warn!("{}", e),
+        }
+        if entry.month == month {
+            usage.rx_bytes += entry.rx_bytes;
+            usage.tx_bytes += entry.tx_bytes;
+        }
+    }
+    usage
+}
+
+#[derive(Debug, Deserialize)]
+struct VnstatDate {
+    year: i32,
+    month: u32,
+    day: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VnstatEntry {
+    date: VnstatDate,
+    rx: u64,
+    tx: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct VnstatTraffic {
+    #[serde(default)]
+    day: Vec<VnstatEntry>,
+    #[serde(default)]
+    month: Vec<VnstatEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VnstatInterface {
+    name: String,
+    traffic: VnstatTraffic,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vnstat {
+    interfaces: Vec<VnstatInterface>,
+}
+
+/// Reads this month's and today's traffic from `vnstat --json`, which needs
+/// vnstat 2 (byte counts, `day`/`month` keys).
+async fn vnstat_usage(interfaces: &[String]) -> Result<Usage, String> {
+    let output = match Command::new("vnstat").arg("--json").output().await {
+        Ok(o) => o,
+        Err(e) => {
+            return Err(format!("Failed to execute vnstat: {}", e));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("vnstat failed: {}", stderr.trim()));
+    }
+    let vnstat: Vnstat = match serde_json::from_slice(&output.stdout) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(format!("Failed to parse vnstat JSON: {}", e));
+        }
+    };
+
+    let now = Local::now();
+    let this_month = |d: &VnstatDate| d.year == now.year() && d.month == now.month();
+    let mut usage = Usage {
+        today_bytes: Some(0),
+        ..Default::default()
+    };
+    for iface in vnstat.interfaces {
+        if !interfaces.contains(&iface.name) {
+            continue;
+        }
+        for m in iface.traffic.month.iter().filter(|m| this_month(&m.date)) {
+            usage.rx_bytes += m.rx;
+            usage.tx_bytes += m.tx;
+        }
+        for d in iface.traffic.day.iter() {
+            if this_month(&d.date) && d.date.day == Some(now.day()) {
+                usage.today_bytes = usage.today_bytes.map(|t| t + d.rx + d.tx);
+            }
+        }
+    }
+    Ok(usage)
+}
+
+/// Updates and returns this month's usage over the configured interfaces,
+/// notifying once per month as the cap gets close and once it's reached.
+pub async fn update(config: &UsageConfig) -> Result<Usage, String> {
+    let interfaces = if config.interfaces.is_empty() {
+        vec![netdev::default_interface()?]
+    } else {
+        config.interfaces.clone()
+    };
+    let path = get_data_filename(USAGE_FILE_PATH)?;
+    let path = match path.to_str() {
+        Some(p) => p.to_string(),
+        None => {
+            return Err("Failed to convert path to string".to_string());
+        }
+    };
+    let month = Local::now().format("%Y-%m").to_string();
+    let mut state = load_state(&path);
+
+    let usage = match config.source {
+        UsageSource::Native => counted_usage(&interfaces, &mut state, &month),
+        UsageSource::Vnstat => vnstat_usage(&interfaces).await?,
+    };
+
+    if let Some(level) = usage.level(config).filter(|l| *l != Level::Good) {
+        let already = state.alerted_month.as_deref() == Some(month.as_str())
+            && state.alerted_level.is_some_and(|l| l >= level);
+        if config.notify && !already {
+            match notify_cap(config, &usage, level).await {
+                Ok(_) => {
+                    state.alerted_month = Some(month.clone());
+                    state.alerted_level = Some(level);
+                }
+                Err(e) => warn!("{}", e),
+            }
+        }
+    }
+    save_state(&path, &state)?;
+    Ok(usage)
+}
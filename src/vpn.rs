@@ -0,0 +1,76 @@
+use crate::config::VpnConfig;
+use crate::netdev;
+use log::info;
+use std::fs;
+use std::path::Path;
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+// ARPHRD_NONE, used by tun and wireguard devices
+const ARPHRD_NONE: &str = "65534";
+
+fn is_up(iface: &Path) -> bool {
+    match fs::read_to_string(iface.join("operstate")) {
+        // tun devices commonly report "unknown" while passing traffic
+        Ok(s) => s.trim() != "down",
+        Err(_) => false,
+    }
+}
+
+fn looks_like_vpn(config: &VpnConfig, name: &str, iface: &Path) -> bool {
+    if config
+        .interfaces
+        .iter()
+        .any(|p| name.starts_with(p.as_str()))
+    {
+        return true;
+    }
+    if iface.join("tun_flags").exists() {
+        return true;
+    }
+    match fs::read_to_string(iface.join("type")) {
+        Ok(t) => t.trim() == ARPHRD_NONE,
+        Err(_) => false,
+    }
+}
+
+/// Returns the name of the first active VPN interface, if any.
+fn find_vpn_interface(config: &VpnConfig) -> Option<String> {
+    // Prefer the interface carrying the default route
+    if let Ok(default) = netdev::default_interface() {
+        let path = Path::new(SYS_CLASS_NET).join(&default);
+        if looks_like_vpn(config, &default, &path) {
+            return Some(default);
+        }
+    }
+    let entries = match fs::read_dir(SYS_CLASS_NET) {
+        Ok(e) => e,
+        Err(_) => return None,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        if looks_like_vpn(config, &name, &path) && is_up(&path) {
+            return Some(name);
+        }
+    }
+    None
+}
+
+/// Checks for VPN interfaces and, when `expected_isp` is configured, whether
+/// the public ip belongs to someone else.
+pub fn is_vpn_active(config: &VpnConfig, isp: Option<&str>) -> bool {
+    if let Some(name) = find_vpn_interface(config) {
+        info!("VPN interface found: '{}'", name);
+        return true;
+    }
+    match (&config.expected_isp, isp) {
+        (Some(expected), Some(actual)) => {
+            let other = !actual.to_lowercase().contains(&expected.to_lowercase());
+            if other {
+                info!("Public ip belongs to '{}', not '{}'", actual, expected);
+            }
+            other
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,128 @@
+use crate::config::{WebhookConfig, WebhookPreset};
+use crate::notify::degraded_reasons;
+use crate::system::SystemClock;
+use crate::{format, get_cache_filename, get_seconds_since_file_modified, http_client, Fast};
+use log::info;
+use std::collections::HashMap;
+use tokio::fs;
+
+const COOLDOWN_FILE_PATH: &str = ".polybar-internet-speed-webhook";
+const FAILURES_FILE_PATH: &str = ".polybar-internet-speed-failures";
+
+fn preset_body(preset: WebhookPreset) -> &'static str {
+    match preset {
+        WebhookPreset::Generic => {
+            r#"{"event": "{event}", "message": "{message}", "download": "{download}", "upload": "{upload}", "latency": "{latency}"}"#
+        }
+        WebhookPreset::Slack => r#"{"text": "{message}"}"#,
+        WebhookPreset::Discord => r#"{"content": "{message}"}"#,
+        WebhookPreset::Telegram => r#"{"chat_id": "{chat_id}", "text": "{message}"}"#,
+    }
+}
+
+/// Escapes `s` so it can be dropped between quotes in a JSON template.
+fn json_escape(s: &str) -> String {
+    let quoted = serde_json::Value::String(s.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+async fn send(
+    config: &WebhookConfig,
+    event: &str,
+    message: &str,
+    info: Option<&Fast>,
+) -> Result<(), String> {
+    let mut values = HashMap::new();
+    values.insert("event".to_string(), json_escape(event));
+    values.insert("message".to_string(), json_escape(message));
+    values.insert(
+        "chat_id".to_string(),
+        json_escape(config.chat_id.as_deref().unwrap_or("")),
+    );
+    if let Some(i) = info {
+        values.insert("download".to_string(), i.download_speed.to_string());
+        values.insert("latency".to_string(), i.latency.to_string());
+        if let Some(up) = i.upload_speed {
+            values.insert("upload".to_string(), up.to_string());
+        }
+    }
+    let template = match &config.body {
+        Some(b) => b.as_str(),
+        None => preset_body(config.preset),
+    };
+    let body = format::render(template, &values);
+
+    let client = http_client(config.timeout_secs)?;
+    match client
+        .post(&config.url)
+        .header("Content-Type", "application/json")
+        .body(body.clone())
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+    {
+        Ok(_) => {
+            info!("Sent webhook: {}", body);
+            Ok(())
+        }
+        Err(e) => Err(format!("Failed to send webhook to '{}': {}", config.url, e)),
+    }
+}
+
+/// POSTs an alert when the measurement crosses the thresholds, at most once
+/// per cooldown period.
+pub async fn alert_if_degraded(config: &WebhookConfig, info: &Fast) -> Result<(), String> {
+    let reasons = degraded_reasons(&config.thresholds, info);
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    let path = get_cache_filename(COOLDOWN_FILE_PATH)?;
+    if let Ok(elapsed) = get_seconds_since_file_modified(&path, &SystemClock) {
+        if elapsed < config.cooldown_secs {
+            info!("Connection degraded but webhook sent {}s ago", elapsed);
+            return Ok(());
+        }
+    }
+    let message = format!("Internet connection degraded: {}", reasons.join(", "));
+    send(config, "degraded", &message, Some(info)).await?;
+    match fs::write(&path, "").await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write '{}': {}", path, e)),
+    }
+}
+
+async fn read_failures(path: &str) -> u32 {
+    match fs::read_to_string(path).await {
+        Ok(c) => c.trim().parse().unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+
+/// Counts consecutive failed tests, alerting once the count reaches
+/// `max_failures`.
+pub async fn record_failure(config: &WebhookConfig, error: &str) -> Result<(), String> {
+    let path = get_cache_filename(FAILURES_FILE_PATH)?;
+    let failures = read_failures(&path).await + 1;
+    if let Err(e) = fs::write(&path, failures.to_string()).await {
+        return Err(format!("Failed to write '{}': {}", path, e));
+    }
+    if failures != config.max_failures {
+        return Ok(());
+    }
+    let message = format!(
+        "Internet speed test failed {} times in a row: {}",
+        failures, error
+    );
+    send(config, "failing", &message, None).await
+}
+
+pub async fn record_success() -> Result<(), String> {
+    let path = get_cache_filename(FAILURES_FILE_PATH)?;
+    if read_failures(&path).await == 0 {
+        return Ok(());
+    }
+    match fs::remove_file(&path).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to remove '{}': {}", path, e)),
+    }
+}
@@ -0,0 +1,87 @@
+use log::info;
+use std::fs;
+use std::process::Command;
+
+const PROC_WIRELESS: &str = "/proc/net/wireless";
+// Link quality reported by most drivers is out of 70
+const MAX_LINK_QUALITY: f32 = 70.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct WifiInfo {
+    pub interface: String,
+    pub ssid: Option<String>,
+    /// Signal quality in percent
+    pub signal: u32,
+    /// Signal level in dBm
+    pub level: i32,
+}
+
+/// Parses `/proc/net/wireless`, returning the requested interface or the
+/// first one listed.
+fn parse_proc_wireless(contents: &str, interface: Option<&str>) -> Option<WifiInfo> {
+    // First two lines are headers
+    for line in contents.lines().skip(2) {
+        let (name, stats) = match line.split_once(':') {
+            Some(s) => s,
+            None => continue,
+        };
+        let name = name.trim();
+        if let Some(i) = interface {
+            if i != name {
+                continue;
+            }
+        }
+        let fields: Vec<&str> = stats.split_whitespace().collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        let quality: f32 = fields[1].trim_end_matches('.').parse().unwrap_or(0.0);
+        let level: f32 = fields[2].trim_end_matches('.').parse().unwrap_or(0.0);
+        let signal = (quality / MAX_LINK_QUALITY * 100.0).clamp(0.0, 100.0);
+        return Some(WifiInfo {
+            interface: name.to_string(),
+            ssid: None,
+            signal: signal.round() as u32,
+            level: level as i32,
+        });
+    }
+    None
+}
+
+fn get_ssid(interface: &str) -> Option<String> {
+    match Command::new("iwgetid").arg(interface).arg("--raw").output() {
+        Ok(o) if o.status.success() => {
+            let ssid = String::from_utf8_lossy(&o.stdout).trim().to_string();
+            if !ssid.is_empty() {
+                return Some(ssid);
+            }
+        }
+        _ => (),
+    }
+    // Fallback for systems without wireless-tools
+    let output = match Command::new("iw").args(["dev", interface, "link"]).output() {
+        Ok(o) if o.status.success() => o,
+        _ => return None,
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("SSID: ").map(|s| s.to_string()))
+}
+
+pub fn get_wifi_info(interface: Option<&str>) -> Result<WifiInfo, String> {
+    let contents = match fs::read_to_string(PROC_WIRELESS) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(format!("Failed to read '{}': {}", PROC_WIRELESS, e));
+        }
+    };
+    let mut wifi = match parse_proc_wireless(&contents, interface) {
+        Some(w) => w,
+        None => {
+            return Err("No wireless interface found".to_string());
+        }
+    };
+    wifi.ssid = get_ssid(&wifi.interface);
+    info!("Wifi info: {:?}", wifi);
+    Ok(wifi)
+}